@@ -0,0 +1,82 @@
+use crate::interpreter::{Relation, SaveMode};
+use crate::lexer::{Keyword, SnaFamily, Span, Special};
+
+/// Operand of block
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operand {
+    /// Omitted operand, like first one in `TRANSFER ,NEXT`. Block gives it default value.
+    Empty,
+    Number(f64),
+    /// Name of entity or label
+    Name(String),
+    /// Standard numeric attribute, like `X$FLAG` or `P1`
+    Sna(SnaFamily, String),
+    /// Relational operator of TEST
+    Relation(Relation),
+    /// String literal, like `"Client"`
+    Text(String),
+    /// Name with sign of accumulation, like `FLAG+` of SAVEVALUE
+    Accumulate(String, SaveMode),
+    /// Cell of MATRIX: name, row and column, like `MX$COST(1,P2)`
+    MatrixCell(String, Box<Operand>, Box<Operand>),
+}
+
+impl Operand {
+    /// Text of operand for error messages
+    pub(crate) fn describe(&self) -> String {
+        match self {
+            Operand::Empty => "omitted operand".into(),
+            Operand::Number(value) => value.to_string(),
+            Operand::Name(name) => name.clone(),
+            Operand::Sna(family, name) => format!("{}${}", family.prefix(), name),
+            Operand::Relation(relation) => relation.mnemonic().to_string(),
+            Operand::Text(text) => format!("{:?}", text),
+            Operand::Accumulate(name, SaveMode::Dec) => format!("{}-", name),
+            Operand::Accumulate(name, _) => format!("{}+", name),
+            Operand::MatrixCell(name, row, column) => {
+                format!("MX${}({},{})", name, row.describe(), column.describe())
+            }
+        }
+    }
+}
+
+/// Statement of source: `[label] KEYWORD [X] [A,B,...]`.
+/// Blocks, definitions of entities like STORAGE and control statements like START have the same form.
+#[derive(Clone, Debug)]
+pub struct Statement {
+    /// Name and position of label definition
+    pub label: Option<(String, Span)>,
+    pub block: Keyword,
+    pub span: Span,
+    /// Auxiliary operand between keyword and operands, like `NU` in `GATE NU BAR`
+    pub auxiliary: Option<(Operand, Span)>,
+    pub operands: Vec<(Operand, Span)>,
+    /// Arithmetic expression of VARIABLE
+    pub expression: Option<Expr>,
+    /// Points of FUNCTION from follower lines
+    pub points: Vec<(f32, f32)>,
+}
+
+impl Statement {
+    /// Statement without label, auxiliary operand, expression and points
+    pub fn new(block: Keyword, span: Span, operands: Vec<(Operand, Span)>) -> Statement {
+        Statement {
+            label: None,
+            block,
+            span,
+            auxiliary: None,
+            operands,
+            expression: None,
+            points: Vec::new(),
+        }
+    }
+}
+
+/// Arithmetic expression of VARIABLE, like `X$A+P1#3`
+#[derive(Clone, Debug)]
+pub enum Expr {
+    /// Number or standard numeric attribute
+    Value(Operand, Span),
+    /// Operator is one of `+ - # / @`
+    Binary(Box<Expr>, Special, Box<Expr>),
+}
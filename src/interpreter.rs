@@ -1,44 +1,543 @@
+use rand::distributions::{Distribution, Normal};
+use rand::rngs::StdRng;
+use rand::{FromEntropy, Rng, SeedableRng};
 use std::cmp::Ordering;
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::convert::TryFrom;
 use std::fmt;
+use std::mem;
+use std::ops::RangeInclusive;
+
+use serde::{Serialize, Serializer};
+
+use crate::parser::{Program, Symbols};
 
 /// Instructions, marked with (*) contain pointer(usize) to instruction
 /// from what it will be executed, to have proper arguments in stack
-
-enum Instructions {
+pub(crate) enum Instructions {
     /// (*) pops time interval to generate from stack
     Generate(usize),
+    /// (*) pops mean time interval from stack, generates with exponentially distributed intervals
+    GenerateExp(usize),
+    /// (*) pops spread B, then mean A from stack, generates with intervals uniformly distributed in [A-B, A+B]
+    GenerateUniform(usize),
+    /// Operands are memory address of time of the first arrival and limit of count of transacts
+    /// for the next GENERATE. Read at load time, does nothing at runtime.
+    GenerateLimits(Option<usize>, Option<u32>),
     /// (*) pops time interval to wait from stack
     Advance(usize),
+    /// (*) pops spread B, then mean A from stack, waits for time uniformly distributed in [A-B, A+B]
+    AdvanceUniform(usize),
+    /// Operands are memory addresses of mean time and standard deviation.
+    /// Waits for normally distributed time, negative samples are clamped to zero.
+    AdvanceNormal(usize, usize),
     /// (*) pops terminate count from stack
     Terminate(usize),
     /// Prints object by its address
     Print(usize),
+    /// Prints parameter of current transact by its index
+    PrintParam(usize),
     /// Prints clock
     PrintClock,
     /// Operand is pointer to instruction
     Transfer(usize),
-    /// Operand is pointer to instruction for false branch. Pops condition(GppsType::Boolean) from stack.
-    TestVar(usize),
+    /// Operands are probability and pointers to instructions:
+    /// transact goes to the first one with the probability, otherwise to the second one
+    TransferFraction(f64, usize, usize),
+    /// Operands are pointers to instructions: transact goes to the first one,
+    /// if its block accepts transact now, otherwise to the second one
+    TransferBoth(usize, usize),
+    /// Operands are relation and pointer to instruction for false branch.
+    /// Pops B, then A from stack and checks `A relation B`. Numbers of different types are promoted.
+    Test(Relation, usize),
     /// Operand is a pointer to memory. Takes object from stack and writes it to memory.
     SaveValue(usize),
+    /// Operands are pointer to memory and mode. Takes object from stack and writes it to memory,
+    /// adds it to memory or subtracts it from memory.
+    SaveValueOp(usize, SaveMode),
     /// Operand is a pointer to memory. Pushes object from memory to stack.
     Push(usize),
+    /// Operand is matrix id. Pops value, then column, then row and writes value to the cell.
+    SaveMatrix(usize),
+    /// Operand is matrix id. Pops column, then row and pushes value of the cell to stack.
+    PushMatrix(usize),
+    /// Operand is a pointer to memory with GpssType::Facility. Blocks transact while facility is busy.
+    Seize(usize),
+    /// Operand is a pointer to memory with GpssType::Facility. Frees facility and wakes blocked transact.
+    Release(usize),
+    /// Operand is a pointer to memory with GpssType::Facility. Seizes facility even if it is busy:
+    /// the owner is displaced and its ADVANCE is suspended until RETURN.
+    Preempt(usize),
+    /// Operand is a pointer to memory with GpssType::Facility. Frees facility after PREEMPT
+    /// and resumes the displaced transact, like RELEASE does after SEIZE.
+    Return(usize),
+    /// Operands are queue id and count of units. Transact enters the queue.
+    Queue(usize, u32),
+    /// Operands are queue id and count of units. Transact leaves the queue.
+    Depart(usize, u32),
+    /// Operand is index of transact parameter. Pops object from stack and writes it to parameter.
+    Assign(usize),
+    /// Operands are index of transact parameter and mode. Pops object from stack and adds it to
+    /// parameter or subtracts it from parameter, like `ASSIGN 5+,10`.
+    AssignOp(usize, SaveMode),
+    /// Operand is index of transact parameter. Pushes parameter to stack.
+    LoadParam(usize),
+    /// Pushes random number uniformly distributed in [0,1)
+    Random,
+    /// Operands are storage id and count of units. Blocks transact until units are available.
+    Enter(usize, u32),
+    /// Operands are storage id and count of units. Frees units and wakes blocked transacts.
+    Leave(usize, u32),
+    /// Pops two objects from stack, pushes their sum
+    Add,
+    /// Pops two objects from stack, pushes difference of the deeper and the top one
+    Sub,
+    /// Pops two objects from stack, pushes their product
+    Mul,
+    /// Pops two objects from stack, pushes quotient of the deeper and the top one
+    Div,
+    /// Pops two objects from stack, pushes remainder of division of the deeper by the top one
+    Mod,
+    // Логические операции для BVARIABLE, трансляции которых пока нет
+    /// Pops two booleans from stack, pushes true if both are true. Both operands are always computed.
+    #[allow(dead_code)]
+    And,
+    /// Pops two booleans from stack, pushes true if any of them is true. Both operands are always computed.
+    #[allow(dead_code)]
+    Or,
+    /// Pops boolean from stack, pushes its negation
+    #[allow(dead_code)]
+    Not,
+    /// Pops number from stack, pushes it as Float. Float is left as is.
+    ToFloat,
+    /// Pops number from stack, pushes it as Integer, Float is truncated toward zero.
+    /// Integer and UnsignedInteger are left as is.
+    ToInt,
+    /// Operand is function id. Pops argument from stack, pushes value of function.
+    CallFunction(usize),
+    /// Operand is new priority of current transact
+    Priority(usize),
+    /// Operands are index of transact parameter and pointer to instruction.
+    /// Decrements parameter and jumps to instruction, while it is not zero.
+    Loop(usize, usize),
+    /// Operand is index of transact parameter, where current time is stored in units of model time.
+    /// Without parameter mark time of transact is set to current time, so M1 counts from MARK.
+    Mark(Option<usize>),
+    /// Pushes transit time of current transact in units of model time: M1 from its mark time
+    /// or MPj from time in parameter j. Whole times are Integer.
+    PushTransit(Option<usize>),
+    /// Operands are table id and weight. Records transit time of current transact to table
+    /// as `weight` entries.
+    Tabulate(usize, u32),
+    /// Operands are predicate, inclusive range of entity ids and index of transact parameter,
+    /// which receives count of entities satisfying predicate as Integer
+    Count(CountMode, RangeInclusive<usize>, usize),
+    /// Operands are condition and pointer to instruction, where transact goes, when condition is false
+    Gate(GateCondition, usize),
+    /// Operand is condition. Transact waits, until condition becomes true.
+    GateWait(GateCondition),
+    /// Operand is count of family members. The first member waits for the others,
+    /// which are destroyed, then continues.
+    Assemble(usize),
+    /// Operand is count of family members. Members wait, until all of them come, then all continue.
+    Gather(usize),
+    /// Operand is pointer to conjugate MATCH instruction. Transact waits, until member of its family
+    /// comes to the conjugate MATCH, then both continue.
+    Match(usize),
+    /// Operand is pointer to instruction, where copies start. Pops count of copies from stack.
+    /// Copies are scheduled on current clock, original transact continues with the next instruction.
+    Split(usize),
+}
+
+/// Condition of GATE block on state of facility or storage
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum GateCondition {
+    /// U: facility is busy. Operand is pointer to memory with GpssType::Facility.
+    FacilityUsed(usize),
+    /// NU: facility is free
+    FacilityUnused(usize),
+    /// SF: all units of storage are used. Operand is storage id.
+    StorageFull(usize),
+    /// SNF: storage has free units
+    StorageNotFull(usize),
+    /// SE: no units of storage are used
+    StorageEmpty(usize),
+    /// SNE: some units of storage are used
+    StorageNotEmpty(usize),
+}
+
+impl GateCondition {
+    /// Condition by its GPSS mnemonic for facility or storage `entity`
+    pub(crate) fn from_mnemonic(mnemonic: &str, entity: usize) -> Option<GateCondition> {
+        match mnemonic.to_uppercase().as_str() {
+            "U" => Some(GateCondition::FacilityUsed(entity)),
+            "NU" => Some(GateCondition::FacilityUnused(entity)),
+            "SF" => Some(GateCondition::StorageFull(entity)),
+            "SNF" => Some(GateCondition::StorageNotFull(entity)),
+            "SE" => Some(GateCondition::StorageEmpty(entity)),
+            "SNE" => Some(GateCondition::StorageNotEmpty(entity)),
+            _ => None,
+        }
+    }
+}
+
+/// Predicate of COUNT block
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum CountMode {
+    /// U: busy facilities. Ids are memory addresses, cells of other types are skipped.
+    FacilityUsed,
+    /// SNE: storages with used units. Ids are storage ids.
+    StorageNotEmpty,
+}
+
+/// How SAVEVALUE combines new value with the saved one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SaveMode {
+    /// `SAVEVALUE A,B`: value is replaced
+    Set,
+    /// `SAVEVALUE A+,B`: value is increased
+    Inc,
+    /// `SAVEVALUE A-,B`: value is decreased
+    Dec,
+}
+
+/// Relational operator of TEST block
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Relation {
+    L,
+    LE,
+    E,
+    NE,
+    G,
+    GE,
+}
+
+impl Relation {
+    /// Relation by its GPSS mnemonic: `L`, `LE`, `E`, `NE`, `G`, `GE`
+    pub(crate) fn from_mnemonic(mnemonic: &str) -> Option<Relation> {
+        match mnemonic.to_uppercase().as_str() {
+            "L" => Some(Relation::L),
+            "LE" => Some(Relation::LE),
+            "E" => Some(Relation::E),
+            "NE" => Some(Relation::NE),
+            "G" => Some(Relation::G),
+            "GE" => Some(Relation::GE),
+            _ => None,
+        }
+    }
+
+    /// GPSS mnemonic of relation
+    pub(crate) fn mnemonic(self) -> &'static str {
+        match self {
+            Relation::L => "L",
+            Relation::LE => "LE",
+            Relation::E => "E",
+            Relation::NE => "NE",
+            Relation::G => "G",
+            Relation::GE => "GE",
+        }
+    }
+
+    /// Checks relation for result of comparison of A and B
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Relation::L => ordering == Ordering::Less,
+            Relation::LE => ordering != Ordering::Greater,
+            Relation::E => ordering == Ordering::Equal,
+            Relation::NE => ordering != Ordering::Equal,
+            Relation::G => ordering == Ordering::Greater,
+            Relation::GE => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// Operation of arithmetic instructions
+#[derive(Clone, Copy, Debug)]
+enum Arithmetic {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+/// Integer division truncates toward zero, division by zero is an error
+impl Arithmetic {
+    fn integer(self, a: i64, b: i64) -> Result<i64, RuntimeError> {
+        match self {
+            Arithmetic::Add => a.checked_add(b),
+            Arithmetic::Sub => a.checked_sub(b),
+            Arithmetic::Mul => a.checked_mul(b),
+            Arithmetic::Div if b == 0 => return Err(RuntimeError::DivisionByZero),
+            Arithmetic::Div => a.checked_div(b),
+            Arithmetic::Mod if b == 0 => return Err(RuntimeError::DivisionByZero),
+            Arithmetic::Mod => a.checked_rem(b),
+        }
+        .ok_or(RuntimeError::Overflow)
+    }
+
+    fn float(self, a: f32, b: f32) -> Result<f32, RuntimeError> {
+        match self {
+            Arithmetic::Add => Ok(a + b),
+            Arithmetic::Sub => Ok(a - b),
+            Arithmetic::Mul => Ok(a * b),
+            Arithmetic::Div if b == 0.0 => Err(RuntimeError::DivisionByZero),
+            Arithmetic::Div => Ok(a / b),
+            Arithmetic::Mod if b == 0.0 => Err(RuntimeError::DivisionByZero),
+            Arithmetic::Mod => Ok(a % b),
+        }
+    }
+}
+
+/// State of interpreter after one step
+#[derive(Debug)]
+pub enum StepResult {
+    /// Program can execute next instruction
+    Running,
+    /// START count reached zero or there is nothing to execute
+    Halted,
+    /// Events ran out before START count reached zero: all transacts are terminated or blocked.
+    /// Operand is the rest of START count.
+    Exhausted(u32),
+    /// Executed instruction failed
+    Error(RuntimeError),
+}
+
+/// Error of program execution
+#[derive(Debug)]
+pub enum RuntimeError {
+    /// Instruction pops from empty stack
+    StackUnderflow,
+    /// Object has type, which instruction can not work with
+    TypeMismatch {
+        expected: &'static str,
+        got: &'static str,
+    },
+    /// Address is out of memory
+    BadMemoryAccess(usize),
+    /// Division by zero in DIV instruction
+    DivisionByZero,
+    /// Result of arithmetic is out of range of its type
+    Overflow,
+    /// Instruction works with transact, but there is no current transact
+    NoTransact,
+    /// RELEASE of facility, which is not seized
+    FacilityNotSeized(usize),
+    /// RELEASE or RETURN of facility by transact, which is displaced from it by PREEMPT
+    FacilityPreempted(usize),
+    /// RELEASE or RETURN of facility by transact, which does not own it
+    FacilityNotOwned(usize),
+    /// DEPART from queue, which has less transacts than departing units. Operand is name of queue.
+    EmptyQueue(String),
+    /// Transact has no parameter with such index. Transact is given by its number.
+    BadParameter { param_id: usize, transact: u32 },
+    /// Parameter is read before it is assigned. Transact is given by its number.
+    UnassignedParameter { param_id: usize, transact: u32 },
+    /// Storage id is not defined
+    BadStorage(usize),
+    /// ENTER asks more units than storage capacity, so transact would wait forever
+    StorageTooSmall(usize),
+    /// LEAVE frees more units than are used
+    StorageNotEntered(usize),
+    /// Function id is not defined or function has no points
+    BadFunction(usize),
+    /// Table id is not defined
+    BadTable(usize),
+    /// Program executed more instructions than allowed, probably it loops without ADVANCE
+    StepLimitExceeded,
+    /// Model ran out of events before START count reached zero. Operand is the rest of START count.
+    ModelExhausted(u32),
+    /// Model ran out of events, while transacts wait for family members. Operand is names of blocks,
+    /// where they wait.
+    Deadlock(Vec<String>),
+    /// Time scale is changed after simulation started, when times are already counted in ticks
+    TimeScaleAfterStart,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeError::StackUnderflow => write!(f, "stack is empty, but trying to pop"),
+            RuntimeError::TypeMismatch { expected, got } => {
+                write!(f, "expected {}, got {}", expected, got)
+            }
+            RuntimeError::BadMemoryAccess(var_id) => write!(f, "cannot access variable {}", var_id),
+            RuntimeError::DivisionByZero => write!(f, "division by zero"),
+            RuntimeError::Overflow => write!(f, "arithmetic overflow"),
+            RuntimeError::NoTransact => write!(f, "there is no current transact"),
+            RuntimeError::FacilityNotSeized(fac_id) => {
+                write!(f, "releasing facility {} which is not seized", fac_id)
+            }
+            RuntimeError::FacilityPreempted(fac_id) => {
+                write!(
+                    f,
+                    "facility {} is preempted from transact, which frees it",
+                    fac_id
+                )
+            }
+            RuntimeError::FacilityNotOwned(fac_id) => {
+                write!(
+                    f,
+                    "facility {} is freed by transact, which does not own it",
+                    fac_id
+                )
+            }
+            RuntimeError::EmptyQueue(name) => write!(f, "departing from empty queue {}", name),
+            RuntimeError::BadParameter { param_id, transact } => {
+                write!(f, "transact {} has no parameter {}", transact, param_id)
+            }
+            RuntimeError::UnassignedParameter { param_id, transact } => {
+                write!(
+                    f,
+                    "parameter {} of transact {} is read before ASSIGN",
+                    param_id, transact
+                )
+            }
+            RuntimeError::BadStorage(storage_id) => {
+                write!(f, "storage {} is not defined", storage_id)
+            }
+            RuntimeError::StorageTooSmall(storage_id) => {
+                write!(
+                    f,
+                    "entering more units than capacity of storage {}",
+                    storage_id
+                )
+            }
+            RuntimeError::StorageNotEntered(storage_id) => {
+                write!(f, "leaving more units than used in storage {}", storage_id)
+            }
+            RuntimeError::BadFunction(function_id) => {
+                write!(f, "function {} is not defined", function_id)
+            }
+            RuntimeError::BadTable(table_id) => write!(f, "table {} is not defined", table_id),
+            RuntimeError::StepLimitExceeded => {
+                write!(f, "limit of executed instructions is exceeded")
+            }
+            RuntimeError::ModelExhausted(remaining) => write!(
+                f,
+                "model exhausted: no events left, while termination count is {}",
+                remaining
+            ),
+            RuntimeError::Deadlock(blocks) => write!(
+                f,
+                "deadlock: no events left, while transacts wait for family members at {}",
+                blocks.join(", ")
+            ),
+            RuntimeError::TimeScaleAfterStart => {
+                write!(f, "time scale can not be changed after simulation started")
+            }
+        }
+    }
+}
+
+/// Operand of instruction, which points out of program or its entities.
+/// Found before simulation by `Interpreter::validate`.
+#[derive(Debug, PartialEq)]
+pub enum ValidationError {
+    /// Jump out of program, start of operands after the block or MATCH with other block.
+    /// Operands are instruction and its pointer.
+    BadPointer { instruction: usize, pointer: usize },
+    /// Operands are instruction and address out of memory
+    BadMemory { instruction: usize, address: usize },
+    /// Storage, table, function or matrix is not defined. Operands are instruction, kind of entity and its id.
+    BadEntity {
+        instruction: usize,
+        entity: &'static str,
+        id: usize,
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::BadPointer {
+                instruction,
+                pointer,
+            } => {
+                write!(
+                    f,
+                    "instruction {} points to bad instruction {}",
+                    instruction, pointer
+                )
+            }
+            ValidationError::BadMemory {
+                instruction,
+                address,
+            } => {
+                write!(
+                    f,
+                    "instruction {} uses address {} out of memory",
+                    instruction, address
+                )
+            }
+            ValidationError::BadEntity {
+                instruction,
+                entity,
+                id,
+            } => write!(
+                f,
+                "instruction {} uses undefined {} {}",
+                instruction, entity, id
+            ),
+        }
+    }
 }
 
+/// Callback of `Interpreter::set_event_observer`
+pub type EventObserver = Box<dyn FnMut(&Event, &Interpreter)>;
+
 /// Event info, which must be handled to execute it lates
-struct Event {
+#[derive(Clone)]
+pub struct Event {
     /// pointer to instruction
     instruction_id: usize,
     /// when will event be executed
     wake_time: u64,
     /// transact related to event
     transact: Option<Transact>,
+    /// order of creation, to execute simultaneous events of equal priority in FIFO order
+    sequence: u64,
+}
+
+impl Event {
+    /// Block, where transact continues or GENERATE, which creates transact
+    pub fn instruction_id(&self) -> usize {
+        self.instruction_id
+    }
+
+    /// Time of event in ticks of clock
+    pub fn wake_time(&self) -> u64 {
+        self.wake_time
+    }
+
+    /// Number of transact, None for arrival of GENERATE
+    pub fn transact_number(&self) -> Option<u32> {
+        self.transact.as_ref().map(|transact| transact.number)
+    }
+
+    /// Parameters of transact, empty for arrival of GENERATE
+    pub fn transact_params(&self) -> &[GpssType] {
+        self.transact
+            .as_ref()
+            .map_or(&[], |transact| &transact.params[..])
+    }
+
+    fn priority(&self) -> u8 {
+        self.transact
+            .as_ref()
+            .map_or(0, |transact| transact.priority)
+    }
 }
 
+/// BinaryHeap pops the greatest event: the earliest one,
+/// then the one with highest priority, then the first created
 impl Ord for Event {
     fn cmp(&self, other: &Event) -> Ordering {
-        other.wake_time.cmp(&self.wake_time)
+        other
+            .wake_time
+            .cmp(&self.wake_time)
+            .then_with(|| self.priority().cmp(&other.priority()))
+            .then_with(|| other.sequence.cmp(&self.sequence))
     }
 }
 
@@ -50,7 +549,7 @@ impl PartialOrd for Event {
 
 impl PartialEq for Event {
     fn eq(&self, other: &Event) -> bool {
-        self.wake_time == other.wake_time
+        self.cmp(other) == Ordering::Equal
     }
 }
 
@@ -59,9 +558,9 @@ impl Eq for Event {}
 macro_rules! gpss_type_impl {
     ($($name:ident($type_of:ty)),+) => {
         /// Types you can use as properties of transacts
-        /// or as variables
-        #[derive(Clone,Copy)]
-        enum GpssType {
+        /// or as variables. String is not Copy, so objects are cloned.
+        #[derive(Clone,Debug)]
+        pub enum GpssType {
             $($name($type_of),)+
         }
 
@@ -70,7 +569,8 @@ macro_rules! gpss_type_impl {
                 match self {
                     $(GpssType::$name(self_val) => match other {
                         GpssType::$name(other_val) => self_val == other_val,
-                        _ => panic!("Comparing $name and not $name"),
+                        // Значения разных типов не равны, как и не упорядочены
+                        _ => false,
                     },)
                     +
                 }
@@ -90,38 +590,46 @@ macro_rules! gpss_type_impl {
         }
 
         $(
-        impl From<GpssType> for $type_of {
-            fn from(item: GpssType) -> Self {
-                match &item {
-                    GpssType::$name(value) => *value,
-                    _ => panic!("Cannot convert type to boolean"),
+        impl TryFrom<GpssType> for $type_of {
+            type Error = RuntimeError;
+            fn try_from(item: GpssType) -> Result<Self, RuntimeError> {
+                match item {
+                    GpssType::$name(value) => Ok(value),
+                    _ => Err(RuntimeError::TypeMismatch {
+                        expected: stringify!($name),
+                        got: item.type_name(),
+                    }),
                 }
             }
         }
         )
         +
 
-        impl From<GpssType> for usize {
-            fn from(item: GpssType) -> Self {
+        impl TryFrom<GpssType> for usize {
+            type Error = RuntimeError;
+            fn try_from(item: GpssType) -> Result<Self, RuntimeError> {
                 match &item {
-                    GpssType::UnsignedInteger(value) => *value as usize,
-                    _ => panic!("Cannot convert type to boolean"),
+                    GpssType::UnsignedInteger(value) => Ok(*value as usize),
+                    _ => Err(RuntimeError::TypeMismatch {
+                        expected: "UnsignedInteger",
+                        got: item.type_name(),
+                    }),
                 }
             }
         }
 
-        impl fmt::Display for GpssType {
-            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-                match self {
-                    $(GpssType::$name(val) => write!(f, "{}, {}", stringify!($name), val),)
-                    +
-                }
-            }
-        }
         impl GpssType {
             fn empty() -> GpssType {
                 GpssType::Boolean(false)
             }
+
+            /// Name of variant for error messages
+            fn type_name(&self) -> &'static str {
+                match self {
+                    $(GpssType::$name(_) => stringify!($name),)
+                    +
+                }
+            }
         }
     }
 }
@@ -131,286 +639,3871 @@ gpss_type_impl!(
     Float(f32),
     Integer(i32),
     Facility(u8),
-    UnsignedInteger(u32)
+    UnsignedInteger(u32),
+    String(String)
 );
 
+/// Value only, for output of PRINT. Type of value is shown by Debug.
+impl fmt::Display for GpssType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GpssType::String(text) => write!(f, "{}", text),
+            GpssType::Boolean(val) => write!(f, "{}", val),
+            GpssType::Float(val) => write!(f, "{}", val),
+            GpssType::Integer(val) => write!(f, "{}", val),
+            GpssType::Facility(val) => write!(f, "{}", val),
+            GpssType::UnsignedInteger(val) => write!(f, "{}", val),
+        }
+    }
+}
+
+impl From<&str> for GpssType {
+    fn from(text: &str) -> GpssType {
+        GpssType::String(text.to_string())
+    }
+}
+
+impl GpssType {
+    fn as_integer(&self) -> Result<i64, RuntimeError> {
+        match *self {
+            GpssType::Integer(value) => Ok(i64::from(value)),
+            GpssType::UnsignedInteger(value) => Ok(i64::from(value)),
+            _ => Err(RuntimeError::TypeMismatch {
+                expected: "Integer",
+                got: self.type_name(),
+            }),
+        }
+    }
+
+    fn as_boolean(&self) -> Result<bool, RuntimeError> {
+        match *self {
+            GpssType::Boolean(value) => Ok(value),
+            _ => Err(RuntimeError::TypeMismatch {
+                expected: "Boolean",
+                got: self.type_name(),
+            }),
+        }
+    }
+
+    fn as_float(&self) -> Result<f32, RuntimeError> {
+        match *self {
+            GpssType::Float(value) => Ok(value),
+            GpssType::Integer(value) => Ok(value as f32),
+            GpssType::UnsignedInteger(value) => Ok(value as f32),
+            _ => Err(RuntimeError::TypeMismatch {
+                expected: "Float",
+                got: self.type_name(),
+            }),
+        }
+    }
+
+    /// Compares objects with numeric promotion like `arithmetic`: Float with any number is compared
+    /// as Float, integers of both kinds are compared as integers. Other objects must have the same type.
+    fn compare(&self, other: &GpssType) -> Result<Ordering, RuntimeError> {
+        let ordering = match (self, other) {
+            (GpssType::Float(_), _) | (_, GpssType::Float(_)) => {
+                self.as_float()?.partial_cmp(&other.as_float()?)
+            }
+            (GpssType::Integer(_), GpssType::UnsignedInteger(_))
+            | (GpssType::UnsignedInteger(_), GpssType::Integer(_)) => {
+                Some(self.as_integer()?.cmp(&other.as_integer()?))
+            }
+            _ => self.partial_cmp(other),
+        };
+        // Несравнимы объекты разных типов и NaN
+        ordering.ok_or(RuntimeError::TypeMismatch {
+            expected: self.type_name(),
+            got: other.type_name(),
+        })
+    }
+
+    /// Applies arithmetic operation with numeric promotion: Float with any number is Float,
+    /// Integer with UnsignedInteger is Integer, UnsignedInteger with UnsignedInteger stays unsigned.
+    fn arithmetic(&self, op: Arithmetic, other: &GpssType) -> Result<GpssType, RuntimeError> {
+        match (self, other) {
+            (GpssType::UnsignedInteger(a), GpssType::UnsignedInteger(b)) => {
+                let value = op.integer(i64::from(*a), i64::from(*b))?;
+                u32::try_from(value)
+                    .map(GpssType::UnsignedInteger)
+                    .map_err(|_| RuntimeError::Overflow)
+            }
+            (GpssType::Float(_), _) | (_, GpssType::Float(_)) => op
+                .float(self.as_float()?, other.as_float()?)
+                .map(GpssType::Float),
+            _ => {
+                let value = op.integer(self.as_integer()?, other.as_integer()?)?;
+                i32::try_from(value)
+                    .map(GpssType::Integer)
+                    .map_err(|_| RuntimeError::Overflow)
+            }
+        }
+    }
+}
+
 /// Transact. Has 16 properties.
 #[derive(Clone)]
 struct Transact {
+    /// Unique number of transact, copies made by SPLIT get their own numbers
+    number: u32,
     params: [GpssType; 16],
+    /// Transacts of higher priority are served first at the same time
+    priority: u8,
+    /// Family: generated transact and its copies made by SPLIT
+    family: u32,
+    /// Time of creation by GENERATE
+    mark_time: u64,
+    /// Times of entry to queues, where transact is now, by queue id.
+    /// Parameters are left to model.
+    queue_entries: HashMap<usize, u64>,
 }
 
 impl Transact {
     fn empty() -> Transact {
         Transact {
+            number: 0,
             params: array![|_| GpssType::empty();16],
+            priority: 0,
+            family: 0,
+            mark_time: 0,
+            queue_entries: HashMap::new(),
         }
     }
 }
 
-/// State of interpreter
-pub struct Interpreter {
-    /// Instructions to execute(program)
-    instructions: Vec<Instructions>,
-    /// Pointer to current instruction
-    current_instruction: usize,
-    /// Current transact. For current context.
-    current_transact: Option<Transact>,
-    /// Number START. Program stops when START becomes zero.
-    start_entities: u32,
-    /// Clock of simulation
-    current_time: u64,
-    /// Events, sorted by time of awake
-    events: BinaryHeap<Event>,
-    /// Global memory
-    memory: Vec<GpssType>,
-    /// Stack
-    stack: Vec<GpssType>,
+/// Default limit of instructions, executed by one run of program
+const DEFAULT_MAX_INSTRUCTIONS: u64 = 100_000_000;
+
+/// Default ticks of clock in unit of model time, so time has resolution 0.001
+const DEFAULT_TIME_SCALE: u64 = 1000;
+
+/// Source of `Interpreter::build_test_interpreter`
+const TEST_PROGRAM: &str = include_str!("../models/test_program.gps");
+
+/// Waiting line statistics, accumulated by QUEUE and DEPART
+#[derive(Clone, Default)]
+pub struct QueueStats {
+    /// Current count of transacts in queue
+    content: u32,
+    /// Maximum count of transacts in queue
+    max_content: u32,
+    /// Count of transacts entered queue
+    entries: u32,
+    /// Count of transacts departed from queue
+    departures: u32,
+    /// Count of transacts departed at the time of entry, without waiting
+    zero_entries: u32,
+    /// Sum of time spent in queue by departed transacts
+    total_time: u64,
+    /// Integral of content over time
+    content_area: u64,
+    /// Time of last content change
+    last_change: u64,
+    /// Time, from which statistics are collected: start of simulation or the last RESET
+    since: u64,
+    /// Clock ticks in unit of model time, see `Interpreter::set_time_scale`
+    time_scale: u64,
 }
 
-impl Interpreter {
-    fn build_interpreter(instructions: Vec<Instructions>, memory: Vec<GpssType>) -> Interpreter {
-        Interpreter {
-            instructions,
-            current_instruction: 0,
-            current_transact: None,
-            start_entities: 15,
-            current_time: 0,
-            events: BinaryHeap::new(),
-            memory,
-            stack: Vec::new(),
-        }
+impl QueueStats {
+    /// Accumulates content area up to `time`
+    fn update_area(&mut self, time: u64) {
+        self.content_area += u64::from(self.content) * (time - self.last_change);
+        self.last_change = time;
     }
 
-    /// Program example
-    pub fn build_test_interpreter() -> Interpreter {
-        use GpssType::*;
-        use Instructions::*;
-        Self::build_interpreter(
-            vec![
-                Push(1),      // Какой обьект сохранить (#1)
-                SaveValue(0), // Вызов инструкции для сохранения значения
-                Push(0),      // Generate возмет время генерации из ячейки #2
-                Generate(2),  // Generate в следующий раз вернется на 2-ую иструкцию
-                Transfer(5),
-                Push(2),
-                Advance(5),
-                Push(4),
-                TestVar(10),
-                PrintClock,
-                Push(3),
-                Terminate(8),
-                Push(3),
-                Terminate(10),
-            ],
-            vec![
-                UnsignedInteger(0),
-                Float(0.01),
-                Float(0.02),
-                UnsignedInteger(1),
-                Boolean(false),
-            ],
-        )
+    pub fn max_content(&self) -> u32 {
+        self.max_content
     }
 
-    fn fraction_time_to_int(t: f32) -> u64 {
-        (t * 1000.0) as u64
+    pub fn content(&self) -> u32 {
+        self.content
     }
 
-    fn int_time_to_fraction(t: u64) -> f32 {
-        t as f32 / 1000.0
+    pub fn entries(&self) -> u32 {
+        self.entries
     }
 
-    fn is_facility_utilised(fac: GpssType) -> Option<bool> {
-        match fac {
-            GpssType::Facility(count) => Some(count != 0),
-            _ => None,
-        }
+    pub fn zero_entries(&self) -> u32 {
+        self.zero_entries
     }
 
-    /// Pops object from stack. Panics if stack is empty.
-    fn stack_pop(&mut self) -> GpssType {
-        self.stack.pop().expect("Stack is empty, but trying to pop")
+    /// Time-weighted average content
+    pub fn average_content(&self) -> f32 {
+        if self.last_change == self.since {
+            return 0.0;
+        }
+        self.content_area as f32 / (self.last_change - self.since) as f32
     }
 
-    fn stack_pop_time(&mut self) -> u64 {
-        Self::fraction_time_to_int(self.stack_pop().into())
+    /// Average time in queue of departed transacts, in units of model time
+    pub fn average_time(&self) -> f32 {
+        if self.departures == 0 {
+            return 0.0;
+        }
+        Interpreter::scaled_time(self.total_time, self.time_scale) / self.departures as f32
     }
+}
 
-    fn generate(&mut self, time: u64) {
-        info!("Wake time for GENERATE {}", self.current_time + time);
-        self.create_event(self.current_instruction, self.current_time + time, None);
-        // После того, как создали новое событие
-        // ищем и исполняем ближайшее
-        self.perform_closest();
+/// Facility statistics, accumulated by SEIZE and RELEASE
+#[derive(Clone, Default)]
+pub struct FacilityStats {
+    /// Count of captures of facility
+    entries: u32,
+    /// Sum of time, when facility was busy
+    busy_time: u64,
+    /// Time of capture, while facility is busy
+    seized_since: Option<u64>,
+    /// Time, from which statistics are collected: start of simulation or the last RESET
+    since: u64,
+}
+
+impl FacilityStats {
+    /// Ends busy period at `time`, when facility becomes free
+    fn free(&mut self, time: u64) {
+        if let Some(since) = self.seized_since.take() {
+            self.busy_time += time - since;
+        }
     }
 
-    fn advance(&mut self, time: u64) {
-        info!("Wake time for ADVANCE {}", self.current_time + time);
-        self.create_event(
-            self.current_instruction,
-            self.current_time + time,
-            self.current_transact.clone(),
-        );
-        self.perform_closest();
+    pub fn entries(&self) -> u32 {
+        self.entries
     }
 
-    fn terminate(&mut self, count: u32) {
-        info!("TERMINATE {}", count);
-        self.start_entities -= count;
-        self.current_transact = None;
-        if self.events.len() > 0 && self.start_entities > 0 {
-            self.perform_closest();
-        } else {
-            info!("STOP");
-            self.current_instruction += 1;
+    /// Fraction of time up to `total_time`, when facility was busy, counted from the last RESET.
+    /// Facility, which is still busy, counts as busy up to `total_time`.
+    pub fn utilization(&self, total_time: u64) -> f64 {
+        if total_time == self.since {
+            return 0.0;
         }
+        let busy_time = self.busy_time + self.seized_since.map_or(0, |since| total_time - since);
+        busy_time as f64 / (total_time - self.since) as f64
     }
+}
 
-    fn print(&mut self, var_id: usize) {
-        println!("{}", self.memory[var_id]);
-        self.current_instruction += 1;
+/// Transact, displaced from facility by PREEMPT
+#[derive(Clone)]
+struct Preempted {
+    /// Number of displaced transact
+    number: Option<u32>,
+    /// Event of suspended ADVANCE, taken from event list, and time left to its end.
+    /// None, if transact was not in ADVANCE, it keeps moving then.
+    advance: Option<(Event, u64)>,
+}
+
+/// Owner of facility and transacts displaced by PREEMPT, the last displaced one is on top
+#[derive(Clone, Default)]
+struct Ownership {
+    owner: Option<u32>,
+    preempted: Vec<Preempted>,
+}
+
+/// Offset and limit of GENERATE with count of transacts it created
+#[derive(Clone, Default)]
+struct Generator {
+    /// Memory address of time of the first arrival
+    offset: Option<usize>,
+    /// Maximal count of transacts to create
+    limit: Option<u32>,
+    created: u32,
+}
+
+impl Generator {
+    /// Counts created transact. Returns false, when limit is reached.
+    fn create(&mut self) -> bool {
+        self.created += 1;
+        self.limit.map_or(true, |limit| self.created < limit)
     }
+}
 
-    fn print_clock(&mut self) {
-        println!("Clock {}", self.current_time);
-        self.current_instruction += 1;
+/// Matrix savevalue: two-dimensional cells, indexed from 1 like in GPSS
+#[derive(Clone)]
+struct Matrix {
+    rows: Vec<Vec<GpssType>>,
+}
+
+/// Storage: resource of finite capacity for ENTER and LEAVE
+#[derive(Clone, Default)]
+struct Storage {
+    capacity: u32,
+    /// Count of units in use
+    used: u32,
+    /// Transacts blocked on ENTER: count of units and event to resume
+    waiters: VecDeque<(u32, Event)>,
+    /// Count of units entered, like ENTRIES of GPSS
+    entries: u32,
+    /// Maximum count of units in use
+    max_used: u32,
+    /// Integral of units in use over time
+    used_area: u64,
+    /// Time of last change of units in use
+    last_change: u64,
+    /// Time, from which statistics are collected: start of simulation or the last RESET
+    since: u64,
+}
+
+impl Storage {
+    /// Accumulates area of units in use up to `time`
+    fn update_area(&mut self, time: u64) {
+        self.used_area += u64::from(self.used) * (time - self.last_change);
+        self.last_change = time;
     }
 
-    fn transfer(&mut self, instruction_id: usize) {
-        info!(
-            "TRANSFER FROM {} TO {}",
-            self.current_instruction, instruction_id
-        );
-        self.current_instruction = instruction_id;
+    /// Takes `units` at `time` for entering transact
+    fn take(&mut self, units: u32, time: u64) {
+        self.update_area(time);
+        self.used += units;
+        self.entries += units;
+        self.max_used = self.max_used.max(self.used);
     }
 
-    fn test_var(&mut self, else_goto: usize, cond_result: bool) {
-        info!("Condition is {}", cond_result);
-        if cond_result {
-            self.current_instruction += 1;
-        } else {
-            self.current_instruction = else_goto;
+    /// Time-weighted average of units in use up to `total_time`
+    fn average_content(&self, total_time: u64) -> f64 {
+        if total_time == self.since {
+            return 0.0;
         }
+        let area = self.used_area + u64::from(self.used) * (total_time - self.last_change);
+        area as f64 / (total_time - self.since) as f64
     }
+}
 
-    fn save_value(&mut self, var_id: usize, object: GpssType) {
-        info!("Saving value {} to {}", object, var_id);
-        if self.memory.len() > var_id {
-            self.memory[var_id] = object.clone();
-        } else if self.memory.len() == var_id {
-            self.memory.push(object.clone());
-        } else {
-            panic!("Cannot access variable {}", var_id);
+/// How function computes value between points
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum FunctionMode {
+    /// Linear interpolation between neighbour points
+    Continuous,
+    /// Value of the first point, which argument is not less than given one
+    Discrete,
+}
+
+/// FUNCTION entity: lookup table of (argument, value) points, sorted by argument
+struct Function {
+    points: Vec<(f32, f32)>,
+    mode: FunctionMode,
+}
+
+impl Function {
+    /// Value of function. Arguments out of table range take value of the nearest end.
+    fn evaluate(&self, argument: f32) -> Option<f32> {
+        let (&first, &last) = (self.points.first()?, self.points.last()?);
+        if argument <= first.0 {
+            return Some(first.1);
+        }
+        // Первая точка, аргумент которой не меньше заданного
+        let upper = match self.points.iter().position(|point| point.0 >= argument) {
+            Some(upper) => upper,
+            None => return Some(last.1),
+        };
+        let (x1, y1) = self.points[upper];
+        match self.mode {
+            FunctionMode::Discrete => Some(y1),
+            FunctionMode::Continuous => {
+                let (x0, y0) = self.points[upper - 1];
+                Some(y0 + (y1 - y0) * (argument - x0) / (x1 - x0))
+            }
         }
-        self.current_instruction += 1;
     }
+}
 
-    fn push(&mut self, var_id: usize) {
-        info!("Push: {}", self.memory[var_id]);
-        self.stack.push(self.memory[var_id]);
-        self.current_instruction += 1;
+/// Value, which table collects
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum TableArgument {
+    /// TABLE M1 or MPj: transit time from creation of transact or from MARK to parameter j,
+    /// recorded by TABULATE
+    Transit(Option<usize>),
+    /// TABLE Pj: value of parameter j, recorded by TABULATE
+    Parameter(usize),
+    /// QTABLE: time in queue with this id, recorded by DEPART
+    Queue(usize),
+}
+
+/// Control statement, which runs simulation or prepares the next run
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Control {
+    /// START: termination count and whether standard report is printed, false for NP operand
+    Start(u32, bool),
+    /// RESET: statistics are collected anew, transacts stay in model
+    Reset,
+    /// CLEAR: model returns to initial state, random numbers continue
+    Clear,
+}
+
+/// TABLE entity: frequency histogram of transit times with mean and deviation
+#[derive(Clone)]
+struct Table {
+    argument: TableArgument,
+    /// Upper limit of the first frequency class
+    upper_limit: f32,
+    /// Width of frequency classes
+    width: f32,
+    /// Count of transacts in each class. Like in GPSS, the first class has no lower limit,
+    /// so it is the underflow class, and the last one is the overflow class above the others.
+    frequencies: Vec<u32>,
+    entries: u32,
+    sum: f64,
+    sum_squares: f64,
+}
+
+impl Table {
+    /// Records value as `weight` entries. Values up to the upper limit of the first class,
+    /// however small, go to it, values above the last limit go to the last class.
+    fn record(&mut self, value: f32, weight: u32) {
+        let class = if value <= self.upper_limit {
+            0
+        } else {
+            ((value - self.upper_limit) / self.width).ceil() as usize
+        };
+        let last = self.frequencies.len() - 1;
+        self.frequencies[class.min(last)] += weight;
+        self.entries += weight;
+        self.sum += f64::from(value) * f64::from(weight);
+        self.sum_squares += f64::from(value) * f64::from(value) * f64::from(weight);
     }
 
-    /// Executes commands from start to end. Excluding end.
-    fn process_from_to(&mut self, start: usize, end: usize) {
-        self.current_instruction = start;
-        while self.current_instruction < end {
-            self.process_instruction();
-            self.current_instruction += 1;
+    /// Forgets recorded values, for RESET
+    fn clear(&mut self) {
+        for frequency in &mut self.frequencies {
+            *frequency = 0;
         }
+        self.entries = 0;
+        self.sum = 0.0;
+        self.sum_squares = 0.0;
     }
 
-    /// Executes closest event
-    fn perform_closest(&mut self) {
-        // В этом match идет исполнение кода для откладываемых событий
-        // Исполняем ближайшее событие, если оно есть
-        match self.events.pop() {
-            Some(nearest_event) => {
-                self.current_time = nearest_event.wake_time;
-                info!("Woke up at {}", self.current_time);
-                self.current_transact = nearest_event.transact;
-
-                match self.instructions[nearest_event.instruction_id] {
-                    Instructions::Generate(begin) | Instructions::Advance(begin) => {
-                        self.process_from_to(begin, nearest_event.instruction_id);
-                    }
-                    _ => return,
-                };
-
-                match self.instructions[nearest_event.instruction_id] {
-                    Instructions::Generate(_) => {
-                        let time = self.stack_pop_time();
-                        info!("DOING GENERATE");
-                        let mut new_transact = Transact::empty();
-                        new_transact.params[0] = GpssType::Integer(rand::random::<i32>());
-                        self.current_transact = Some(new_transact);
-                        // после генерации текущего транзакта, надо запланировать генерацию следующего
-                        self.create_event(
-                            nearest_event.instruction_id,
-                            self.current_time + time,
-                            None,
-                        );
-                        self.current_instruction = nearest_event.instruction_id + 1;
-                    }
-                    Instructions::Advance(_) => {
-                        info!("DOING ADVANCE");
-                        self.current_instruction = nearest_event.instruction_id + 1;
-                    }
-                    _ => {
-                        self.current_instruction = nearest_event.instruction_id + 1;
-                    }
-                }
-            }
-            None => {}
+    fn mean(&self) -> f64 {
+        if self.entries == 0 {
+            return 0.0;
         }
+        self.sum / f64::from(self.entries)
     }
 
-    /// Schedules event in future
-    fn create_event(&mut self, instruction_id: usize, wake_time: u64, transact: Option<Transact>) {
-        self.events.push(Event {
-            instruction_id,
-            wake_time,
-            transact,
-        });
+    fn std_dev(&self) -> f64 {
+        if self.entries == 0 {
+            return 0.0;
+        }
+        let mean = self.mean();
+        (self.sum_squares / f64::from(self.entries) - mean * mean)
+            .max(0.0)
+            .sqrt()
     }
+}
 
-    /// Executes current instruction
-    fn process_instruction(&mut self) {
-        match self.instructions[self.current_instruction] {
-            //Блоки, требущие подождать. Создаем для них событие в будущем
-            Instructions::Generate(_) => {
-                let time = self.stack_pop_time();
-                self.generate(time);
-            }
-            Instructions::Advance(_) => {
-                let time = self.stack_pop_time();
-                self.advance(time);
-            }
-            //Блоки, не требующие подождать
+/// Table of final report
+#[derive(Debug, Serialize)]
+pub struct TableReport {
+    pub id: usize,
+    /// Name in model, or id for program without names
+    pub name: String,
+    pub entries: u32,
+    pub mean: f64,
+    pub std_dev: f64,
+    /// Upper limit and frequency of each class. Limit of the last class is infinity.
+    #[serde(serialize_with = "serialize_classes")]
+    pub classes: Vec<(f32, u32)>,
+}
+
+/// Facility line of final report
+#[derive(Debug, Serialize)]
+pub struct FacilityReport {
+    /// Facility address
+    pub id: usize,
+    /// Name in model, or address for program without names
+    pub name: String,
+    pub entries: u32,
+    /// Fraction of simulated time, when facility was busy
+    pub utilization: f64,
+}
+
+/// Queue line of final report
+#[derive(Debug, Serialize)]
+pub struct QueueReport {
+    pub id: usize,
+    /// Name in model, or id for program without names
+    pub name: String,
+    pub max_content: u32,
+    /// Content at the end of simulation
+    pub content: u32,
+    pub entries: u32,
+    /// Entries, which departed without waiting
+    pub zero_entries: u32,
+    pub average_content: f32,
+    pub average_time: f32,
+}
+
+/// Storage line of final report
+#[derive(Debug, Serialize)]
+pub struct StorageReport {
+    pub id: usize,
+    /// Name in model, or id for program without names
+    pub name: String,
+    pub capacity: u32,
+    /// Units in use at the end of simulation
+    pub content: u32,
+    pub max_content: u32,
+    /// Count of units entered
+    pub entries: u32,
+    pub average_content: f64,
+    /// Average fraction of capacity in use
+    pub utilization: f64,
+}
+
+/// Final report of simulation
+#[derive(Debug, Serialize)]
+pub struct Report {
+    /// Clock at the end of simulation
+    pub end_time: f32,
+    /// Count of transacts, created by GENERATE
+    pub generated: u32,
+    /// Count of transacts, destroyed by TERMINATE
+    pub terminated: u32,
+    pub facilities: Vec<FacilityReport>,
+    pub queues: Vec<QueueReport>,
+    pub storages: Vec<StorageReport>,
+    pub tables: Vec<TableReport>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "END TIME {:.3}", self.end_time)?;
+        writeln!(
+            f,
+            "TRANSACTS GENERATED {} TERMINATED {}",
+            self.generated, self.terminated
+        )?;
+        if !self.facilities.is_empty() {
+            writeln!(f, "\n{:<10} {:>8} {:>8}", "FACILITY", "ENTRIES", "UTIL.")?;
+        }
+        for facility in &self.facilities {
+            writeln!(
+                f,
+                "{:<10} {:>8} {:>8.3}",
+                facility.name, facility.entries, facility.utilization
+            )?;
+        }
+        if !self.queues.is_empty() {
+            writeln!(
+                f,
+                "\n{:<10} {:>8} {:>8} {:>8} {:>8} {:>10} {:>10}",
+                "QUEUE", "MAX", "CONT.", "ENTRIES", "ZERO", "AVE.CONT.", "AVE.TIME"
+            )?;
+        }
+        for queue in &self.queues {
+            writeln!(
+                f,
+                "{:<10} {:>8} {:>8} {:>8} {:>8} {:>10.3} {:>10.3}",
+                queue.name,
+                queue.max_content,
+                queue.content,
+                queue.entries,
+                queue.zero_entries,
+                queue.average_content,
+                queue.average_time
+            )?;
+        }
+        if !self.storages.is_empty() {
+            writeln!(
+                f,
+                "\n{:<10} {:>8} {:>8} {:>8} {:>8} {:>10} {:>8}",
+                "STORAGE", "CAP.", "CONT.", "MAX", "ENTRIES", "AVE.CONT.", "UTIL."
+            )?;
+        }
+        for storage in &self.storages {
+            writeln!(
+                f,
+                "{:<10} {:>8} {:>8} {:>8} {:>8} {:>10.3} {:>8.3}",
+                storage.name,
+                storage.capacity,
+                storage.content,
+                storage.max_content,
+                storage.entries,
+                storage.average_content,
+                storage.utilization
+            )?;
+        }
+        for table in &self.tables {
+            writeln!(
+                f,
+                "\nTABLE {:<4} ENTRIES {:>8} MEAN {:>10.3} STD.DEV. {:>10.3}",
+                table.name, table.entries, table.mean, table.std_dev
+            )?;
+            writeln!(f, "{:>12} {:>10}", "UPPER LIMIT", "FREQUENCY")?;
+            // Пустые классы в конце гистограммы не печатаются
+            let used = table
+                .classes
+                .iter()
+                .rposition(|&(_, frequency)| frequency > 0)
+                .map_or(0, |last| last + 1);
+            for &(limit, frequency) in &table.classes[..used] {
+                if limit.is_finite() {
+                    writeln!(f, "{:>12.3} {:>10}", limit, frequency)?;
+                } else {
+                    writeln!(f, "{:>12} {:>10}", "OVERFLOW", frequency)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Name of entity in model, or its id for program without names
+fn entity_name(names: &HashMap<String, usize>, entity_id: usize) -> String {
+    names
+        .iter()
+        .find(|&(_, &id)| id == entity_id)
+        .map_or_else(|| entity_id.to_string(), |(name, _)| name.clone())
+}
+
+/// Classes of table as JSON objects. JSON has no infinity, so the last upper limit is written as null.
+fn serialize_classes<S: Serializer>(
+    classes: &[(f32, u32)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Class {
+        upper_limit: f32,
+        frequency: u32,
+    }
+    serializer.collect_seq(classes.iter().map(|&(upper_limit, frequency)| Class {
+        upper_limit,
+        frequency,
+    }))
+}
+
+impl Report {
+    /// Report as JSON object, for tools, which check results of simulation.
+    /// Infinity and NaN are written as null.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("report has only numbers and names")
+    }
+}
+
+/// Saved state of simulation, see `Interpreter::snapshot`.
+/// Program, functions and limit of instructions are not part of state.
+#[derive(Clone)]
+pub struct InterpreterState {
+    current_instruction: usize,
+    current_transact: Option<Transact>,
+    start_entities: u32,
+    current_time: u64,
+    /// Events in order of execution
+    events: Vec<Event>,
+    memory: Vec<GpssType>,
+    stack: Vec<GpssType>,
+    facility_waiters: HashMap<usize, VecDeque<Event>>,
+    ownership: HashMap<usize, Ownership>,
+    facilities: HashMap<usize, FacilityStats>,
+    queues: HashMap<usize, QueueStats>,
+    storages: Vec<Storage>,
+    matrices: Vec<Matrix>,
+    tables: Vec<Table>,
+    rng: StdRng,
+    generated: u32,
+    terminated: u32,
+    next_sequence: u64,
+    next_family: u32,
+    next_number: u32,
+    assemblies: HashMap<(usize, u32), (usize, Event)>,
+    gatherings: HashMap<(usize, u32), (usize, Vec<Event>)>,
+    matchings: HashMap<(usize, u32), VecDeque<Event>>,
+    gate_waiters: Vec<(GateCondition, Event)>,
+    generators: HashMap<usize, Generator>,
+    started: bool,
+    executed: u64,
+    reset_time: u64,
+    /// START, RESET and CLEAR statements, which are not executed yet
+    controls: VecDeque<Control>,
+}
+
+/// State of interpreter
+pub struct Interpreter {
+    /// Instructions to execute(program)
+    instructions: Vec<Instructions>,
+    /// Pointer to current instruction
+    current_instruction: usize,
+    /// Current transact. For current context.
+    current_transact: Option<Transact>,
+    /// Number START. Program stops when START becomes zero.
+    start_entities: u32,
+    /// Clock of simulation
+    current_time: u64,
+    /// Events, sorted by time of awake
+    events: BinaryHeap<Event>,
+    /// Global memory
+    memory: Vec<GpssType>,
+    /// Stack
+    stack: Vec<GpssType>,
+    /// Events of transacts blocked on SEIZE, by facility address
+    facility_waiters: HashMap<usize, VecDeque<Event>>,
+    /// Owners and preempted transacts of facilities, by facility address.
+    /// Memory cell of facility keeps only whether it is busy.
+    ownership: HashMap<usize, Ownership>,
+    /// Statistics of facilities, by facility address
+    facilities: HashMap<usize, FacilityStats>,
+    /// Statistics of queues, by queue id
+    queues: HashMap<usize, QueueStats>,
+    /// Storages, by storage id
+    storages: Vec<Storage>,
+    /// Matrix savevalues, by matrix id
+    matrices: Vec<Matrix>,
+    /// Functions, by function id
+    functions: Vec<Function>,
+    /// Tables, by table id
+    tables: Vec<Table>,
+    /// Source of all random draws, so runs with the same seed are reproducible
+    rng: StdRng,
+    /// Count of transacts, created by GENERATE
+    generated: u32,
+    /// Count of transacts, destroyed by TERMINATE
+    terminated: u32,
+    /// Sequence number of the next event
+    next_sequence: u64,
+    /// Family of the next generated transact
+    next_family: u32,
+    /// Number of the last created transact
+    next_number: u32,
+    /// First family members waiting on ASSEMBLE, by instruction and family:
+    /// count of members to wait for and event to resume
+    assemblies: HashMap<(usize, u32), (usize, Event)>,
+    /// Family members waiting on GATHER, by instruction and family:
+    /// count of members to wait for and their events
+    gatherings: HashMap<(usize, u32), (usize, Vec<Event>)>,
+    /// Transacts waiting on MATCH for family member at conjugate MATCH, by instruction and family
+    matchings: HashMap<(usize, u32), VecDeque<Event>>,
+    /// Transacts blocked on GATE, with their conditions
+    gate_waiters: Vec<(GateCondition, Event)>,
+    /// Offsets and limits of GENERATE, by instruction
+    generators: HashMap<usize, Generator>,
+    /// First arrivals of GENERATE blocks are scheduled
+    started: bool,
+    /// Count of executed instructions
+    executed: u64,
+    /// Limit of executed instructions, which stops endless loops
+    max_instructions: u64,
+    /// Ticks of clock in unit of model time
+    time_scale: u64,
+    /// Unassigned parameters read as 0 instead of error
+    unassigned_params_zero: bool,
+    /// Time of the last RESET, statistics are collected from it
+    reset_time: u64,
+    /// START, RESET and CLEAR statements, which are not executed yet
+    controls: VecDeque<Control>,
+    /// State before the first START, to which CLEAR returns
+    initial_state: Option<InterpreterState>,
+    /// Names of labels and entities of loaded model
+    symbols: Symbols,
+    /// Callback, which sees each event, when it is executed
+    event_observer: Option<EventObserver>,
+}
+
+impl Interpreter {
+    pub(crate) fn build_interpreter(
+        instructions: Vec<Instructions>,
+        memory: Vec<GpssType>,
+    ) -> Interpreter {
+        Self::build_interpreter_with_rng(instructions, memory, StdRng::from_entropy())
+    }
+
+    /// Interpreter, which produces the same results for the same seed.
+    /// Public entry points are `Interpreter::from_source_with_seed` and `run_source_with_seed`.
+    pub(crate) fn build_interpreter_with_seed(
+        instructions: Vec<Instructions>,
+        memory: Vec<GpssType>,
+        seed: u64,
+    ) -> Interpreter {
+        Self::build_interpreter_with_rng(instructions, memory, StdRng::seed_from_u64(seed))
+    }
+
+    fn build_interpreter_with_rng(
+        instructions: Vec<Instructions>,
+        memory: Vec<GpssType>,
+        rng: StdRng,
+    ) -> Interpreter {
+        // Счетчик завершения задает START из управляющих операторов, см. `run_next_start`
+        let start_entities = 1;
+        // Ограничения относятся к следующему за ними GENERATE
+        let mut generators = HashMap::new();
+        let mut limits = None;
+        for (instruction_id, instruction) in instructions.iter().enumerate() {
+            match *instruction {
+                Instructions::GenerateLimits(offset, limit) => limits = Some((offset, limit)),
+                Instructions::Generate(_)
+                | Instructions::GenerateExp(_)
+                | Instructions::GenerateUniform(_) => {
+                    if let Some((offset, limit)) = limits.take() {
+                        generators.insert(
+                            instruction_id,
+                            Generator {
+                                offset,
+                                limit,
+                                created: 0,
+                            },
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+        Interpreter {
+            instructions,
+            current_instruction: 0,
+            current_transact: None,
+            start_entities,
+            current_time: 0,
+            events: BinaryHeap::new(),
+            memory,
+            stack: Vec::new(),
+            facility_waiters: HashMap::new(),
+            ownership: HashMap::new(),
+            facilities: HashMap::new(),
+            queues: HashMap::new(),
+            storages: Vec::new(),
+            matrices: Vec::new(),
+            functions: Vec::new(),
+            tables: Vec::new(),
+            rng,
+            generated: 0,
+            terminated: 0,
+            next_sequence: 0,
+            next_family: 0,
+            next_number: 0,
+            assemblies: HashMap::new(),
+            gatherings: HashMap::new(),
+            matchings: HashMap::new(),
+            gate_waiters: Vec::new(),
+            generators,
+            started: false,
+            executed: 0,
+            max_instructions: DEFAULT_MAX_INSTRUCTIONS,
+            unassigned_params_zero: false,
+            time_scale: DEFAULT_TIME_SCALE,
+            reset_time: 0,
+            controls: VecDeque::new(),
+            initial_state: None,
+            symbols: Symbols::default(),
+            event_observer: None,
+        }
+    }
+
+    /// Interpreter of generated program with its storages, tables, functions and control statements.
+    /// Program is checked by `validate`. Without seed random numbers differ from run to run.
+    pub(crate) fn from_program(
+        program: Program,
+        seed: Option<u64>,
+    ) -> Result<Interpreter, ValidationError> {
+        let mut interpreter = match seed {
+            Some(seed) => {
+                Self::build_interpreter_with_seed(program.instructions, program.memory, seed)
+            }
+            None => Self::build_interpreter(program.instructions, program.memory),
+        };
+        for capacity in program.storages {
+            interpreter.define_storage(capacity);
+        }
+        for (argument, upper_limit, width, count) in program.tables {
+            interpreter.define_table(argument, upper_limit, width, count);
+        }
+        for (points, mode) in program.functions {
+            interpreter.define_function(points, mode);
+        }
+        for (rows, columns) in program.matrices {
+            interpreter.define_matrix(rows, columns, GpssType::Integer(0));
+        }
+        interpreter.set_controls(program.controls);
+        interpreter.symbols = program.symbols;
+        interpreter.validate()?;
+        Ok(interpreter)
+    }
+
+    /// Checks, that pointers of instructions are in program and ids of memory and entities are defined,
+    /// so bad program fails before simulation. Operands are pushed before their block,
+    /// so `begin` of GENERATE or ADVANCE is not after it.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        for (instruction, operation) in self.instructions.iter().enumerate() {
+            let pointer = |pointer: usize| {
+                if pointer < self.instructions.len() {
+                    Ok(())
+                } else {
+                    Err(ValidationError::BadPointer {
+                        instruction,
+                        pointer,
+                    })
+                }
+            };
+            let address = |address: usize| {
+                if address < self.memory.len() {
+                    Ok(())
+                } else {
+                    Err(ValidationError::BadMemory {
+                        instruction,
+                        address,
+                    })
+                }
+            };
+            let entity = |entity: &'static str, id: usize, count: usize| {
+                if id < count {
+                    Ok(())
+                } else {
+                    Err(ValidationError::BadEntity {
+                        instruction,
+                        entity,
+                        id,
+                    })
+                }
+            };
+            match *operation {
+                Instructions::Generate(begin)
+                | Instructions::GenerateExp(begin)
+                | Instructions::GenerateUniform(begin)
+                | Instructions::Advance(begin)
+                | Instructions::AdvanceUniform(begin)
+                | Instructions::Terminate(begin)
+                    if begin > instruction =>
+                {
+                    return Err(ValidationError::BadPointer {
+                        instruction,
+                        pointer: begin,
+                    });
+                }
+                Instructions::GenerateLimits(Some(offset), _) => address(offset)?,
+                Instructions::AdvanceNormal(mean, std_dev) => {
+                    address(mean)?;
+                    address(std_dev)?;
+                }
+                Instructions::Transfer(target)
+                | Instructions::Test(_, target)
+                | Instructions::Gate(_, target)
+                | Instructions::Loop(_, target)
+                | Instructions::Split(target) => pointer(target)?,
+                Instructions::TransferFraction(_, first, second)
+                | Instructions::TransferBoth(first, second) => {
+                    pointer(first)?;
+                    pointer(second)?;
+                }
+                // Парный MATCH должен быть блоком MATCH, иначе транзакт ждет вечно
+                Instructions::Match(conjugate) => match self.instructions.get(conjugate) {
+                    Some(Instructions::Match(_)) => {}
+                    _ => {
+                        return Err(ValidationError::BadPointer {
+                            instruction,
+                            pointer: conjugate,
+                        })
+                    }
+                },
+                Instructions::Print(var_id)
+                | Instructions::SaveValue(var_id)
+                | Instructions::SaveValueOp(var_id, _)
+                | Instructions::Push(var_id)
+                | Instructions::Seize(var_id)
+                | Instructions::Release(var_id)
+                | Instructions::Preempt(var_id)
+                | Instructions::Return(var_id) => address(var_id)?,
+                Instructions::Enter(storage_id, _) | Instructions::Leave(storage_id, _) => {
+                    entity("storage", storage_id, self.storages.len())?
+                }
+                Instructions::SaveMatrix(matrix_id) | Instructions::PushMatrix(matrix_id) => {
+                    entity("matrix", matrix_id, self.matrices.len())?
+                }
+                Instructions::CallFunction(function_id) => {
+                    entity("function", function_id, self.functions.len())?
+                }
+                Instructions::Tabulate(table_id, _) => {
+                    entity("table", table_id, self.tables.len())?
+                }
+                _ => {}
+            }
+            if let Instructions::Gate(condition, _) | Instructions::GateWait(condition) = *operation
+            {
+                match condition {
+                    GateCondition::FacilityUsed(fac_id) | GateCondition::FacilityUnused(fac_id) => {
+                        address(fac_id)?
+                    }
+                    GateCondition::StorageFull(storage_id)
+                    | GateCondition::StorageNotFull(storage_id)
+                    | GateCondition::StorageEmpty(storage_id)
+                    | GateCondition::StorageNotEmpty(storage_id) => {
+                        entity("storage", storage_id, self.storages.len())?
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Defines storage with `capacity` units. Returns id of storage for ENTER and LEAVE.
+    pub(crate) fn define_storage(&mut self, capacity: u32) -> usize {
+        self.storages.push(Storage {
+            capacity,
+            ..Storage::default()
+        });
+        self.storages.len() - 1
+    }
+
+    /// Defines matrix savevalue of `rows` x `columns` cells, filled with `fill`.
+    /// Returns id of matrix for SaveMatrix and PushMatrix.
+    pub(crate) fn define_matrix(&mut self, rows: usize, columns: usize, fill: GpssType) -> usize {
+        self.matrices.push(Matrix {
+            rows: vec![vec![fill; columns]; rows],
+        });
+        self.matrices.len() - 1
+    }
+
+    /// Defines function by points, sorted by argument. Returns id of function for CallFunction.
+    pub(crate) fn define_function(&mut self, points: Vec<(f32, f32)>, mode: FunctionMode) -> usize {
+        self.functions.push(Function { points, mode });
+        self.functions.len() - 1
+    }
+
+    /// Defines table of `count` frequency classes: values up to `upper_limit`,
+    /// then classes of `width` and the last one for the rest.
+    /// Returns id of table for TABULATE.
+    pub(crate) fn define_table(
+        &mut self,
+        argument: TableArgument,
+        upper_limit: f32,
+        width: f32,
+        count: usize,
+    ) -> usize {
+        self.tables.push(Table {
+            argument,
+            upper_limit,
+            width,
+            frequencies: vec![0; count.max(2)],
+            entries: 0,
+            sum: 0.0,
+            sum_squares: 0.0,
+        });
+        self.tables.len() - 1
+    }
+
+    /// Sets START, RESET and CLEAR statements in order of program, see `run_next_start`
+    pub(crate) fn set_controls(&mut self, controls: Vec<Control>) {
+        self.controls = controls.into();
+    }
+
+    /// Interpreter of `models/test_program.gps`, built like any other model
+    pub fn build_test_interpreter() -> Interpreter {
+        Self::from_source(TEST_PROGRAM).expect("test program must be valid")
+    }
+
+    /// Ticks of model time. Integers are multiplied by scale exactly,
+    /// fractions are rounded to the nearest tick, negative time is zero.
+    fn time_to_ticks(&self, time: &GpssType) -> Result<u64, RuntimeError> {
+        match *time {
+            GpssType::Integer(_) | GpssType::UnsignedInteger(_) => {
+                let time = u64::try_from(time.as_integer()?).unwrap_or(0);
+                time.checked_mul(self.time_scale)
+                    .ok_or(RuntimeError::Overflow)
+            }
+            _ => Ok((f64::from(time.as_float()?) * self.time_scale as f64).round() as u64),
+        }
+    }
+
+    /// Model time of ticks
+    fn ticks_to_time(&self, ticks: u64) -> f32 {
+        Self::scaled_time(ticks, self.time_scale)
+    }
+
+    /// Ticks as object of model time. Whole times are Integer, like constants of model,
+    /// others are Float.
+    fn time_value(&self, ticks: u64) -> GpssType {
+        match i32::try_from(ticks / self.time_scale) {
+            Ok(time) if ticks % self.time_scale == 0 => GpssType::Integer(time),
+            _ => GpssType::Float(self.ticks_to_time(ticks)),
+        }
+    }
+
+    fn scaled_time(ticks: u64, time_scale: u64) -> f32 {
+        (ticks as f64 / time_scale as f64) as f32
+    }
+
+    fn is_facility_utilised(fac: &GpssType) -> Option<bool> {
+        match *fac {
+            GpssType::Facility(count) => Some(count != 0),
+            _ => None,
+        }
+    }
+
+    /// Pops object from stack
+    fn stack_pop(&mut self) -> Result<GpssType, RuntimeError> {
+        self.stack.pop().ok_or(RuntimeError::StackUnderflow)
+    }
+
+    fn stack_pop_time(&mut self) -> Result<u64, RuntimeError> {
+        let time = self.stack_pop()?;
+        self.time_to_ticks(&time)
+    }
+
+    /// Reads object from memory
+    fn memory_get(&self, var_id: usize) -> Result<GpssType, RuntimeError> {
+        self.memory
+            .get(var_id)
+            .cloned()
+            .ok_or(RuntimeError::BadMemoryAccess(var_id))
+    }
+
+    /// Pops column, then row, and finds cell of matrix. Rows and columns are counted from 1.
+    fn matrix_cell(&mut self, matrix_id: usize) -> Result<&mut GpssType, RuntimeError> {
+        let column = self.stack_pop()?.as_integer()?;
+        let row = self.stack_pop()?.as_integer()?;
+        let matrix = self
+            .matrices
+            .get_mut(matrix_id)
+            .ok_or(RuntimeError::BadMemoryAccess(matrix_id))?;
+        // Номера вне матрицы, в том числе нулевые и отрицательные, не находят ячейку
+        match (usize::try_from(row - 1), usize::try_from(column - 1)) {
+            (Ok(row), Ok(column)) => matrix
+                .rows
+                .get_mut(row)
+                .and_then(|cells| cells.get_mut(column)),
+            _ => None,
+        }
+        .ok_or(RuntimeError::BadMemoryAccess(matrix_id))
+    }
+
+    fn save_matrix(&mut self, matrix_id: usize) -> Result<(), RuntimeError> {
+        let object = self.stack_pop()?;
+        info!("Saving value {:?} to matrix {}", object, matrix_id);
+        *self.matrix_cell(matrix_id)? = object;
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    fn push_matrix(&mut self, matrix_id: usize) -> Result<(), RuntimeError> {
+        let object = self.matrix_cell(matrix_id)?.clone();
+        info!("Push from matrix {}: {:?}", matrix_id, object);
+        self.stack.push(object);
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    /// Samples exponentially distributed interval with mean `mean` ticks.
+    /// Rate of arrivals is 1 / mean. Interval is at least one tick,
+    /// so zero mean doesn't flood the same clock value with arrivals.
+    fn sample_exponential(&mut self, mean: u64) -> u64 {
+        let uniform: f64 = self.rng.gen();
+        let interval = -(mean as f64) * (1.0 - uniform).ln();
+        (interval as u64).max(1)
+    }
+
+    /// Samples interval uniformly distributed in [mean - spread, mean + spread] ticks.
+    /// Lower bound is clamped to zero, so spread greater than mean doesn't produce negative time.
+    fn sample_uniform(&mut self, mean: u64, spread: u64) -> u64 {
+        let low = mean.saturating_sub(spread);
+        self.rng.gen_range(low, mean + spread + 1)
+    }
+
+    /// Samples normally distributed time in ticks. Negative samples are clamped to zero.
+    fn sample_normal(&mut self, mean: u64, std_dev: u64) -> u64 {
+        let time = Normal::new(mean as f64, std_dev as f64).sample(&mut self.rng);
+        time.max(0.0).round() as u64
+    }
+
+    /// Pops operands of GENERATE-like instruction and computes interval to the next arrival
+    fn pop_generate_interval(&mut self, instruction_id: usize) -> Result<u64, RuntimeError> {
+        match self.instructions[instruction_id] {
+            Instructions::GenerateExp(_) => {
+                let mean = self.stack_pop_time()?;
+                Ok(self.sample_exponential(mean))
+            }
+            Instructions::GenerateUniform(_) => {
+                let spread = self.stack_pop_time()?;
+                let mean = self.stack_pop_time()?;
+                Ok(self.sample_uniform(mean, spread))
+            }
+            _ => self.stack_pop_time(),
+        }
+    }
+
+    /// Schedules the first arrival of GENERATE `instruction_id` after `time` ticks,
+    /// or at its offset C, unless its limit D is zero
+    fn schedule_arrival(&mut self, instruction_id: usize, time: u64) -> Result<(), RuntimeError> {
+        let (offset, limit) = self
+            .generators
+            .get(&instruction_id)
+            .map_or((None, None), |generator| {
+                (generator.offset, generator.limit)
+            });
+        // Смещение C задает время первого прихода вместо интервала
+        let time = match offset {
+            Some(var_id) => self.time_to_ticks(&self.memory_get(var_id)?)?,
+            None => time,
+        };
+        if limit != Some(0) {
+            info!("Wake time for GENERATE {}", self.current_time + time);
+            self.create_event(instruction_id, self.current_time + time, None);
+        }
+        Ok(())
+    }
+
+    /// Schedules the first arrivals of all GENERATE blocks before simulation, so each of them
+    /// creates transacts, not only the first one. Program without GENERATE runs from its first instruction.
+    fn schedule_generators(&mut self) -> Result<(), RuntimeError> {
+        let generators: Vec<(usize, usize)> = self
+            .instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(instruction_id, instruction)| match *instruction {
+                Instructions::Generate(begin)
+                | Instructions::GenerateExp(begin)
+                | Instructions::GenerateUniform(begin) => Some((begin, instruction_id)),
+                _ => None,
+            })
+            .collect();
+        if generators.is_empty() {
+            return Ok(());
+        }
+        for (begin, instruction_id) in generators {
+            // Операнды GENERATE вычисляются без транзакта
+            self.process_from_to(begin, instruction_id)?;
+            let time = self.pop_generate_interval(instruction_id)?;
+            self.schedule_arrival(instruction_id, time)?;
+        }
+        self.wait_next_event()
+    }
+
+    fn generate(&mut self, time: u64) -> Result<(), RuntimeError> {
+        self.schedule_arrival(self.current_instruction, time)?;
+        // После того, как создали новое событие
+        // ищем и исполняем ближайшее
+        self.wait_next_event()
+    }
+
+    fn advance(&mut self, time: u64) -> Result<(), RuntimeError> {
+        // Нулевая задержка не создает событие, иначе транзакт встал бы за событиями этого же момента
+        if time == 0 {
+            info!("ADVANCE 0");
+            self.current_instruction += 1;
+            return Ok(());
+        }
+        info!("Wake time for ADVANCE {}", self.current_time + time);
+        self.create_event(
+            self.current_instruction,
+            self.current_time + time,
+            self.current_transact.clone(),
+        );
+        self.perform_closest()
+    }
+
+    fn terminate(&mut self, count: u32) -> Result<(), RuntimeError> {
+        info!("TERMINATE {}", count);
+        self.start_entities = self.start_entities.saturating_sub(count);
+        self.current_transact = None;
+        self.terminated += 1;
+        if !self.events.is_empty() && self.start_entities > 0 {
+            self.perform_closest()
+        } else {
+            info!("STOP");
+            self.current_instruction = self.instructions.len();
+            Ok(())
+        }
+    }
+
+    /// Address out of memory is an error, not a panic
+    fn print(&mut self, var_id: usize) -> Result<(), RuntimeError> {
+        println!("{}", self.memory_get(var_id)?);
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    fn print_param(&mut self, param_id: usize) -> Result<(), RuntimeError> {
+        println!("{}", self.param_mut(param_id)?);
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    fn print_clock(&mut self) {
+        println!("Clock {}", self.ticks_to_time(self.current_time));
+        self.current_instruction += 1;
+    }
+
+    fn transfer_fraction(&mut self, fraction: f64, target: usize, next: usize) {
+        let instruction_id = if self.rng.gen::<f64>() < fraction {
+            target
+        } else {
+            next
+        };
+        self.transfer(instruction_id);
+    }
+
+    /// Checks that block would block transact right now: busy facility, full storage or closed gate
+    fn refuses_entry(&self, instruction_id: usize) -> Result<bool, RuntimeError> {
+        match self.instructions.get(instruction_id) {
+            Some(&Instructions::Seize(fac_id)) => {
+                Ok(Self::is_facility_utilised(&self.memory_get(fac_id)?) == Some(true))
+            }
+            Some(&Instructions::Enter(storage_id, units)) => {
+                let storage = self
+                    .storages
+                    .get(storage_id)
+                    .ok_or(RuntimeError::BadStorage(storage_id))?;
+                Ok(!storage.waiters.is_empty() || storage.used + units > storage.capacity)
+            }
+            Some(&Instructions::GateWait(condition)) => Ok(!self.gate_holds(condition)?),
+            _ => Ok(false),
+        }
+    }
+
+    /// Transact, which is refused by both blocks, goes to the second one and waits there
+    fn transfer_both(&mut self, first: usize, second: usize) -> Result<(), RuntimeError> {
+        let instruction_id = if self.refuses_entry(first)? {
+            second
+        } else {
+            first
+        };
+        self.transfer(instruction_id);
+        Ok(())
+    }
+
+    fn transfer(&mut self, instruction_id: usize) {
+        info!(
+            "TRANSFER FROM {} TO {}",
+            self.current_instruction, instruction_id
+        );
+        self.current_instruction = instruction_id;
+    }
+
+    fn test_var(&mut self, else_goto: usize, cond_result: bool) {
+        info!("Condition is {}", cond_result);
+        if cond_result {
+            self.current_instruction += 1;
+        } else {
+            self.current_instruction = else_goto;
+        }
+    }
+
+    fn test(&mut self, relation: Relation, else_goto: usize) -> Result<(), RuntimeError> {
+        let right = self.stack_pop()?;
+        let left = self.stack_pop()?;
+        let ordering = left.compare(&right)?;
+        info!("TEST {:?} {:?} {:?}", left, relation, right);
+        self.test_var(else_goto, relation.holds(ordering));
+        Ok(())
+    }
+
+    fn save_value(
+        &mut self,
+        var_id: usize,
+        mode: SaveMode,
+        object: GpssType,
+    ) -> Result<(), RuntimeError> {
+        info!("Saving value {:?} to {} by {:?}", object, var_id, mode);
+        if self.memory.len() > var_id {
+            // Накопление следует правилам арифметики: целое с дробным дает дробное
+            self.memory[var_id] = match mode {
+                SaveMode::Set => object,
+                SaveMode::Inc => self.memory[var_id].arithmetic(Arithmetic::Add, &object)?,
+                SaveMode::Dec => self.memory[var_id].arithmetic(Arithmetic::Sub, &object)?,
+            };
+        } else if self.memory.len() == var_id && mode == SaveMode::Set {
+            self.memory.push(object);
+        } else {
+            return Err(RuntimeError::BadMemoryAccess(var_id));
+        }
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    fn arithmetic(&mut self, op: Arithmetic) -> Result<(), RuntimeError> {
+        let right = self.stack_pop()?;
+        let left = self.stack_pop()?;
+        let result = left.arithmetic(op, &right)?;
+        info!("{:?}: {:?} and {:?} is {:?}", op, left, right, result);
+        self.stack.push(result);
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    /// Operands of other types than Boolean are TypeMismatch, even if result does not depend on them
+    fn logical(&mut self, name: &str, op: fn(bool, bool) -> bool) -> Result<(), RuntimeError> {
+        let right = self.stack_pop()?.as_boolean()?;
+        let left = self.stack_pop()?.as_boolean()?;
+        let result = op(left, right);
+        info!("{}: {} and {} is {}", name, left, right, result);
+        self.stack.push(GpssType::Boolean(result));
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    fn not(&mut self) -> Result<(), RuntimeError> {
+        let value = self.stack_pop()?.as_boolean()?;
+        info!("NOT: {} is {}", value, !value);
+        self.stack.push(GpssType::Boolean(!value));
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    /// Booleans and other non-numeric objects are not converted: that is TypeMismatch
+    fn convert_to_float(&mut self) -> Result<(), RuntimeError> {
+        let object = self.stack_pop()?;
+        let value = match object {
+            GpssType::Float(_) => object,
+            _ => GpssType::Float(object.as_float()?),
+        };
+        info!("To float: {:?}", value);
+        self.stack.push(value);
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    fn convert_to_int(&mut self) -> Result<(), RuntimeError> {
+        let object = self.stack_pop()?;
+        let value = match object {
+            GpssType::Integer(_) | GpssType::UnsignedInteger(_) => object,
+            GpssType::Float(value) => {
+                // Сравнение ложно и для NaN
+                let truncated = value.trunc();
+                if !(truncated >= i32::MIN as f32 && truncated < i32::MAX as f32) {
+                    return Err(RuntimeError::Overflow);
+                }
+                GpssType::Integer(truncated as i32)
+            }
+            _ => {
+                return Err(RuntimeError::TypeMismatch {
+                    expected: "Integer",
+                    got: object.type_name(),
+                })
+            }
+        };
+        info!("To integer: {:?}", value);
+        self.stack.push(value);
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    fn push(&mut self, var_id: usize) -> Result<(), RuntimeError> {
+        let object = self.memory_get(var_id)?;
+        info!("Push: {:?}", object);
+        self.stack.push(object);
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    fn seize(&mut self, fac_id: usize) -> Result<(), RuntimeError> {
+        let facility = self.memory_get(fac_id)?;
+        match Self::is_facility_utilised(&facility) {
+            Some(true) => {
+                info!("SEIZE {} is busy, transact is blocked", fac_id);
+                // Транзакт ждет освобождения устройства, RELEASE передаст его
+                // и транзакт продолжит со следующего блока
+                let event = self.block_current();
+                self.facility_waiters
+                    .entry(fac_id)
+                    .or_default()
+                    .push_back(event);
+                self.wait_next_event()
+            }
+            Some(false) => {
+                info!("SEIZE {}", fac_id);
+                let count = u8::try_from(facility)?;
+                self.memory[fac_id] = GpssType::Facility(count + 1);
+                let owner = self.current_number();
+                self.ownership.entry(fac_id).or_default().owner = owner;
+                let time = self.current_time;
+                let stats = self.facility_stats_mut(fac_id);
+                stats.entries += 1;
+                stats.seized_since = Some(time);
+                self.wake_gates();
+                self.current_instruction += 1;
+                Ok(())
+            }
+            None => Err(RuntimeError::TypeMismatch {
+                expected: "Facility",
+                got: facility.type_name(),
+            }),
+        }
+    }
+
+    /// Number of current transact
+    fn current_number(&self) -> Option<u32> {
+        self.current_transact
+            .as_ref()
+            .map(|transact| transact.number)
+    }
+
+    fn preempt(&mut self, fac_id: usize) -> Result<(), RuntimeError> {
+        let facility = self.memory_get(fac_id)?;
+        match Self::is_facility_utilised(&facility) {
+            Some(true) => {}
+            // Свободное устройство занимается как при SEIZE
+            Some(false) => return self.seize(fac_id),
+            None => {
+                return Err(RuntimeError::TypeMismatch {
+                    expected: "Facility",
+                    got: facility.type_name(),
+                })
+            }
+        }
+        let time = self.current_time;
+        let number = self.current_number();
+        let instructions = &self.instructions;
+        let ownership = self.ownership.entry(fac_id).or_default();
+        let displaced = ownership.owner;
+        // Вытесненный транзакт в ADVANCE снимается со списка событий вместе с остатком времени
+        let mut events = mem::take(&mut self.events).into_vec();
+        let advance = events
+            .iter()
+            .position(|event| {
+                event.transact.as_ref().map(|transact| transact.number) == displaced
+                    && matches!(
+                        instructions[event.instruction_id],
+                        Instructions::Advance(_)
+                            | Instructions::AdvanceUniform(_)
+                            | Instructions::AdvanceNormal(..)
+                    )
+            })
+            .map(|index| {
+                let event = events.swap_remove(index);
+                let left = event.wake_time - time;
+                (event, left)
+            });
+        self.events = BinaryHeap::from(events);
+        info!(
+            "PREEMPT {}: transact {:?} is displaced, time left {:?}",
+            fac_id,
+            displaced,
+            advance.as_ref().map(|(_, left)| left)
+        );
+        ownership.preempted.push(Preempted {
+            number: displaced,
+            advance,
+        });
+        ownership.owner = number;
+        self.facility_stats_mut(fac_id).entries += 1;
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    /// RELEASE after SEIZE and RETURN after PREEMPT. Facility goes to the last displaced transact,
+    /// then to the first blocked one.
+    fn release(&mut self, fac_id: usize) -> Result<(), RuntimeError> {
+        info!("RELEASE {}", fac_id);
+        let count = u8::try_from(self.memory_get(fac_id)?)?;
+        if count == 0 {
+            return Err(RuntimeError::FacilityNotSeized(fac_id));
+        }
+        let number = self.current_number();
+        let time = self.current_time;
+        let ownership = self.ownership.entry(fac_id).or_default();
+        if ownership
+            .preempted
+            .iter()
+            .any(|preempted| number.is_some() && preempted.number == number)
+        {
+            return Err(RuntimeError::FacilityPreempted(fac_id));
+        }
+        if ownership.owner.is_some() && number.is_some() && ownership.owner != number {
+            return Err(RuntimeError::FacilityNotOwned(fac_id));
+        }
+        // Остаток ADVANCE отсчитывается от момента возврата устройства
+        if let Some(preempted) = ownership.preempted.pop() {
+            info!(
+                "Facility {} is returned to transact {:?}",
+                fac_id, preempted.number
+            );
+            ownership.owner = preempted.number;
+            if let Some((mut event, left)) = preempted.advance {
+                event.wake_time = time + left;
+                self.events.push(event);
+            }
+            self.current_instruction += 1;
+            return Ok(());
+        }
+        // Устройство сразу передается первому ожидающему транзакту,
+        // чтобы его не перехватил транзакт, пришедший в то же время
+        match self
+            .facility_waiters
+            .get_mut(&fac_id)
+            .and_then(|waiters| waiters.pop_front())
+        {
+            Some(mut event) => {
+                info!("Facility {} is passed to blocked transact", fac_id);
+                ownership.owner = event.transact.as_ref().map(|transact| transact.number);
+                event.wake_time = time;
+                self.events.push(event);
+                self.facility_stats_mut(fac_id).entries += 1;
+            }
+            None => {
+                ownership.owner = None;
+                self.memory[fac_id] = GpssType::Facility(count - 1);
+                self.facility_stats_mut(fac_id).free(time);
+                self.wake_gates();
+            }
+        }
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    /// Parameter of current transact
+    fn param_mut(&mut self, param_id: usize) -> Result<&mut GpssType, RuntimeError> {
+        let transact = self
+            .current_transact
+            .as_mut()
+            .ok_or(RuntimeError::NoTransact)?;
+        let number = transact.number;
+        transact
+            .params
+            .get_mut(param_id)
+            .ok_or(RuntimeError::BadParameter {
+                param_id,
+                transact: number,
+            })
+    }
+
+    fn assign(&mut self, param_id: usize, object: GpssType) -> Result<(), RuntimeError> {
+        info!("ASSIGN {:?} to parameter {}", object, param_id);
+        *self.param_mut(param_id)? = object;
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    /// Parameter, which was never assigned, counts from zero, like in GPSS
+    fn assign_op(
+        &mut self,
+        param_id: usize,
+        mode: SaveMode,
+        object: GpssType,
+    ) -> Result<(), RuntimeError> {
+        info!(
+            "ASSIGN {:?} to parameter {} by {:?}",
+            object, param_id, mode
+        );
+        let param = self.param_mut(param_id)?;
+        let current = match param {
+            GpssType::Boolean(false) => GpssType::Integer(0),
+            _ => param.clone(),
+        };
+        *param = match mode {
+            SaveMode::Set => object,
+            SaveMode::Inc => current.arithmetic(Arithmetic::Add, &object)?,
+            SaveMode::Dec => current.arithmetic(Arithmetic::Sub, &object)?,
+        };
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    /// Unassigned parameter still holds `GpssType::empty()`, reading it is an error
+    /// or zero, see `set_unassigned_params_zero`
+    fn load_param(&mut self, param_id: usize) -> Result<(), RuntimeError> {
+        let unassigned_zero = self.unassigned_params_zero;
+        let transact = self
+            .current_transact
+            .as_ref()
+            .map_or(0, |transact| transact.number);
+        let object = match self.param_mut(param_id)? {
+            GpssType::Boolean(false) if unassigned_zero => GpssType::Integer(0),
+            GpssType::Boolean(false) => {
+                return Err(RuntimeError::UnassignedParameter { param_id, transact })
+            }
+            param => param.clone(),
+        };
+        info!("Load parameter {}: {:?}", param_id, object);
+        self.stack.push(object);
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    fn random(&mut self) -> Result<(), RuntimeError> {
+        let value: f32 = self.rng.gen();
+        info!("Random number {}", value);
+        self.stack.push(GpssType::Float(value));
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    /// Counter of LOOP must be integer: Float parameter is an error.
+    /// Counter, which is already zero or negative, is not changed and transact goes to the next block.
+    fn loop_param(&mut self, param_id: usize, target: usize) -> Result<(), RuntimeError> {
+        let counter = self.param_mut(param_id)?;
+        let left = match counter {
+            GpssType::Integer(count) if *count <= 0 => false,
+            GpssType::UnsignedInteger(0) => false,
+            GpssType::Integer(count) => {
+                *count -= 1;
+                *count != 0
+            }
+            GpssType::UnsignedInteger(count) => {
+                *count -= 1;
+                *count != 0
+            }
+            other => {
+                return Err(RuntimeError::TypeMismatch {
+                    expected: "Integer",
+                    got: other.type_name(),
+                })
+            }
+        };
+        info!("LOOP on parameter {}: {:?}", param_id, counter);
+        if left {
+            self.current_instruction = target;
+        } else {
+            self.current_instruction += 1;
+        }
+        Ok(())
+    }
+
+    fn assemble(&mut self, count: usize) -> Result<(), RuntimeError> {
+        let transact = self
+            .current_transact
+            .as_ref()
+            .ok_or(RuntimeError::NoTransact)?;
+        let key = (self.current_instruction, transact.family);
+        let mark_time = transact.mark_time;
+        info!("ASSEMBLE {} of family {}", count, transact.family);
+        match self.assemblies.remove(&key) {
+            // Первый член семейства ждет остальных
+            None if count > 1 => {
+                let event = self.block_current();
+                self.assemblies.insert(key, (count - 1, event));
+                self.wait_next_event()
+            }
+            None => {
+                self.current_instruction += 1;
+                Ok(())
+            }
+            // Остальные члены уничтожаются, первый сохраняет самое раннее время создания
+            Some((remaining, mut event)) => {
+                self.current_transact = None;
+                if let Some(first) = event.transact.as_mut() {
+                    first.mark_time = first.mark_time.min(mark_time);
+                }
+                if remaining > 1 {
+                    self.assemblies.insert(key, (remaining - 1, event));
+                } else {
+                    info!("Family {} is assembled", key.1);
+                    event.wake_time = self.current_time;
+                    self.events.push(event);
+                }
+                self.wait_next_event()
+            }
+        }
+    }
+
+    fn gather(&mut self, count: usize) -> Result<(), RuntimeError> {
+        let family = self
+            .current_transact
+            .as_ref()
+            .ok_or(RuntimeError::NoTransact)?
+            .family;
+        let key = (self.current_instruction, family);
+        info!("GATHER {} of family {}", count, family);
+        let (remaining, mut events) = self.gatherings.remove(&key).unwrap_or((count, Vec::new()));
+        if remaining > 1 {
+            let event = self.block_current();
+            events.push(event);
+            self.gatherings.insert(key, (remaining - 1, events));
+            self.wait_next_event()
+        } else {
+            // Последний член семейства продолжает сразу, остальные - в порядке прихода
+            info!("Family {} is gathered", family);
+            for mut event in events {
+                event.wake_time = self.current_time;
+                self.events.push(event);
+            }
+            self.current_instruction += 1;
+            Ok(())
+        }
+    }
+
+    fn match_block(&mut self, conjugate: usize) -> Result<(), RuntimeError> {
+        let family = self
+            .current_transact
+            .as_ref()
+            .ok_or(RuntimeError::NoTransact)?
+            .family;
+        info!("MATCH with {} in family {}", conjugate, family);
+        let partner = self
+            .matchings
+            .get_mut(&(conjugate, family))
+            .and_then(VecDeque::pop_front);
+        match partner {
+            Some(mut event) => {
+                if self.matchings[&(conjugate, family)].is_empty() {
+                    self.matchings.remove(&(conjugate, family));
+                }
+                event.wake_time = self.current_time;
+                self.events.push(event);
+                self.current_instruction += 1;
+                Ok(())
+            }
+            None => {
+                let key = (self.current_instruction, family);
+                let event = self.block_current();
+                self.matchings.entry(key).or_default().push_back(event);
+                self.wait_next_event()
+            }
+        }
+    }
+
+    /// Names of ASSEMBLE, GATHER and MATCH blocks, where transacts wait for family members.
+    /// Block without label is named by its instruction.
+    fn waiting_family_blocks(&self) -> Vec<String> {
+        let mut blocks: Vec<usize> = self
+            .assemblies
+            .keys()
+            .chain(self.gatherings.keys())
+            .chain(self.matchings.keys())
+            .map(|&(instruction_id, _)| instruction_id)
+            .collect();
+        blocks.sort();
+        blocks.dedup();
+        blocks
+            .into_iter()
+            .map(|instruction_id| {
+                self.symbols
+                    .labels
+                    .iter()
+                    .find(|&(_, &id)| id == instruction_id)
+                    .map_or_else(
+                        || format!("instruction {}", instruction_id),
+                        |(name, _)| name.clone(),
+                    )
+            })
+            .collect()
+    }
+
+    fn split(&mut self, copies: usize, target: usize) -> Result<(), RuntimeError> {
+        info!("SPLIT {} copies to {}", copies, target);
+        let original = self
+            .current_transact
+            .clone()
+            .ok_or(RuntimeError::NoTransact)?;
+        // Номера копий отличаются от номера оригинала и друг от друга
+        let mut family_ids: Vec<i32> = i32::try_from(original.params[0].clone())
+            .into_iter()
+            .collect();
+        for _ in 0..copies {
+            let id = loop {
+                let id = self.rng.gen::<i32>();
+                if !family_ids.contains(&id) {
+                    break id;
+                }
+            };
+            family_ids.push(id);
+            let mut copy = original.clone();
+            copy.params[0] = GpssType::Integer(id);
+            self.next_number += 1;
+            copy.number = self.next_number;
+            self.create_event(self.current_instruction, self.current_time, Some(copy));
+        }
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    fn priority(&mut self, priority: usize) -> Result<(), RuntimeError> {
+        info!("PRIORITY {}", priority);
+        let priority = u8::try_from(priority).map_err(|_| RuntimeError::Overflow)?;
+        self.current_transact
+            .as_mut()
+            .ok_or(RuntimeError::NoTransact)?
+            .priority = priority;
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    /// Parameter gets clock in units of model time, like other times of model
+    fn mark(&mut self, param_id: Option<usize>) -> Result<(), RuntimeError> {
+        let time = self.current_time;
+        match param_id {
+            Some(param_id) => {
+                info!("MARK time {} to parameter {}", time, param_id);
+                *self.param_mut(param_id)? = self.time_value(time);
+            }
+            None => {
+                info!("MARK time {}", time);
+                self.current_transact
+                    .as_mut()
+                    .ok_or(RuntimeError::NoTransact)?
+                    .mark_time = time;
+            }
+        }
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    /// Ticks since mark time of current transact or since time in parameter `mark_param`
+    fn transit_ticks(&mut self, mark_param: Option<usize>) -> Result<u64, RuntimeError> {
+        let mark = match mark_param {
+            Some(param_id) => {
+                let mark = self.param_mut(param_id)?.clone();
+                self.time_to_ticks(&mark)?
+            }
+            None => {
+                self.current_transact
+                    .as_ref()
+                    .ok_or(RuntimeError::NoTransact)?
+                    .mark_time
+            }
+        };
+        self.current_time
+            .checked_sub(mark)
+            .ok_or(RuntimeError::Overflow)
+    }
+
+    fn push_transit(&mut self, mark_param: Option<usize>) -> Result<(), RuntimeError> {
+        let transit = self.transit_ticks(mark_param)?;
+        let value = self.time_value(transit);
+        info!("Transit time {:?}", value);
+        self.stack.push(value);
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    fn tabulate(&mut self, table_id: usize, weight: u32) -> Result<(), RuntimeError> {
+        // Таблицу очереди (QTABLE) заполняет только DEPART
+        let value = match self.tables.get(table_id).map(|table| table.argument) {
+            Some(TableArgument::Transit(mark_param)) => {
+                let transit = self.transit_ticks(mark_param)?;
+                self.ticks_to_time(transit)
+            }
+            Some(TableArgument::Parameter(param_id)) => self.param_mut(param_id)?.as_float()?,
+            _ => return Err(RuntimeError::BadTable(table_id)),
+        };
+        info!(
+            "TABULATE {} to table {} with weight {}",
+            value, table_id, weight
+        );
+        self.tables[table_id].record(value, weight);
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    fn call_function(&mut self, function_id: usize) -> Result<(), RuntimeError> {
+        let argument = self.stack_pop()?.as_float()?;
+        let value = self
+            .functions
+            .get(function_id)
+            .and_then(|function| function.evaluate(argument))
+            .ok_or(RuntimeError::BadFunction(function_id))?;
+        info!("FUNCTION {}({}) = {}", function_id, argument, value);
+        self.stack.push(GpssType::Float(value));
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    fn enter(&mut self, storage_id: usize, units: u32) -> Result<(), RuntimeError> {
+        let storage = self
+            .storages
+            .get_mut(storage_id)
+            .ok_or(RuntimeError::BadStorage(storage_id))?;
+        if units > storage.capacity {
+            return Err(RuntimeError::StorageTooSmall(storage_id));
+        }
+        // Транзакт входит, только если свободны все запрошенные единицы
+        // и никто не ждет раньше него
+        if storage.waiters.is_empty() && storage.used + units <= storage.capacity {
+            info!("ENTER {} units to storage {}", units, storage_id);
+            storage.take(units, self.current_time);
+            self.wake_gates();
+            self.current_instruction += 1;
+            Ok(())
+        } else {
+            info!("ENTER {} is full, transact is blocked", storage_id);
+            let event = self.block_current();
+            self.storages[storage_id].waiters.push_back((units, event));
+            self.wait_next_event()
+        }
+    }
+
+    fn leave(&mut self, storage_id: usize, units: u32) -> Result<(), RuntimeError> {
+        info!("LEAVE {} units from storage {}", units, storage_id);
+        let time = self.current_time;
+        let storage = self
+            .storages
+            .get_mut(storage_id)
+            .ok_or(RuntimeError::BadStorage(storage_id))?;
+        if units > storage.used {
+            return Err(RuntimeError::StorageNotEntered(storage_id));
+        }
+        storage.update_area(time);
+        storage.used -= units;
+        // Будим ожидающих по порядку, пока им хватает единиц
+        while let Some(&(wanted, _)) = storage.waiters.front() {
+            if storage.used + wanted > storage.capacity {
+                break;
+            }
+            let (_, mut event) = storage.waiters.pop_front().unwrap();
+            storage.take(wanted, time);
+            event.wake_time = time;
+            self.events.push(event);
+        }
+        self.wake_gates();
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    /// Checks condition of GATE on current state of entities
+    fn gate_holds(&self, condition: GateCondition) -> Result<bool, RuntimeError> {
+        let storage = |storage_id: usize| {
+            self.storages
+                .get(storage_id)
+                .ok_or(RuntimeError::BadStorage(storage_id))
+        };
+        match condition {
+            GateCondition::FacilityUsed(fac_id) | GateCondition::FacilityUnused(fac_id) => {
+                let facility = self.memory_get(fac_id)?;
+                let used =
+                    Self::is_facility_utilised(&facility).ok_or(RuntimeError::TypeMismatch {
+                        expected: "Facility",
+                        got: facility.type_name(),
+                    })?;
+                Ok(used == (condition == GateCondition::FacilityUsed(fac_id)))
+            }
+            GateCondition::StorageFull(storage_id) => {
+                storage(storage_id).map(|storage| storage.used == storage.capacity)
+            }
+            GateCondition::StorageNotFull(storage_id) => {
+                storage(storage_id).map(|storage| storage.used < storage.capacity)
+            }
+            GateCondition::StorageEmpty(storage_id) => {
+                storage(storage_id).map(|storage| storage.used == 0)
+            }
+            GateCondition::StorageNotEmpty(storage_id) => {
+                storage(storage_id).map(|storage| storage.used > 0)
+            }
+        }
+    }
+
+    fn count(
+        &mut self,
+        mode: CountMode,
+        range: RangeInclusive<usize>,
+        param_id: usize,
+    ) -> Result<(), RuntimeError> {
+        let count = match mode {
+            CountMode::FacilityUsed => range
+                .filter_map(|fac_id| self.memory.get(fac_id))
+                .filter(|facility| Self::is_facility_utilised(facility) == Some(true))
+                .count(),
+            CountMode::StorageNotEmpty => range
+                .filter_map(|storage_id| self.storages.get(storage_id))
+                .filter(|storage| storage.used > 0)
+                .count(),
+        };
+        info!("COUNT {:?}: {}", mode, count);
+        *self.param_mut(param_id)? = GpssType::Integer(count as i32);
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    fn gate(&mut self, condition: GateCondition, else_goto: usize) -> Result<(), RuntimeError> {
+        info!("GATE {:?}", condition);
+        if self.gate_holds(condition)? {
+            self.current_instruction += 1;
+        } else {
+            self.current_instruction = else_goto;
+        }
+        Ok(())
+    }
+
+    fn gate_wait(&mut self, condition: GateCondition) -> Result<(), RuntimeError> {
+        if self.gate_holds(condition)? {
+            info!("GATE {:?} is open", condition);
+            self.current_instruction += 1;
+            Ok(())
+        } else {
+            info!("GATE {:?} is closed, transact is blocked", condition);
+            let event = self.block_current();
+            self.gate_waiters.push((condition, event));
+            self.wait_next_event()
+        }
+    }
+
+    /// Wakes transacts on GATE, which conditions became true after change of facility or storage
+    fn wake_gates(&mut self) {
+        let time = self.current_time;
+        for (condition, mut event) in mem::take(&mut self.gate_waiters) {
+            // Условие уже проверялось при блокировке, поэтому ошибки здесь нет
+            if self.gate_holds(condition).unwrap_or(false) {
+                event.wake_time = time;
+                self.events.push(event);
+            } else {
+                self.gate_waiters.push((condition, event));
+            }
+        }
+    }
+
+    fn queue(&mut self, queue_id: usize, units: u32) -> Result<(), RuntimeError> {
+        info!("QUEUE {},{}", queue_id, units);
+        let time = self.current_time;
+        // Запоминаем время входа в очередь, повторный QUEUE его не меняет
+        self.current_transact
+            .as_mut()
+            .ok_or(RuntimeError::NoTransact)?
+            .queue_entries
+            .entry(queue_id)
+            .or_insert(time);
+        let since = self.reset_time;
+        let time_scale = self.time_scale;
+        let stats = self.queues.entry(queue_id).or_insert_with(|| QueueStats {
+            since,
+            last_change: since,
+            time_scale,
+            ..QueueStats::default()
+        });
+        stats.update_area(time);
+        stats.content += units;
+        stats.entries += units;
+        stats.max_content = stats.max_content.max(stats.content);
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    fn depart(&mut self, queue_id: usize, units: u32) -> Result<(), RuntimeError> {
+        info!("DEPART {},{}", queue_id, units);
+        let time = self.current_time;
+        // Транзакт, который не входил в очередь, уходит из нее без ожидания
+        let entry_time = self
+            .current_transact
+            .as_mut()
+            .ok_or(RuntimeError::NoTransact)?
+            .queue_entries
+            .remove(&queue_id)
+            .unwrap_or(time);
+        let stats = match self.queues.get_mut(&queue_id) {
+            Some(stats) if stats.content >= units => stats,
+            _ => return Err(RuntimeError::EmptyQueue(self.queue_name(queue_id))),
+        };
+        stats.update_area(time);
+        stats.content -= units;
+        stats.departures += units;
+        let residence = time - entry_time;
+        stats.total_time += residence * u64::from(units);
+        if residence == 0 {
+            stats.zero_entries += units;
+        }
+        let value = self.ticks_to_time(residence);
+        for table in &mut self.tables {
+            if table.argument == TableArgument::Queue(queue_id) {
+                table.record(value, 1);
+            }
+        }
+        self.current_instruction += 1;
+        Ok(())
+    }
+
+    /// Statistics of facility, created at first SEIZE with clock of the last RESET
+    fn facility_stats_mut(&mut self, fac_id: usize) -> &mut FacilityStats {
+        let since = self.reset_time;
+        self.facilities
+            .entry(fac_id)
+            .or_insert_with(|| FacilityStats {
+                since,
+                ..FacilityStats::default()
+            })
+    }
+
+    /// Statistics of queue by its name in model, like `LINE`. Available after first QUEUE to it.
+    pub fn queue_stats(&self, name: &str) -> Option<&QueueStats> {
+        self.symbols
+            .queues
+            .get(name)
+            .and_then(|queue_id| self.queues.get(queue_id))
+    }
+
+    /// Name of queue in model, or its id for program without names
+    fn queue_name(&self, queue_id: usize) -> String {
+        entity_name(&self.symbols.queues, queue_id)
+    }
+
+    /// Statistics of facility. Available after first SEIZE of it.
+    pub fn facility_stats(&self, fac_id: usize) -> Option<&FacilityStats> {
+        self.facilities.get(&fac_id)
+    }
+
+    /// Fraction of simulated time, when facility was busy. Zero for facility, which was never seized.
+    pub fn facility_utilization(&self, fac_id: usize) -> f64 {
+        self.facilities
+            .get(&fac_id)
+            .map_or(0.0, |stats| stats.utilization(self.current_time))
+    }
+
+    /// Takes current transact away to wait on blocking block.
+    /// Returned event resumes it at the next block.
+    fn block_current(&mut self) -> Event {
+        let transact = self.current_transact.take();
+        self.build_event(self.current_instruction, self.current_time, transact)
+    }
+
+    /// Switches to the closest event after current transact is blocked
+    fn wait_next_event(&mut self) -> Result<(), RuntimeError> {
+        if self.events.is_empty() {
+            info!("STOP, no events left");
+            self.current_instruction = self.instructions.len();
+            Ok(())
+        } else {
+            self.perform_closest()
+        }
+    }
+
+    /// Executes commands from start to end. Excluding end.
+    fn process_from_to(&mut self, start: usize, end: usize) -> Result<(), RuntimeError> {
+        self.current_instruction = start;
+        while self.current_instruction < end {
+            self.process_instruction()?;
+        }
+        Ok(())
+    }
+
+    /// Executes closest event
+    fn perform_closest(&mut self) -> Result<(), RuntimeError> {
+        // В этом блоке идет исполнение кода для откладываемых событий
+        // Исполняем ближайшее событие, если оно есть
+        if let Some(nearest_event) = self.events.pop() {
+            self.current_time = nearest_event.wake_time;
+            info!("Woke up at {}", self.current_time);
+            // Наблюдатель на время вызова вынимается, чтобы получить интерпретатор целиком
+            if let Some(mut observer) = self.event_observer.take() {
+                observer(&nearest_event, self);
+                self.event_observer = Some(observer);
+            }
+            self.current_transact = nearest_event.transact;
+
+            // Операнды GENERATE вычисляются заново для следующего интервала.
+            // ADVANCE уже снял свою задержку со стека, его операнды не повторяются.
+            match self.instructions[nearest_event.instruction_id] {
+                Instructions::Generate(begin)
+                | Instructions::GenerateExp(begin)
+                | Instructions::GenerateUniform(begin) => {
+                    self.process_from_to(begin, nearest_event.instruction_id)?;
+                }
+                _ => {}
+            };
+
+            match self.instructions[nearest_event.instruction_id] {
+                Instructions::Generate(_)
+                | Instructions::GenerateExp(_)
+                | Instructions::GenerateUniform(_) => {
+                    let time = self.pop_generate_interval(nearest_event.instruction_id)?;
+                    info!("DOING GENERATE");
+                    self.generated += 1;
+                    let mut new_transact = Transact::empty();
+                    self.next_family += 1;
+                    new_transact.family = self.next_family;
+                    self.next_number += 1;
+                    new_transact.number = self.next_number;
+                    new_transact.mark_time = self.current_time;
+                    new_transact.params[0] = GpssType::Integer(self.rng.gen::<i32>());
+                    self.current_transact = Some(new_transact);
+                    // после генерации текущего транзакта, надо запланировать генерацию следующего,
+                    // если GENERATE еще не создал предельное число транзактов
+                    let more = self
+                        .generators
+                        .get_mut(&nearest_event.instruction_id)
+                        .map_or(true, Generator::create);
+                    if more {
+                        self.create_event(
+                            nearest_event.instruction_id,
+                            self.current_time + time,
+                            None,
+                        );
+                    }
+                    self.current_instruction = nearest_event.instruction_id + 1;
+                }
+                Instructions::Advance(_)
+                | Instructions::AdvanceUniform(_)
+                | Instructions::AdvanceNormal(..) => {
+                    info!("DOING ADVANCE");
+                    self.current_instruction = nearest_event.instruction_id + 1;
+                }
+                // Копия транзакта начинает с адреса, указанного в SPLIT
+                Instructions::Split(target) => {
+                    self.current_instruction = target;
+                }
+                _ => {
+                    self.current_instruction = nearest_event.instruction_id + 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Event with the next sequence number
+    fn build_event(
+        &mut self,
+        instruction_id: usize,
+        wake_time: u64,
+        transact: Option<Transact>,
+    ) -> Event {
+        self.next_sequence += 1;
+        Event {
+            instruction_id,
+            wake_time,
+            transact,
+            sequence: self.next_sequence,
+        }
+    }
+
+    /// Schedules event in future
+    fn create_event(&mut self, instruction_id: usize, wake_time: u64, transact: Option<Transact>) {
+        let event = self.build_event(instruction_id, wake_time, transact);
+        self.events.push(event);
+    }
+
+    /// Executes current instruction
+    fn process_instruction(&mut self) -> Result<(), RuntimeError> {
+        match self.instructions[self.current_instruction] {
+            //Блоки, требущие подождать. Создаем для них событие в будущем
+            Instructions::Generate(_)
+            | Instructions::GenerateExp(_)
+            | Instructions::GenerateUniform(_) => {
+                let time = self.pop_generate_interval(self.current_instruction)?;
+                self.generate(time)
+            }
+            Instructions::Advance(_) => {
+                let time = self.stack_pop_time()?;
+                self.advance(time)
+            }
+            Instructions::AdvanceUniform(_) => {
+                let spread = self.stack_pop_time()?;
+                let mean = self.stack_pop_time()?;
+                let time = self.sample_uniform(mean, spread);
+                self.advance(time)
+            }
+            Instructions::AdvanceNormal(mean, std_dev) => {
+                let std_dev = self.time_to_ticks(&self.memory_get(std_dev)?)?;
+                let mean = self.time_to_ticks(&self.memory_get(mean)?)?;
+                let time = self.sample_normal(mean, std_dev);
+                self.advance(time)
+            }
+            //Блоки, не требующие подождать
             Instructions::Terminate(_) => {
-                let count = self.stack_pop().into();
-                self.terminate(count);
+                let count = u32::try_from(self.stack_pop()?)?;
+                self.terminate(count)
             }
             Instructions::Print(var_id) => self.print(var_id),
-            Instructions::PrintClock => self.print_clock(),
-            Instructions::Transfer(instruction_id) => self.transfer(instruction_id),
-            Instructions::TestVar(else_goto) => {
-                let cond_result = self.stack_pop().into();
-                self.test_var(else_goto, cond_result)
+            Instructions::PrintParam(param_id) => self.print_param(param_id),
+            Instructions::PrintClock => {
+                self.print_clock();
+                Ok(())
+            }
+            Instructions::Transfer(instruction_id) => {
+                self.transfer(instruction_id);
+                Ok(())
             }
+            Instructions::TransferFraction(fraction, target, next) => {
+                self.transfer_fraction(fraction, target, next);
+                Ok(())
+            }
+            Instructions::TransferBoth(first, second) => self.transfer_both(first, second),
+            Instructions::Test(relation, else_goto) => self.test(relation, else_goto),
             Instructions::SaveValue(var_id) => {
-                let object = self.stack_pop();
-                self.save_value(var_id, object);
+                let object = self.stack_pop()?;
+                self.save_value(var_id, SaveMode::Set, object)
+            }
+            Instructions::SaveValueOp(var_id, mode) => {
+                let object = self.stack_pop()?;
+                self.save_value(var_id, mode, object)
             }
             Instructions::Push(var_id) => self.push(var_id),
+            Instructions::SaveMatrix(matrix_id) => self.save_matrix(matrix_id),
+            Instructions::PushMatrix(matrix_id) => self.push_matrix(matrix_id),
+            Instructions::Seize(fac_id) => self.seize(fac_id),
+            Instructions::Release(fac_id) | Instructions::Return(fac_id) => self.release(fac_id),
+            Instructions::Preempt(fac_id) => self.preempt(fac_id),
+            Instructions::Assign(param_id) => {
+                let object = self.stack_pop()?;
+                self.assign(param_id, object)
+            }
+            Instructions::AssignOp(param_id, mode) => {
+                let object = self.stack_pop()?;
+                self.assign_op(param_id, mode, object)
+            }
+            Instructions::LoadParam(param_id) => self.load_param(param_id),
+            Instructions::Random => self.random(),
+            Instructions::Loop(param_id, target) => self.loop_param(param_id, target),
+            Instructions::CallFunction(function_id) => self.call_function(function_id),
+            Instructions::Enter(storage_id, units) => self.enter(storage_id, units),
+            Instructions::Leave(storage_id, units) => self.leave(storage_id, units),
+            Instructions::Queue(queue_id, units) => self.queue(queue_id, units),
+            Instructions::Depart(queue_id, units) => self.depart(queue_id, units),
+            Instructions::Add => self.arithmetic(Arithmetic::Add),
+            Instructions::Sub => self.arithmetic(Arithmetic::Sub),
+            Instructions::Mul => self.arithmetic(Arithmetic::Mul),
+            Instructions::Div => self.arithmetic(Arithmetic::Div),
+            Instructions::Mod => self.arithmetic(Arithmetic::Mod),
+            Instructions::And => self.logical("AND", |left, right| left && right),
+            Instructions::Or => self.logical("OR", |left, right| left || right),
+            Instructions::Not => self.not(),
+            Instructions::ToFloat => self.convert_to_float(),
+            Instructions::ToInt => self.convert_to_int(),
+            Instructions::Priority(priority) => self.priority(priority),
+            Instructions::Split(target) => {
+                let copies = self.stack_pop()?.as_integer()?;
+                let copies = usize::try_from(copies).map_err(|_| RuntimeError::Overflow)?;
+                self.split(copies, target)
+            }
+            Instructions::Assemble(count) => self.assemble(count),
+            Instructions::Gather(count) => self.gather(count),
+            Instructions::Match(conjugate) => self.match_block(conjugate),
+            Instructions::Mark(param_id) => self.mark(param_id),
+            Instructions::PushTransit(mark_param) => self.push_transit(mark_param),
+            Instructions::Tabulate(table_id, weight) => self.tabulate(table_id, weight),
+            Instructions::Count(mode, ref range, param_id) => {
+                let range = range.clone();
+                self.count(mode, range, param_id)
+            }
+            Instructions::Gate(condition, else_goto) => self.gate(condition, else_goto),
+            Instructions::GateWait(condition) => self.gate_wait(condition),
+            Instructions::GenerateLimits(..) => {
+                self.current_instruction += 1;
+                Ok(())
+            }
+        }
+    }
+
+    /// Executes exactly one instruction. Blocks, which wait, switch to the closest event.
+    pub fn step(&mut self) -> StepResult {
+        if !self.started {
+            self.started = true;
+            if let Err(err) = self.schedule_generators() {
+                return StepResult::Error(err);
+            }
+        }
+        if self.start_entities == 0 || self.current_instruction >= self.instructions.len() {
+            // Доводим статистику очередей до конца моделирования
+            let time = self.current_time;
+            for stats in self.queues.values_mut() {
+                stats.update_area(time);
+            }
+            // Остановка без исчерпания счетчика START значит, что событий больше нет
+            if self.start_entities > 0 {
+                let blocks = self.waiting_family_blocks();
+                if !blocks.is_empty() {
+                    return StepResult::Error(RuntimeError::Deadlock(blocks));
+                }
+                return StepResult::Exhausted(self.start_entities);
+            }
+            return StepResult::Halted;
+        }
+        if self.executed >= self.max_instructions {
+            return StepResult::Error(RuntimeError::StepLimitExceeded);
+        }
+        self.executed += 1;
+        match self.process_instruction() {
+            Ok(()) => StepResult::Running,
+            Err(err) => StepResult::Error(err),
+        }
+    }
+
+    /// Interpretation. Stops at first runtime error.
+    /// Running out of events before START count reaches zero is ModelExhausted error.
+    pub fn process(&mut self) -> Result<(), RuntimeError> {
+        loop {
+            match self.step() {
+                StepResult::Running => {}
+                StepResult::Halted => return Ok(()),
+                StepResult::Exhausted(remaining) => {
+                    return Err(RuntimeError::ModelExhausted(remaining))
+                }
+                StepResult::Error(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Sets termination count: the run stops, when TERMINATE blocks decrement it to zero
+    pub fn set_start_count(&mut self, count: u32) {
+        self.start_entities = count;
+    }
+
+    /// Executes RESET and CLEAR before the next START, then runs simulation until
+    /// termination count of the START is exhausted. Each run continues from the state,
+    /// where the previous one stopped. Returns whether standard report of the run is printed,
+    /// or None, when all STARTs are executed.
+    pub fn run_next_start(&mut self) -> Result<Option<bool>, RuntimeError> {
+        if self.initial_state.is_none() {
+            self.initial_state = Some(self.snapshot());
+        }
+        while let Some(control) = self.controls.pop_front() {
+            match control {
+                Control::Reset => self.reset(),
+                Control::Clear => self.clear(),
+                Control::Start(count, report) => {
+                    self.set_start_count(count);
+                    // Предыдущий запуск остановился на TERMINATE, продолжаем с ближайшего события
+                    if self.current_instruction >= self.instructions.len()
+                        && !self.events.is_empty()
+                    {
+                        self.perform_closest()?;
+                    }
+                    self.process()?;
+                    return Ok(Some(report));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// RESET: statistics are collected anew from current time. Transacts and contents of entities stay.
+    fn reset(&mut self) {
+        let time = self.current_time;
+        info!("RESET at {}", time);
+        self.reset_time = time;
+        for stats in self.queues.values_mut() {
+            *stats = QueueStats {
+                content: stats.content,
+                max_content: stats.content,
+                last_change: time,
+                since: time,
+                time_scale: stats.time_scale,
+                ..QueueStats::default()
+            };
+        }
+        for stats in self.facilities.values_mut() {
+            *stats = FacilityStats {
+                seized_since: stats.seized_since.map(|_| time),
+                since: time,
+                ..FacilityStats::default()
+            };
+        }
+        for storage in &mut self.storages {
+            storage.update_area(time);
+            storage.entries = 0;
+            storage.max_used = storage.used;
+            storage.used_area = 0;
+            storage.since = time;
+        }
+        for table in &mut self.tables {
+            table.clear();
+        }
+        self.generated = 0;
+        self.terminated = 0;
+    }
+
+    /// CLEAR: simulation starts again from state before the first START.
+    /// Random numbers are not repeated and executed instructions still count to the limit.
+    fn clear(&mut self) {
+        info!("CLEAR");
+        if let Some(mut state) = self.initial_state.clone() {
+            state.rng = self.rng.clone();
+            state.executed = self.executed;
+            // Следующие управляющие операторы выполняются после CLEAR как обычно
+            state.controls = self.controls.clone();
+            self.restore(state);
+        }
+    }
+
+    /// Sets count of clock ticks in unit of model time, 1000 by default.
+    /// Clock is an integer count of ticks, so times are rounded to `1 / time_scale`:
+    /// 1000000 keeps microseconds of time, but the clock overflows sooner,
+    /// 1 runs in integer time units, where integer times are exact and fractions are rounded.
+    /// Scheduled events are already counted in ticks, so the scale is fixed once simulation starts.
+    pub fn set_time_scale(&mut self, time_scale: u64) -> Result<(), RuntimeError> {
+        if self.started || self.initial_state.is_some() {
+            return Err(RuntimeError::TimeScaleAfterStart);
+        }
+        self.time_scale = time_scale.max(1);
+        Ok(())
+    }
+
+    /// Unassigned parameters of transacts read as 0, like in GPSS, instead of error.
+    /// Error is default, it catches mistyped parameter numbers.
+    pub fn set_unassigned_params_zero(&mut self, zero: bool) {
+        self.unassigned_params_zero = zero;
+    }
+
+    /// Sets limit of executed instructions. Long simulations may need more than default one.
+    pub fn set_max_instructions(&mut self, max_instructions: u64) {
+        self.max_instructions = max_instructions;
+    }
+
+    /// Saves state of simulation. Simulation, continued after `restore` of the state,
+    /// produces the same results as if it was never interrupted.
+    pub fn snapshot(&self) -> InterpreterState {
+        InterpreterState {
+            current_instruction: self.current_instruction,
+            current_transact: self.current_transact.clone(),
+            start_entities: self.start_entities,
+            current_time: self.current_time,
+            // Куча не сохраняет порядок при клонировании, сохраняем события в порядке исполнения
+            events: self.events.clone().into_sorted_vec(),
+            memory: self.memory.clone(),
+            stack: self.stack.clone(),
+            facility_waiters: self.facility_waiters.clone(),
+            ownership: self.ownership.clone(),
+            facilities: self.facilities.clone(),
+            queues: self.queues.clone(),
+            storages: self.storages.clone(),
+            matrices: self.matrices.clone(),
+            tables: self.tables.clone(),
+            rng: self.rng.clone(),
+            generated: self.generated,
+            terminated: self.terminated,
+            next_sequence: self.next_sequence,
+            next_family: self.next_family,
+            next_number: self.next_number,
+            assemblies: self.assemblies.clone(),
+            gatherings: self.gatherings.clone(),
+            matchings: self.matchings.clone(),
+            gate_waiters: self.gate_waiters.clone(),
+            generators: self.generators.clone(),
+            started: self.started,
+            executed: self.executed,
+            reset_time: self.reset_time,
+            controls: self.controls.clone(),
+        }
+    }
+
+    /// Returns simulation to saved state. State must be saved by interpreter of the same program.
+    pub fn restore(&mut self, state: InterpreterState) {
+        self.current_instruction = state.current_instruction;
+        self.current_transact = state.current_transact;
+        self.start_entities = state.start_entities;
+        self.current_time = state.current_time;
+        self.events = BinaryHeap::from(state.events);
+        self.memory = state.memory;
+        self.stack = state.stack;
+        self.facility_waiters = state.facility_waiters;
+        self.ownership = state.ownership;
+        self.facilities = state.facilities;
+        self.queues = state.queues;
+        self.storages = state.storages;
+        self.matrices = state.matrices;
+        self.tables = state.tables;
+        self.rng = state.rng;
+        self.generated = state.generated;
+        self.terminated = state.terminated;
+        self.next_sequence = state.next_sequence;
+        self.next_family = state.next_family;
+        self.next_number = state.next_number;
+        self.assemblies = state.assemblies;
+        self.gatherings = state.gatherings;
+        self.matchings = state.matchings;
+        self.gate_waiters = state.gate_waiters;
+        self.generators = state.generators;
+        self.started = state.started;
+        self.executed = state.executed;
+        self.reset_time = state.reset_time;
+        self.controls = state.controls;
+    }
+
+    /// Clock of simulation in ticks
+    pub fn current_time(&self) -> u64 {
+        self.current_time
+    }
+
+    /// Sets callback, which is called for each event, when it is executed: for arrivals of GENERATE
+    /// and transacts, which wake up. Clock of interpreter is already at time of the event.
+    /// The callback is not a part of saved state.
+    pub fn set_event_observer(&mut self, observer: EventObserver) {
+        self.event_observer = Some(observer);
+    }
+
+    /// Names of labels and entities of model with their ids
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    /// Index of instruction, which will be executed by the next step
+    pub fn current_instruction(&self) -> usize {
+        self.current_instruction
+    }
+
+    /// Future events as (instruction_id, wake_time) in order of execution. The heap is not changed.
+    pub fn pending_events(&self) -> impl Iterator<Item = (usize, u64)> {
+        // Отсортированный вектор идет от последнего события к ближайшему
+        self.events
+            .clone()
+            .into_sorted_vec()
+            .into_iter()
+            .rev()
+            .map(|event| (event.instruction_id, event.wake_time))
+    }
+
+    /// Memory cells: constants, savevalues and facilities by their addresses
+    pub fn memory_snapshot(&self) -> &[GpssType] {
+        &self.memory
+    }
+
+    /// Object on top of stack, like operand, which the next block takes
+    pub fn stack_top(&self) -> Option<GpssType> {
+        self.stack.last().cloned()
+    }
+
+    /// Final report of simulation: clock, transacts and statistics of facilities, queues and tables
+    pub fn build_report(&self) -> Report {
+        // HashMap не упорядочен, сортируем для воспроизводимого отчета
+        let mut facilities: Vec<_> = self
+            .facilities
+            .iter()
+            .map(|(&id, stats)| FacilityReport {
+                id,
+                name: entity_name(&self.symbols.facilities, id),
+                entries: stats.entries,
+                utilization: stats.utilization(self.current_time),
+            })
+            .collect();
+        facilities.sort_by_key(|facility| facility.id);
+        let mut queues: Vec<_> = self
+            .queues
+            .iter()
+            .map(|(&id, stats)| QueueReport {
+                id,
+                name: entity_name(&self.symbols.queues, id),
+                max_content: stats.max_content,
+                content: stats.content,
+                entries: stats.entries,
+                zero_entries: stats.zero_entries,
+                average_content: stats.average_content(),
+                average_time: stats.average_time(),
+            })
+            .collect();
+        queues.sort_by_key(|queue| queue.id);
+        let tables = self
+            .tables
+            .iter()
+            .enumerate()
+            .map(|(id, table)| {
+                let limits = (0..table.frequencies.len()).map(|class| {
+                    if class + 1 == table.frequencies.len() {
+                        f32::INFINITY
+                    } else {
+                        table.upper_limit + table.width * class as f32
+                    }
+                });
+                TableReport {
+                    id,
+                    name: entity_name(&self.symbols.tables, id),
+                    entries: table.entries,
+                    mean: table.mean(),
+                    std_dev: table.std_dev(),
+                    classes: limits.zip(table.frequencies.iter().copied()).collect(),
+                }
+            })
+            .collect();
+        let storages = self
+            .storages
+            .iter()
+            .enumerate()
+            .map(|(id, storage)| {
+                let average_content = storage.average_content(self.current_time);
+                StorageReport {
+                    id,
+                    name: entity_name(&self.symbols.storages, id),
+                    capacity: storage.capacity,
+                    content: storage.used,
+                    max_content: storage.max_used,
+                    entries: storage.entries,
+                    average_content,
+                    utilization: average_content / f64::from(storage.capacity),
+                }
+            })
+            .collect();
+        Report {
+            end_time: self.ticks_to_time(self.current_time),
+            generated: self.generated,
+            terminated: self.terminated,
+            facilities,
+            queues,
+            storages,
+            tables,
+        }
+    }
+
+    /// Final report of simulation as text
+    pub fn report(&self) -> String {
+        self.build_report().to_string()
+    }
+
+    /// Final report as JSON, see `Report::to_json`
+    pub fn report_json(&self) -> String {
+        self.build_report().to_json()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Runs all STARTs of model
+    fn run(source: &str) -> Interpreter {
+        let mut interpreter = Interpreter::from_source(source).expect("model must be valid");
+        while interpreter
+            .run_next_start()
+            .expect("model must run")
+            .is_some()
+        {}
+        interpreter
+    }
+
+    /// Runs all STARTs of model with seed of random numbers
+    fn run_seeded(source: &str, seed: u64) -> Interpreter {
+        let mut interpreter =
+            Interpreter::from_source_with_seed(source, seed).expect("model must be valid");
+        while interpreter
+            .run_next_start()
+            .expect("model must run")
+            .is_some()
+        {}
+        interpreter
+    }
+
+    /// Value of savevalue by its name
+    fn savevalue(interpreter: &Interpreter, name: &str) -> GpssType {
+        interpreter.memory_snapshot()[interpreter.symbols().savevalues[name]].clone()
+    }
+
+    #[test]
+    fn simultaneous_events_run_in_creation_order() {
+        let mut interpreter = Interpreter::build_interpreter(Vec::new(), Vec::new());
+        for instruction_id in &[2, 0, 1] {
+            interpreter.create_event(*instruction_id, 5, None);
+        }
+        let order: Vec<usize> = interpreter
+            .pending_events()
+            .map(|(instruction_id, _)| instruction_id)
+            .collect();
+        assert_eq!(order, vec![2, 0, 1]);
+        let popped: Vec<usize> = (0..3)
+            .map(|_| interpreter.events.pop().unwrap().instruction_id)
+            .collect();
+        assert_eq!(popped, vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn every_generate_creates_transacts() {
+        let interpreter = run("        GENERATE 2
+        SAVEVALUE FIRST+,1
+        TERMINATE 1
+        GENERATE 3
+        SAVEVALUE SECOND+,1
+        TERMINATE 1
+        START 10
+");
+        assert_eq!(savevalue(&interpreter, "FIRST"), GpssType::Integer(6));
+        assert_eq!(savevalue(&interpreter, "SECOND"), GpssType::Integer(4));
+        assert_eq!(interpreter.current_time(), 12 * DEFAULT_TIME_SCALE);
+    }
+
+    #[test]
+    fn generate_creates_no_more_than_limit() {
+        let interpreter = run("        GENERATE 6,3,,5
+        SAVEVALUE CREATED+,1
+        TERMINATE
+        GENERATE 100
+        TERMINATE 1
+        START 1
+");
+        // За 100 единиц времени без предела было бы не меньше 11 транзактов
+        assert_eq!(savevalue(&interpreter, "CREATED"), GpssType::Integer(5));
+        assert_eq!(interpreter.build_report().generated, 6);
+    }
+
+    #[test]
+    fn test_compares_transit_time_with_any_number() {
+        let interpreter = run("        GENERATE 1,,,1
+        ADVANCE 5
+        ADVANCE 5
+        SAVEVALUE TRANSIT,M1
+        TEST G M1,9.5,OUT
+        ADVANCE 0.25
+        TEST G M1,10,OUT
+        SAVEVALUE PASSED,1
+OUT     TERMINATE 1
+        START 1
+");
+        let transit = savevalue(&interpreter, "TRANSIT").as_float().unwrap();
+        assert!((transit - 10.0).abs() < 1e-6);
+        assert_eq!(savevalue(&interpreter, "PASSED"), GpssType::Integer(1));
+    }
+
+    #[test]
+    fn test_on_parameter_routes_classes_of_transacts() {
+        let interpreter = run("        GENERATE 10,,,3
+        ASSIGN 1,1
+        TRANSFER ,ROUTE
+        GENERATE 10,,,2
+        ASSIGN 1,2
+ROUTE   TEST E P1,1,OTHER
+        SAVEVALUE FIRST+,1
+        TERMINATE 1
+OTHER   SAVEVALUE SECOND+,1
+        TERMINATE 1
+        START 5
+");
+        assert_eq!(savevalue(&interpreter, "FIRST"), GpssType::Integer(3));
+        assert_eq!(savevalue(&interpreter, "SECOND"), GpssType::Integer(2));
+    }
+
+    const READS_UNASSIGNED_PARAMETER: &str = "        GENERATE 1,,,1
+        SAVEVALUE VALUE,P2
+        TERMINATE 1
+        START 1
+";
+
+    #[test]
+    fn unassigned_parameter_is_error_by_default() {
+        let mut interpreter =
+            Interpreter::from_source(READS_UNASSIGNED_PARAMETER).expect("model must be valid");
+        assert!(matches!(
+            interpreter.run_next_start(),
+            Err(RuntimeError::UnassignedParameter { param_id: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn unassigned_parameter_reads_as_zero_when_configured() {
+        let mut interpreter =
+            Interpreter::from_source(READS_UNASSIGNED_PARAMETER).expect("model must be valid");
+        interpreter.set_unassigned_params_zero(true);
+        while interpreter
+            .run_next_start()
+            .expect("model must run")
+            .is_some()
+        {}
+        assert_eq!(savevalue(&interpreter, "VALUE"), GpssType::Integer(0));
+    }
+
+    #[test]
+    fn mark_keeps_clock_beyond_u32_ticks() {
+        // 5000000 единиц времени при 1000 тиков в единице не помещаются в u32
+        let interpreter = run("        GENERATE 5000000,,,1
+        MARK 2
+        ADVANCE 1.5
+        SAVEVALUE MARKED,P2
+        SAVEVALUE TRANSIT,MP2
+        TERMINATE 1
+        START 1
+");
+        assert_eq!(
+            savevalue(&interpreter, "MARKED"),
+            GpssType::Integer(5_000_000)
+        );
+        assert_eq!(savevalue(&interpreter, "TRANSIT"), GpssType::Float(1.5));
+    }
+
+    #[test]
+    fn queues_keep_parameters_and_nest() {
+        let interpreter = run("        GENERATE 10,,,1
+        ASSIGN 15,7
+        QUEUE OUTER
+        ADVANCE 2
+        QUEUE INNER
+        ADVANCE 3
+        DEPART OUTER
+        DEPART INNER
+        SAVEVALUE KEPT,P15
+        TERMINATE 1
+        START 1
+");
+        assert_eq!(savevalue(&interpreter, "KEPT"), GpssType::Integer(7));
+        let outer = interpreter.queue_stats("OUTER").unwrap();
+        let inner = interpreter.queue_stats("INNER").unwrap();
+        assert_eq!(outer.average_time(), 5.0);
+        assert_eq!(inner.average_time(), 3.0);
+    }
+
+    #[test]
+    fn queue_grows_when_arrivals_outpace_service() {
+        let interpreter = run("        GENERATE 2
+        QUEUE LINE
+        SEIZE BARBER
+        DEPART LINE
+        ADVANCE 5
+        RELEASE BARBER
+        TERMINATE 1
+        START 20
+");
+        let line = interpreter.queue_stats("LINE").unwrap();
+        assert!(line.max_content() > 1);
+        assert!(line.average_content() > 0.0);
+        assert!(line.max_content() as f32 > line.average_content());
+        assert_eq!(line.content(), line.max_content());
+        let report = interpreter.build_report();
+        assert_eq!(report.queues[0].max_content, line.max_content());
+    }
+
+    #[test]
+    fn loop_repeats_advance_by_parameter() {
+        let interpreter = run("        GENERATE 100,,,2
+        ASSIGN 1,5
+BACK    ADVANCE 3
+        SAVEVALUE TURNS+,1
+        LOOP 1,BACK
+        SAVEVALUE LEFT,P1
+        TERMINATE 1
+        START 2
+");
+        // Каждый из двух транзактов проходит петлю ровно 5 раз
+        assert_eq!(savevalue(&interpreter, "TURNS"), GpssType::Integer(10));
+        assert_eq!(savevalue(&interpreter, "LEFT"), GpssType::Integer(0));
+        assert_eq!(interpreter.build_report().end_time, 215.0);
+    }
+
+    #[test]
+    fn loop_accumulates_parameter_for_table() {
+        let interpreter = run("TURNS   TABLE P1,2,2,4
+        GENERATE 10,,,3
+        ASSIGN 2,5
+BACK    ASSIGN 1+,1
+        LOOP 2,BACK
+        TABULATE TURNS
+        TERMINATE 1
+        START 3
+");
+        let report = interpreter.build_report();
+        let table = &report.tables[0];
+        assert_eq!(table.entries, 3);
+        // Все транзакты проходят петлю 5 раз и попадают в класс (4, 6]
+        let frequencies: Vec<u32> = table
+            .classes
+            .iter()
+            .map(|&(_, frequency)| frequency)
+            .collect();
+        assert_eq!(frequencies, vec![0, 0, 3, 0]);
+        assert_eq!(table.mean, 5.0);
+    }
+
+    #[test]
+    fn storage_of_three_servers_is_never_overfilled() {
+        let mut interpreter = Interpreter::from_source_with_seed(
+            "SERVERS STORAGE 3
+        GENERATE EXPONENTIAL 2
+        ENTER SERVERS
+        ADVANCE 5,2
+        LEAVE SERVERS
+        TERMINATE 1
+        START 5000
+",
+            11,
+        )
+        .unwrap();
+        interpreter.set_start_count(5000);
+        loop {
+            match interpreter.step() {
+                StepResult::Running => {}
+                StepResult::Halted => break,
+                other => panic!("model stopped with {:?}", other),
+            }
+            assert!(interpreter.storages[0].used <= 3);
+        }
+        let report = interpreter.build_report();
+        let storage = &report.storages[0];
+        assert_eq!(storage.max_content, 3);
+        // Каждую единицу времени приходит 1/2 транзакта и занимает 5 единиц из 3: 0.5 * 5 / 3
+        assert!(
+            (storage.utilization - 5.0 / 6.0).abs() < 0.03,
+            "{}",
+            storage.utilization
+        );
+    }
+
+    #[test]
+    fn blocked_seizes_get_facility_in_arrival_order_at_release() {
+        let mut interpreter = Interpreter::from_source(
+            "        GENERATE 1,,,3
+        SEIZE MACHINE
+        ADVANCE 10
+        RELEASE MACHINE
+        TERMINATE 1
+        START 3
+",
+        )
+        .unwrap();
+        let fac_id = interpreter.symbols().facilities["MACHINE"];
+        interpreter.set_start_count(3);
+        let mut owners = Vec::new();
+        loop {
+            match interpreter.step() {
+                StepResult::Running => {}
+                StepResult::Halted => break,
+                other => panic!("model stopped with {:?}", other),
+            }
+            assert!(matches!(
+                interpreter.memory[fac_id],
+                GpssType::Facility(0) | GpssType::Facility(1)
+            ));
+            let owner = interpreter
+                .ownership
+                .get(&fac_id)
+                .and_then(|ownership| ownership.owner);
+            if owner.is_some() && owners.last().map(|&(_, last)| last) != Some(owner) {
+                owners.push((interpreter.current_time(), owner));
+            }
+        }
+        // Ожидающий транзакт получает устройство в момент RELEASE, часы не идут дальше
+        assert_eq!(
+            owners,
+            vec![
+                (DEFAULT_TIME_SCALE, Some(1)),
+                (11 * DEFAULT_TIME_SCALE, Some(2)),
+                (21 * DEFAULT_TIME_SCALE, Some(3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn release_by_other_transact_is_error() {
+        let error = run_error(
+            "        GENERATE 1,,,1
+        SEIZE MACHINE
+        ADVANCE 10
+        RELEASE MACHINE
+        TERMINATE 1
+        GENERATE 2,,,1
+        RELEASE MACHINE
+        TERMINATE 1
+        START 2
+",
+        );
+        assert!(
+            matches!(error, RuntimeError::FacilityNotOwned(_)),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn report_shows_names_of_entities() {
+        let interpreter = run("CHAIRS  STORAGE 2
+        GENERATE 4
+        QUEUE LINE
+        ENTER CHAIRS
+        SEIZE BARBER
+        DEPART LINE
+        ADVANCE 3
+        RELEASE BARBER
+        LEAVE CHAIRS
+        TERMINATE 1
+        START 5
+");
+        let report = interpreter.build_report();
+        assert_eq!(report.facilities[0].name, "BARBER");
+        assert_eq!(report.queues[0].name, "LINE");
+        assert_eq!(report.storages[0].name, "CHAIRS");
+        let text = report.to_string();
+        assert!(text.contains("\nBARBER "), "{}", text);
+        assert!(text.contains("\nLINE "), "{}", text);
+        assert!(text.contains("\nCHAIRS "), "{}", text);
+        let json = report.to_json();
+        assert!(json.contains("\"name\":\"BARBER\""), "{}", json);
+        assert!(json.contains("\"name\":\"LINE\""), "{}", json);
+        assert!(json.contains("\"name\":\"CHAIRS\""), "{}", json);
+    }
+
+    #[test]
+    fn advance_leaves_stack_empty() {
+        for source in &[
+            "        GENERATE 10,,,1\n        ADVANCE 7\n        TERMINATE 1\n        START 1\n",
+            "        GENERATE 10,,,1\n        ADVANCE 10,4\n        TERMINATE 1\n        START 1\n",
+        ] {
+            let interpreter = run(source);
+            assert!(interpreter.stack_top().is_none(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn restored_snapshot_runs_remaining_starts_again() {
+        let mut interpreter = Interpreter::from_source_with_seed(
+            "        GENERATE EXPONENTIAL 5
+        QUEUE LINE
+        SEIZE BARBER
+        DEPART LINE
+        ADVANCE 4,3
+        RELEASE BARBER
+        TERMINATE 1
+        START 10,NP
+        RESET
+        START 20
+",
+            7,
+        )
+        .expect("model must be valid");
+        assert_eq!(interpreter.run_next_start().unwrap(), Some(false));
+        let state = interpreter.snapshot();
+        while interpreter.run_next_start().unwrap().is_some() {}
+        let first = interpreter.build_report();
+        interpreter.restore(state);
+        while interpreter.run_next_start().unwrap().is_some() {}
+        let second = interpreter.build_report();
+        assert_eq!(first.terminated, 20);
+        assert_eq!(first.to_string(), second.to_string());
+        assert_eq!(first.to_json(), second.to_json());
+    }
+
+    #[test]
+    fn preempted_transact_finishes_after_interrupt() {
+        let interpreter = run("        GENERATE 1,,,1
+        SEIZE MACHINE
+        ADVANCE 10
+        RELEASE MACHINE
+        SAVEVALUE JOB,M1
+        TERMINATE 1
+        GENERATE 4,,,1
+        PREEMPT MACHINE
+        ADVANCE 3
+        RETURN MACHINE
+        SAVEVALUE INTERRUPT,M1
+        TERMINATE
+        START 1
+");
+        // Работа должна была закончиться через 10, прерывание заняло устройство еще на 3
+        assert_eq!(savevalue(&interpreter, "JOB").as_float().unwrap(), 13.0);
+        assert_eq!(
+            savevalue(&interpreter, "INTERRUPT").as_float().unwrap(),
+            3.0
+        );
+        let report = interpreter.build_report();
+        assert_eq!(report.end_time, 14.0);
+        assert_eq!(report.facilities[0].entries, 2);
+    }
+
+    /// Error of the first START of model
+    fn run_error(source: &str) -> RuntimeError {
+        let mut interpreter = Interpreter::from_source(source).expect("model must be valid");
+        interpreter.run_next_start().expect_err("model must fail")
+    }
+
+    #[test]
+    fn release_by_preempted_transact_is_error() {
+        let error = run_error(
+            "        GENERATE 1,,,1
+        SEIZE OTHER
+        ADVANCE 10
+        RELEASE OTHER
+        TERMINATE 1
+        GENERATE 2,,,1
+        SEIZE MACHINE
+        SEIZE OTHER
+        RELEASE MACHINE
+        TERMINATE 1
+        GENERATE 3,,,1
+        PREEMPT MACHINE
+        ADVANCE 20
+        RETURN MACHINE
+        TERMINATE
+        START 2
+",
+        );
+        assert!(
+            matches!(error, RuntimeError::FacilityPreempted(_)),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn return_by_other_transact_is_error() {
+        let error = run_error(
+            "        GENERATE 1,,,1
+        PREEMPT MACHINE
+        ADVANCE 10
+        RETURN MACHINE
+        TERMINATE 1
+        GENERATE 2,,,1
+        RETURN MACHINE
+        TERMINATE 1
+        START 2
+",
+        );
+        assert!(
+            matches!(error, RuntimeError::FacilityNotOwned(_)),
+            "{}",
+            error
+        );
+    }
+
+    /// Result of validation of program, which is built by hand
+    fn validate_program(
+        instructions: Vec<Instructions>,
+        memory: Vec<GpssType>,
+    ) -> Result<(), ValidationError> {
+        let program = Program {
+            instructions,
+            memory,
+            symbols: Symbols::default(),
+            storages: Vec::new(),
+            tables: Vec::new(),
+            functions: Vec::new(),
+            matrices: Vec::new(),
+            controls: Vec::new(),
         };
+        Interpreter::from_program(program, None).map(|_| ())
+    }
+
+    #[test]
+    fn program_with_pointers_inside_is_valid() {
+        let instructions = vec![
+            Instructions::Push(0),
+            Instructions::Generate(0),
+            Instructions::Transfer(3),
+            Instructions::Push(0),
+            Instructions::Terminate(3),
+        ];
+        assert_eq!(
+            validate_program(instructions, vec![GpssType::Integer(1)]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn transfer_out_of_program_is_invalid() {
+        let instructions = vec![
+            Instructions::Push(0),
+            Instructions::Generate(0),
+            Instructions::Transfer(5),
+        ];
+        assert_eq!(
+            validate_program(instructions, vec![GpssType::Integer(1)]),
+            Err(ValidationError::BadPointer {
+                instruction: 2,
+                pointer: 5,
+            })
+        );
+    }
+
+    #[test]
+    fn push_out_of_memory_is_invalid() {
+        let instructions = vec![Instructions::Push(1), Instructions::Generate(0)];
+        assert_eq!(
+            validate_program(instructions, vec![GpssType::Integer(1)]),
+            Err(ValidationError::BadMemory {
+                instruction: 0,
+                address: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn generate_operands_after_block_are_invalid() {
+        // Операнды GENERATE должны вычисляться до блока, а не после него
+        let instructions = vec![
+            Instructions::Push(0),
+            Instructions::Generate(2),
+            Instructions::Push(0),
+            Instructions::Terminate(2),
+        ];
+        assert_eq!(
+            validate_program(instructions, vec![GpssType::Integer(1)]),
+            Err(ValidationError::BadPointer {
+                instruction: 1,
+                pointer: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn time_scale_of_one_runs_in_integer_time() {
+        let mut interpreter = Interpreter::from_source(
+            "        GENERATE 7,,,3
+        QUEUE LINE
+        SEIZE MACHINE
+        DEPART LINE
+        ADVANCE 5
+        RELEASE MACHINE
+        SAVEVALUE TRANSIT,M1
+        TERMINATE 1
+        START 3
+",
+        )
+        .unwrap();
+        interpreter.set_time_scale(1).unwrap();
+        while interpreter.run_next_start().unwrap().is_some() {}
+        // Тик равен единице времени модели, время нигде не округляется
+        assert_eq!(interpreter.current_time(), 26);
+        assert_eq!(savevalue(&interpreter, "TRANSIT"), GpssType::Integer(5));
+        assert_eq!(interpreter.queue_stats("LINE").unwrap().average_time(), 0.0);
+        assert_eq!(interpreter.build_report().end_time, 26.0);
+    }
+
+    #[test]
+    fn time_scale_is_fixed_after_start() {
+        let mut interpreter = Interpreter::from_source(
+            "        GENERATE 7,,,3
+        TERMINATE 1
+        START 1
+        START 1
+",
+        )
+        .unwrap();
+        assert_eq!(interpreter.run_next_start().unwrap(), Some(true));
+        assert!(matches!(
+            interpreter.set_time_scale(1),
+            Err(RuntimeError::TimeScaleAfterStart)
+        ));
+        assert_eq!(interpreter.run_next_start().unwrap(), Some(true));
+        assert_eq!(interpreter.current_time(), 14 * DEFAULT_TIME_SCALE);
+    }
+
+    #[test]
+    fn values_of_different_types_are_not_equal() {
+        assert_ne!(GpssType::Integer(1), GpssType::Float(1.0));
+        assert_eq!(
+            GpssType::Integer(1).partial_cmp(&GpssType::Float(1.0)),
+            None
+        );
+        assert_eq!(GpssType::Float(1.0), GpssType::Float(1.0));
+    }
+
+    /// Pushes operands from memory, then executes logical instruction on them
+    fn logical(
+        instruction: Instructions,
+        operands: Vec<GpssType>,
+    ) -> Result<GpssType, RuntimeError> {
+        let mut instructions: Vec<Instructions> =
+            (0..operands.len()).map(Instructions::Push).collect();
+        instructions.push(instruction);
+        let count = instructions.len();
+        let mut interpreter = Interpreter::build_interpreter(instructions, operands);
+        for _ in 0..count {
+            interpreter.process_instruction()?;
+        }
+        interpreter.stack_pop()
+    }
+
+    #[test]
+    fn logical_instructions_follow_truth_tables() {
+        for &(left, right) in &[(false, false), (false, true), (true, false), (true, true)] {
+            let operands = vec![GpssType::Boolean(left), GpssType::Boolean(right)];
+            let and = logical(Instructions::And, operands.clone()).unwrap();
+            let or = logical(Instructions::Or, operands).unwrap();
+            assert_eq!(and, GpssType::Boolean(left && right));
+            assert_eq!(or, GpssType::Boolean(left || right));
+        }
+        for &value in &[false, true] {
+            let not = logical(Instructions::Not, vec![GpssType::Boolean(value)]).unwrap();
+            assert_eq!(not, GpssType::Boolean(!value));
+        }
+    }
+
+    #[test]
+    fn logical_instructions_reject_non_booleans() {
+        // Результат AND с false и OR с true не зависит от второго операнда, но он все равно проверяется
+        let cases = vec![
+            (
+                Instructions::And,
+                vec![GpssType::Boolean(false), GpssType::Integer(1)],
+            ),
+            (
+                Instructions::And,
+                vec![GpssType::Integer(1), GpssType::Boolean(false)],
+            ),
+            (
+                Instructions::Or,
+                vec![GpssType::Boolean(true), GpssType::Float(1.0)],
+            ),
+            (
+                Instructions::Or,
+                vec![GpssType::Float(1.0), GpssType::Boolean(true)],
+            ),
+            (Instructions::Not, vec![GpssType::Integer(0)]),
+        ];
+        for (instruction, operands) in cases {
+            assert!(matches!(
+                logical(instruction, operands),
+                Err(RuntimeError::TypeMismatch {
+                    expected: "Boolean",
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn tabulate_fills_classes_with_weight() {
+        let interpreter = run_seeded(
+            "TRANSIT TABLE M1,5,5,5
+        GENERATE 3
+        ADVANCE 12,10
+        TABULATE TRANSIT,2
+        TERMINATE 1
+        START 200
+",
+            5,
+        );
+        let report = interpreter.build_report();
+        let table = &report.tables[0];
+        let frequencies: Vec<u32> = table
+            .classes
+            .iter()
+            .map(|&(_, frequency)| frequency)
+            .collect();
+        // Первый класс собирает все значения не больше 5, последний все значения больше 20
+        assert_eq!(frequencies, vec![62, 84, 114, 92, 48]);
+        assert_eq!(table.entries, 400);
+        assert_eq!(frequencies.iter().sum::<u32>(), table.entries);
+        assert!(frequencies.iter().all(|&frequency| frequency % 2 == 0));
+    }
+
+    #[test]
+    fn report_json_keeps_names_and_writes_infinity_as_null() {
+        let interpreter = run("LIM     TABLE M1,1,1,3
+        GENERATE 4
+        SEIZE BARBER
+        ADVANCE 3
+        RELEASE BARBER
+        TABULATE LIM
+        TERMINATE 1
+        START 5
+");
+        let json: serde_json::Value =
+            serde_json::from_str(&interpreter.report_json()).expect("report must be JSON");
+        assert_eq!(json["generated"], 5);
+        assert_eq!(json["facilities"][0]["name"], "BARBER");
+        let classes = json["tables"][0]["classes"].as_array().unwrap();
+        assert_eq!(classes.len(), 3);
+        assert!(classes[2]["upper_limit"].is_null());
+        assert_eq!(classes[2]["frequency"], 5);
+    }
+
+    #[test]
+    fn normal_draws_have_requested_mean_and_variance() {
+        let mut interpreter = Interpreter::build_interpreter(Vec::new(), Vec::new());
+        let count = 100_000;
+        let draws: Vec<f64> = (0..count)
+            .map(|_| interpreter.sample_normal(10_000, 2_000) as f64)
+            .collect();
+        let mean = draws.iter().sum::<f64>() / count as f64;
+        let variance = draws.iter().map(|draw| (draw - mean).powi(2)).sum::<f64>() / count as f64;
+        assert!((mean - 10_000.0).abs() < 50.0, "mean {}", mean);
+        assert!(
+            (variance / 4_000_000.0 - 1.0).abs() < 0.05,
+            "variance {}",
+            variance
+        );
+        // Отрицательные значения обрезаются до нуля
+        assert!((0..1000).all(|_| interpreter.sample_normal(0, 1_000) < 10_000));
+    }
+
+    #[test]
+    fn advance_normal_reads_operands_from_memory() {
+        let interpreter = run("        INITIAL X$MEAN,4
+        GENERATE 1,,,1
+        ADVANCE NORMAL X$MEAN,0
+        SAVEVALUE TRANSIT,M1
+        TERMINATE 1
+        START 1
+");
+        assert_eq!(savevalue(&interpreter, "TRANSIT"), GpssType::Integer(4));
+    }
+
+    #[test]
+    fn count_finds_busy_facilities_and_used_storages() {
+        let interpreter = run("PARK    STORAGE 2
+LOT     STORAGE 1
+        GENERATE 1,,,1
+        SEIZE 1
+        SEIZE 3
+        ENTER LOT
+        COUNT U 5,1,3
+        COUNT SNE 6,PARK,LOT
+        SAVEVALUE FACILITIES,P5
+        SAVEVALUE STORAGES,P6
+        TERMINATE 1
+        START 1
+");
+        assert_eq!(savevalue(&interpreter, "FACILITIES"), GpssType::Integer(2));
+        assert_eq!(savevalue(&interpreter, "STORAGES"), GpssType::Integer(1));
+    }
+
+    #[test]
+    fn matrix_cells_are_saved_and_read() {
+        let interpreter = run("COST    MATRIX ,2,3
+        GENERATE 1,,,1
+        ASSIGN 1,2
+        MSAVEVALUE COST,P1,3,7
+        MSAVEVALUE COST,1,1,MX$COST(P1,3)
+        SAVEVALUE CELL,MX$COST(1,1)
+        TERMINATE 1
+        START 1
+");
+        assert_eq!(savevalue(&interpreter, "CELL"), GpssType::Integer(7));
+        let mut outside = Interpreter::from_source(
+            "COST    MATRIX ,2,3
+        GENERATE 1,,,1
+        MSAVEVALUE COST,3,1,7
+        TERMINATE 1
+        START 1
+",
+        )
+        .unwrap();
+        assert!(matches!(
+            outside.run_next_start(),
+            Err(RuntimeError::BadMemoryAccess(0))
+        ));
+    }
+
+    #[test]
+    fn step_shows_operand_on_top_of_stack() {
+        let mut interpreter = Interpreter::from_source(
+            "        GENERATE 1,,,1
+        SAVEVALUE VALUE,5
+        TERMINATE 1
+        START 1
+",
+        )
+        .unwrap();
+        interpreter.set_start_count(1);
+        let mut steps = 0;
+        while !matches!(interpreter.stack_top(), Some(GpssType::Integer(5))) {
+            assert!(matches!(interpreter.step(), StepResult::Running));
+            steps += 1;
+            assert!(steps < 10, "operand of SAVEVALUE is never pushed");
+        }
+        assert!(matches!(interpreter.step(), StepResult::Running));
+        assert!(interpreter.stack_top().is_none());
+        assert_eq!(savevalue(&interpreter, "VALUE"), GpssType::Integer(5));
+    }
+
+    #[test]
+    fn assign_converts_value_by_type_of_parameter() {
+        let interpreter = run("        GENERATE 1,,,1
+        ASSIGN 1,7,PL
+        ASSIGN 2,-2.75,PF
+        ASSIGN 3,2.75
+        SAVEVALUE LONG,P1
+        SAVEVALUE FULL,P2
+        SAVEVALUE KEPT,P3
+        TERMINATE 1
+        START 1
+");
+        assert_eq!(savevalue(&interpreter, "LONG"), GpssType::Float(7.0));
+        assert_eq!(savevalue(&interpreter, "FULL"), GpssType::Integer(-2));
+        assert_eq!(savevalue(&interpreter, "KEPT"), GpssType::Float(2.75));
     }
 
-    /// Interpretation
-    pub fn process(&mut self) {
-        while self.start_entities > 0 && self.current_instruction < self.instructions.len() {
-            self.process_instruction();
+    #[test]
+    fn split_copies_go_to_label_with_distinct_numbers() {
+        let mut interpreter = Interpreter::from_source(
+            "        GENERATE 1,,,1
+        SPLIT 3,COPY
+        SAVEVALUE ORIGINAL,P0
+        TERMINATE 1
+COPY    ADVANCE 1
+        SAVEVALUE COPIES+,1
+        TERMINATE 1
+        START 4
+",
+        )
+        .unwrap();
+        let copies = Rc::new(RefCell::new(HashMap::new()));
+        let observed = Rc::clone(&copies);
+        // Оригинал проходит без событий, события с транзактами есть только у копий
+        interpreter.set_event_observer(Box::new(move |event, _| {
+            if let Some(number) = event.transact_number() {
+                let family = event.transact_params()[0].clone();
+                observed.borrow_mut().insert(number, family);
+            }
+        }));
+        while interpreter.run_next_start().unwrap().is_some() {}
+        let report = interpreter.build_report();
+        assert_eq!(report.generated, 1);
+        assert_eq!(report.terminated, 4);
+        assert_eq!(savevalue(&interpreter, "COPIES"), GpssType::Integer(3));
+        let original = savevalue(&interpreter, "ORIGINAL");
+        assert!(matches!(original, GpssType::Integer(_)));
+        let copies = copies.borrow();
+        assert_eq!(copies.len(), 3);
+        let mut families: Vec<&GpssType> = copies.values().collect();
+        families.push(&original);
+        for (index, family) in families.iter().enumerate() {
+            assert!(families[index + 1..].iter().all(|other| other != family));
         }
     }
+
+    /// Times of arrivals of GENERATE in units of model time
+    fn arrivals(source: &str) -> Vec<u64> {
+        let mut interpreter = Interpreter::from_source(source).unwrap();
+        let arrivals = Rc::new(RefCell::new(Vec::new()));
+        let observed = Rc::clone(&arrivals);
+        interpreter.set_event_observer(Box::new(move |event, _| {
+            if event.transact_number().is_none() {
+                observed
+                    .borrow_mut()
+                    .push(event.wake_time() / DEFAULT_TIME_SCALE);
+            }
+        }));
+        while interpreter.run_next_start().unwrap().is_some() {}
+        let arrivals = arrivals.borrow().clone();
+        arrivals
+    }
+
+    #[test]
+    fn generate_offset_delays_only_first_arrival() {
+        let delayed = arrivals(
+            "        GENERATE 10,,25
+        TERMINATE 1
+        START 4
+",
+        );
+        assert_eq!(delayed, vec![25, 35, 45, 55]);
+        let immediate = arrivals(
+            "        GENERATE 10,,0
+        TERMINATE 1
+        START 3
+",
+        );
+        assert_eq!(immediate, vec![0, 10, 20]);
+        let default = arrivals(
+            "        GENERATE 10
+        TERMINATE 1
+        START 3
+",
+        );
+        assert_eq!(default, vec![10, 20, 30]);
+    }
+
+    #[test]
+    fn generate_spread_keeps_arrivals_in_interval() {
+        let mut interpreter = Interpreter::from_source_with_seed(
+            "        GENERATE 10,4
+        TERMINATE 1
+        START 1000
+",
+            7,
+        )
+        .unwrap();
+        let arrivals = Rc::new(RefCell::new(Vec::new()));
+        let observed = Rc::clone(&arrivals);
+        interpreter.set_event_observer(Box::new(move |event, _| {
+            if event.transact_number().is_none() {
+                observed.borrow_mut().push(event.wake_time());
+            }
+        }));
+        while interpreter.run_next_start().unwrap().is_some() {}
+        let arrivals = arrivals.borrow();
+        let intervals: Vec<u64> = arrivals.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        assert!(intervals.len() >= 999);
+        let low = 6 * DEFAULT_TIME_SCALE;
+        let high = 14 * DEFAULT_TIME_SCALE;
+        assert!(intervals
+            .iter()
+            .all(|&interval| low <= interval && interval <= high));
+        // Разброс действительно используется, а среднее близко к A
+        assert!(intervals
+            .iter()
+            .any(|&interval| interval < 7 * DEFAULT_TIME_SCALE));
+        assert!(intervals
+            .iter()
+            .any(|&interval| interval > 13 * DEFAULT_TIME_SCALE));
+        let mean = intervals.iter().sum::<u64>() as f64 / intervals.len() as f64;
+        assert!(
+            (mean / DEFAULT_TIME_SCALE as f64 - 10.0).abs() < 0.3,
+            "{}",
+            mean
+        );
+    }
+
+    #[test]
+    fn variable_mixes_multiply_modulus_and_division() {
+        let interpreter = run("MIX     VARIABLE 7#3@5+10/4-1
+        GENERATE 1,,,1
+        SAVEVALUE RESULT,V$MIX
+        TERMINATE 1
+        START 1
+");
+        // (7#3)@5 + 10/4 - 1 = 1 + 2 - 1
+        assert_eq!(savevalue(&interpreter, "RESULT"), GpssType::Integer(2));
+    }
+
+    #[test]
+    fn test_sends_transacts_down_both_exits() {
+        let interpreter = run("        GENERATE 1,,,10
+        SAVEVALUE BACKLOG+,1
+        TEST LE X$BACKLOG,4,OVERFLOW
+        SAVEVALUE ACCEPTED+,1
+        TERMINATE 1
+OVERFLOW SAVEVALUE REJECTED+,1
+        TERMINATE 1
+        START 10
+");
+        assert_eq!(savevalue(&interpreter, "ACCEPTED"), GpssType::Integer(4));
+        assert_eq!(savevalue(&interpreter, "REJECTED"), GpssType::Integer(6));
+    }
+
+    /// Events of all STARTs of seeded model in order of their execution
+    fn seeded_events(source: &str, seed: u64) -> Vec<(usize, u64, Option<u32>)> {
+        let mut interpreter =
+            Interpreter::from_source_with_seed(source, seed).expect("model must be valid");
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let observed = Rc::clone(&events);
+        interpreter.set_event_observer(Box::new(move |event, _| {
+            observed.borrow_mut().push((
+                event.instruction_id(),
+                event.wake_time(),
+                event.transact_number(),
+            ));
+        }));
+        while interpreter.run_next_start().unwrap().is_some() {}
+        let events = events.borrow().clone();
+        events
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_event_order() {
+        let source = "        GENERATE EXPONENTIAL 10
+        QUEUE LINE
+        SEIZE SERVER
+        DEPART LINE
+        ADVANCE 8,4
+        RELEASE SERVER
+        TRANSFER .5,,TWICE
+        TERMINATE 1
+TWICE   SPLIT 1,DONE
+DONE    TERMINATE 1
+        START 100
+";
+        let first = seeded_events(source, 42);
+        let second = seeded_events(source, 42);
+        let other = seeded_events(source, 43);
+        assert!(first.len() > 100);
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn fractional_transfer_splits_transacts_by_fraction() {
+        let interpreter = run_seeded(
+            "        GENERATE 1
+        TRANSFER .25,,REJECT
+        SAVEVALUE KEPT+,1
+        TERMINATE 1
+REJECT  SAVEVALUE REJECTED+,1
+        TERMINATE 1
+        START 10000
+",
+            11,
+        );
+        let rejected = savevalue(&interpreter, "REJECTED").as_float().unwrap();
+        let kept = savevalue(&interpreter, "KEPT").as_float().unwrap();
+        assert_eq!(rejected + kept, 10000.0);
+        assert!((rejected / 10000.0 - 0.25).abs() < 0.015, "{}", rejected);
+    }
+
+    #[test]
+    fn fractional_transfer_chooses_between_two_targets() {
+        let interpreter = run_seeded(
+            "        GENERATE 1
+        TRANSFER .3,STAY,AWAY
+STAY    SAVEVALUE STAYED+,1
+        TERMINATE 1
+AWAY    SAVEVALUE LEFT+,1
+        TERMINATE 1
+        START 10000
+",
+            5,
+        );
+        let left = savevalue(&interpreter, "LEFT").as_float().unwrap();
+        let stayed = savevalue(&interpreter, "STAYED").as_float().unwrap();
+        assert_eq!(left + stayed, 10000.0);
+        assert!((left / 10000.0 - 0.3).abs() < 0.015, "{}", left);
+    }
+
+    #[test]
+    fn transfer_both_takes_second_exit_when_first_refuses() {
+        let interpreter = run("CHAIR   STORAGE 1
+        GENERATE 1,,,4
+        TRANSFER BOTH,SIT,STAND
+SIT     ENTER CHAIR
+        SAVEVALUE SAT+,1
+        ADVANCE 10
+        LEAVE CHAIR
+        TERMINATE 1
+STAND   SAVEVALUE STOOD+,1
+        TERMINATE 1
+        START 4
+");
+        assert_eq!(savevalue(&interpreter, "SAT"), GpssType::Integer(1));
+        assert_eq!(savevalue(&interpreter, "STOOD"), GpssType::Integer(3));
+    }
 }
@@ -1,4 +1,13 @@
-use std::fs;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::iter::Peekable;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::interpreter::{GpssType, Relation};
 
 macro_rules! get_keyword_name_impl {
      ($f_name: ident; $lex_type:ty; $lex_enum:ident; $($lexname:expr,$lexcode:ident),+) =>  {
@@ -21,20 +30,31 @@ macro_rules! get_keyword_code_impl {
     };
 }
 
+macro_rules! get_keyword_code_with_aliases_impl {
+     ($f_name: ident; $lex_enum:ident; $([$($alias:literal),+],$lexcode:ident),+) =>  {
+                fn $f_name(name: &str) -> Option<$lex_enum>{
+                    match name.to_uppercase().as_str(){
+                        $($($alias)|+ => Some($lex_enum::$lexcode),)+
+                        _ => None
+                    }
+              }
+    };
+}
+
 macro_rules! tokens{
     ($tokens_type:ident; $($lex:ident),+) =>  {
-                #[derive(Copy, Clone,Debug)]
-                enum $tokens_type{$($lex),+}
+                #[derive(Copy, Clone,Debug,PartialEq)]
+                pub enum $tokens_type{$($lex),+}
 
     };
 }
 
 macro_rules! implement_lexer{
-    // for tokens
-    ($($lex:ident),+) => {
+    // for tokens: upper case spellings, which are accepted in any case. The first one is canonical.
+    ($($lex:ident [$name:literal $(, $alias:literal)*]),+) => {
             tokens!(Keyword; $($lex),+);
-            get_keyword_name_impl!(get_keyword_name; String; Keyword; $(stringify!($lex),$lex),+);
-            get_keyword_code_impl!(get_keyword_code; &str; Keyword; $(stringify!($lex),$lex),+);
+            get_keyword_name_impl!(get_keyword_name; &'static str; Keyword; $($name,$lex),+);
+            get_keyword_code_with_aliases_impl!(get_keyword_code; Keyword; $([$name $(, $alias)*],$lex),+);
     };
     // for special symbols
     ($(|$name:expr,$code:ident|),+) =>{
@@ -44,64 +64,1589 @@ macro_rules! implement_lexer{
     };
 }
 
-implement_lexer!(Generate, Terminate, Advance, Test, Seize, Release, Queue, Depart);
+implement_lexer!(Generate ["GENERATE", "GEN"],
+                 Terminate ["TERMINATE", "TERM", "TER"],
+                 Advance ["ADVANCE", "ADV"],
+                 Test ["TEST"],
+                 Seize ["SEIZE"],
+                 Release ["RELEASE", "REL"],
+                 Queue ["QUEUE"],
+                 Depart ["DEPART", "DEP"],
+                 Transfer ["TRANSFER", "TRA"],
+                 Enter ["ENTER"],
+                 Leave ["LEAVE"],
+                 Storage ["STORAGE"],
+                 Assign ["ASSIGN"],
+                 Mark ["MARK"],
+                 Tabulate ["TABULATE"],
+                 Table ["TABLE"],
+                 Qtable ["QTABLE"],
+                 Split ["SPLIT"],
+                 Assemble ["ASSEMBLE"],
+                 Gather ["GATHER"],
+                 Match ["MATCH"],
+                 Loop ["LOOP"],
+                 Gate ["GATE"],
+                 Logic ["LOGIC"],
+                 Preempt ["PREEMPT"],
+                 Return ["RETURN"],
+                 Link ["LINK"],
+                 Unlink ["UNLINK"],
+                 Buffer ["BUFFER"],
+                 Select ["SELECT"],
+                 Count ["COUNT"],
+                 Priority ["PRIORITY"],
+                 Savevalue ["SAVEVALUE"],
+                 Msavevalue ["MSAVEVALUE"],
+                 Matrix ["MATRIX"],
+                 Print ["PRINT"],
+                 Start ["START"],
+                 Simulate ["SIMULATE"],
+                 End ["END"],
+                 Reset ["RESET"],
+                 Clear ["CLEAR"],
+                 Initial ["INITIAL"],
+                 Function ["FUNCTION"],
+                 Variable ["VARIABLE"],
+                 Include ["INCLUDE"]);
 implement_lexer!(|' ',Space|, |'\t',Tab|, |'\n', Newline|,
-                 |';',Semicolon|, |'\0', Endfile|,
-                 |'/',Div|, |'*', Multiply|, |'+',Plus|, |'-',Minus|);
+                 |';',Semicolon|, |'\0', Endfile|, |',',Comma|,
+                 |'/',Div|, |'*', Star|, |'+',Plus|, |'-',Minus|,
+                 |'#', Multiply|, |'@', Modulus|,
+                 |'(',LParen|, |')',RParen|, |'$',Dollar|,
+                 |'<',Less|, |'>',Greater|, |'=',Equal|);
 
 #[derive(Debug)]
 pub enum Lexeme {
     Keyword(Keyword),
     Special(Special),
-    UserIdentity(String),
+    UserIdentity(Symbol),
+    /// Numeric literal: Integer without dot, Float with dot
+    Number(GpssType),
+    /// Standard numeric attribute: `X$CELL`, `Q$LINE`, `P1`
+    Sna {
+        family: SnaFamily,
+        name: String,
+    },
+    /// Relational operator of TEST block: `TEST GE Q$LINE,5`
+    Relop(Relation),
+    /// Text between double quotes, `""` inside is a quote
+    StringLit(String),
+    /// Label definition: identifier at the start of line before keyword,
+    /// or field, which starts at column 1 in fixed format. References to label stay `UserIdentity`.
+    LabelDef(Symbol),
+}
+
+/// Family of standard numeric attribute, prefix before `$`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SnaFamily {
+    /// X: savevalue
+    Savevalue,
+    /// Q: current content of queue
+    Queue,
+    /// F: facility is busy
+    Facility,
+    /// P: parameter of transact
+    Parameter,
+    /// S: used units of storage
+    Storage,
+    /// R: remaining units of storage
+    StorageRemaining,
+    /// V: value of arithmetic VARIABLE
+    Variable,
+    /// FN: value of FUNCTION for its argument
+    Function,
+    /// RN: random number in [0,1), like `RN1`
+    RandomNumber,
+    /// M: transit time of transact, `M1`
+    Transit,
+    /// MP: transit time from MARK to parameter, like `MP2`
+    MarkParameter,
+    /// MX: cell of MATRIX, like `MX$COST(1,2)`
+    Matrix,
+}
+
+impl SnaFamily {
+    pub fn prefix(self) -> &'static str {
+        match self {
+            SnaFamily::Savevalue => "X",
+            SnaFamily::Queue => "Q",
+            SnaFamily::Facility => "F",
+            SnaFamily::Parameter => "P",
+            SnaFamily::Storage => "S",
+            SnaFamily::StorageRemaining => "R",
+            SnaFamily::Variable => "V",
+            SnaFamily::Function => "FN",
+            SnaFamily::RandomNumber => "RN",
+            SnaFamily::Transit => "M",
+            SnaFamily::MarkParameter => "MP",
+            SnaFamily::Matrix => "MX",
+        }
+    }
+
+    fn from_prefix(prefix: &str) -> Option<SnaFamily> {
+        match prefix.to_uppercase().as_str() {
+            "X" => Some(SnaFamily::Savevalue),
+            "Q" => Some(SnaFamily::Queue),
+            "F" => Some(SnaFamily::Facility),
+            "P" => Some(SnaFamily::Parameter),
+            "S" => Some(SnaFamily::Storage),
+            "R" => Some(SnaFamily::StorageRemaining),
+            "V" => Some(SnaFamily::Variable),
+            "FN" => Some(SnaFamily::Function),
+            "RN" => Some(SnaFamily::RandomNumber),
+            "M" => Some(SnaFamily::Transit),
+            "MP" => Some(SnaFamily::MarkParameter),
+            "MX" => Some(SnaFamily::Matrix),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", get_keyword_name(*self))
+    }
+}
+
+/// Name, which is not a keyword
+#[derive(Debug)]
+pub struct UnknownKeyword(pub String);
+
+impl fmt::Display for UnknownKeyword {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown keyword {}", self.0)
+    }
+}
+
+impl FromStr for Keyword {
+    type Err = UnknownKeyword;
+
+    /// Keyword by any of its spellings, in any case
+    fn from_str(name: &str) -> Result<Keyword, UnknownKeyword> {
+        get_keyword_code(name).ok_or_else(|| UnknownKeyword(name.to_string()))
+    }
+}
+
+impl fmt::Display for Special {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", get_special_name(*self))
+    }
+}
+
+/// Interned name: index in `Interner`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+/// Table of names, so repeated names are stored once
+#[derive(Debug, Default)]
+pub struct Interner {
+    names: Vec<String>,
+    symbols: HashMap<String, Symbol>,
+}
+
+impl Interner {
+    /// Symbol of name. Name is stored at its first occurrence only.
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(name) {
+            return symbol;
+        }
+        let symbol = Symbol(self.names.len() as u32);
+        self.names.push(name.to_string());
+        self.symbols.insert(name.to_string(), symbol);
+        symbol
+    }
+
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.names[symbol.0 as usize]
+    }
+}
+
+/// Lexeme with interner to resolve names, which is printed as it is spelled in source
+pub struct LexemeDisplay<'a> {
+    lexeme: &'a Lexeme,
+    interner: &'a Interner,
+}
+
+impl Lexeme {
+    pub fn display<'a>(&'a self, interner: &'a Interner) -> LexemeDisplay<'a> {
+        LexemeDisplay {
+            lexeme: self,
+            interner,
+        }
+    }
+}
+
+impl<'a> fmt::Display for LexemeDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.lexeme {
+            Lexeme::Keyword(keyword) => write!(f, "{}", keyword),
+            Lexeme::Special(special) => write!(f, "{}", special),
+            Lexeme::UserIdentity(name) | Lexeme::LabelDef(name) => {
+                write!(f, "{}", self.interner.resolve(*name))
+            }
+            Lexeme::Number(GpssType::Integer(value)) => write!(f, "{}", value),
+            Lexeme::Number(GpssType::Float(value)) => write!(f, "{:?}", value),
+            Lexeme::Number(value) => write!(f, "{}", value),
+            Lexeme::Sna { family, name } => write!(f, "{}${}", family.prefix(), name),
+            Lexeme::Relop(relation) => write!(f, "{}", relation.mnemonic()),
+            Lexeme::StringLit(text) => write!(f, "\"{}\"", text.replace('"', "\"\"")),
+        }
+    }
+}
+
+/// Renders lexemes one per line with position: `line:col<TAB>"text"`.
+/// Text is quoted, so spaces and line feeds are visible.
+pub fn dump_tokens(lexemes: &[Spanned<Lexeme>], interner: &Interner, files: &SourceMap) -> String {
+    lexemes
+        .iter()
+        .map(|lexeme| {
+            format!(
+                "{}\t{:?}\n",
+                files.locate(lexeme.span),
+                lexeme.node.display(interner).to_string()
+            )
+        })
+        .collect()
+}
+
+/// Position of token in source file. Line and column start from 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    /// Index of file in `SourceMap`, 0 is the main file
+    pub file: u32,
+    pub line: u32,
+    pub col: u32,
+    /// Offset of the first byte of token from start of file, for slicing source text
+    pub offset: u32,
+}
+
+/// Paths of lexed source files: the main one and included ones
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    paths: Vec<PathBuf>,
+}
+
+impl SourceMap {
+    fn add(&mut self, path: PathBuf) -> u32 {
+        self.paths.push(path);
+        (self.paths.len() - 1) as u32
+    }
+
+    pub fn path(&self, file: u32) -> Option<&Path> {
+        self.paths.get(file as usize).map(PathBuf::as_path)
+    }
+
+    /// Position as `line:col` in the main file and as `path:line:col` in included one
+    pub fn locate(&self, span: Span) -> String {
+        match self.path(span.file) {
+            Some(path) if span.file != 0 => {
+                format!("{}:{}:{}", path.display(), span.line, span.col)
+            }
+            _ => format!("{}:{}", span.line, span.col),
+        }
+    }
+}
+
+/// Token with its position in source file
+#[derive(Debug)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+/// Checks that identifier looks like numeric literal: starts with digit, dot or sign and digit
+fn is_number_start(ident: &str) -> bool {
+    let mut chars = ident.chars();
+    match chars.next() {
+        Some('+') | Some('-') => chars.next().is_some_and(|c| c.is_ascii_digit() || c == '.'),
+        Some(c) => c.is_ascii_digit() || c == '.',
+        None => false,
+    }
+}
+
+/// Checks that numeric literal ends with exponent mark, so sign after it is exponent sign: `1e-3`
+fn is_exponent_mark(ident: &str) -> bool {
+    is_number_start(ident) && (ident.ends_with('e') || ident.ends_with('E'))
+}
+
+/// Parses numeric literal. Returns None for malformed literal, like `1.2.3` or `1e+`.
+fn parse_number(ident: &str) -> Option<GpssType> {
+    if ident.contains(['.', 'e', 'E']) {
+        ident.parse::<f32>().ok().map(GpssType::Float)
+    } else {
+        ident.parse::<i32>().ok().map(GpssType::Integer)
+    }
+}
+
+/// Checks that lexeme is a value, so sign after it is binary operator, not a part of number
+fn is_value(lexeme: &Lexeme) -> bool {
+    matches!(
+        lexeme,
+        Lexeme::UserIdentity(_)
+            | Lexeme::Number(_)
+            | Lexeme::Sna { .. }
+            | Lexeme::Special(Special::RParen)
+    )
+}
+
+/// Error of lexical analysis
+#[derive(Debug)]
+pub enum LexError {
+    /// Source file can not be read
+    Io(io::Error),
+    /// Character, which can not be a part of any token
+    InvalidCharacter { ch: char, span: Span },
+    /// Malformed token, like `Z$NAME`
+    UnknownToken { text: String, span: Span },
+    /// Malformed numeric literal, like `1.2.3` or `1e+`
+    MalformedNumber { text: String, span: Span },
+    /// String literal without closing quote till the end of line, `span` is its opening quote
+    UnterminatedString { span: Span },
+    /// INCLUDE without file name in quotes
+    BadInclude { span: Span },
+    /// Included file can not be read
+    Include {
+        path: String,
+        span: Span,
+        error: io::Error,
+    },
+    /// File includes itself through `chain` of files
+    IncludeCycle { chain: Vec<String>, span: Span },
+    /// Nesting of INCLUDE is deeper than `MAX_INCLUDE_DEPTH`
+    IncludeTooDeep { path: String, span: Span },
+    /// Error in included file
+    InFile { path: String, error: Box<LexError> },
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexError::Io(err) => write!(f, "cannot read source: {}", err),
+            LexError::InvalidCharacter { ch, span } => {
+                write!(f, "{}:{}: invalid character {:?}", span.line, span.col, ch)
+            }
+            LexError::UnknownToken { text, span } => {
+                write!(f, "{}:{}: unknown token {}", span.line, span.col, text)
+            }
+            LexError::MalformedNumber { text, span } => {
+                write!(f, "{}:{}: malformed number {}", span.line, span.col, text)
+            }
+            LexError::UnterminatedString { span } => {
+                write!(f, "{}:{}: unterminated string", span.line, span.col)
+            }
+            LexError::BadInclude { span } => {
+                write!(
+                    f,
+                    "{}:{}: INCLUDE requires file name in quotes",
+                    span.line, span.col
+                )
+            }
+            LexError::Include { path, span, error } => {
+                write!(
+                    f,
+                    "{}:{}: cannot include {}: {}",
+                    span.line, span.col, path, error
+                )
+            }
+            LexError::IncludeCycle { chain, span } => write!(
+                f,
+                "{}:{}: include cycle {}",
+                span.line,
+                span.col,
+                chain.join(" -> ")
+            ),
+            LexError::IncludeTooDeep { path, span } => write!(
+                f,
+                "{}:{}: cannot include {}: nesting is deeper than {}",
+                span.line, span.col, path, MAX_INCLUDE_DEPTH
+            ),
+            LexError::InFile { path, error } => write!(f, "{}: {}", path, error),
+        }
+    }
+}
+
+/// All errors of lexical analysis of source
+#[derive(Debug)]
+pub struct LexErrors(pub Vec<LexError>);
+
+impl fmt::Display for LexErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for err in &self.0 {
+            writeln!(f, "{}", err)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<io::Error> for LexErrors {
+    fn from(err: io::Error) -> LexErrors {
+        LexErrors(vec![LexError::Io(err)])
+    }
+}
+
+impl From<io::Error> for LexError {
+    fn from(err: io::Error) -> LexError {
+        LexError::Io(err)
+    }
+}
+
+/// Checks that character separates fields, so scanning can resume after error at it
+fn is_separator(ch: char) -> bool {
+    ch == ' ' || ch == '\t' || ch == '\n' || ch == '\r'
+}
+
+/// Checks that character can be a part of identifier or number
+fn is_ident_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_' || ch == '.'
+}
+
+/// Parses `FAMILY$NAME` and `Pn`, `RNn`, `M1`, `MPn` forms of standard numeric attribute.
+/// Returns None, if identifier is not an attribute, and Some(None) for malformed one.
+fn parse_sna(ident: &str) -> Option<Option<Lexeme>> {
+    if let Some(dollar) = ident.find('$') {
+        let (prefix, name) = (&ident[..dollar], &ident[dollar + 1..]);
+        let family = SnaFamily::from_prefix(prefix);
+        let valid_name = !name.is_empty() && !name.contains('$');
+        return Some(family.filter(|_| valid_name).map(|family| Lexeme::Sna {
+            family,
+            name: name.to_string(),
+        }));
+    }
+    // P1 является параметром, а P1X или P - просто идентификатором. Так же RN1 - генератор случайных чисел,
+    // M1 и MP1 - время пребывания транзакта в модели.
+    let split = ident.find(|c: char| c.is_ascii_digit())?;
+    let (prefix, number) = (&ident[..split], &ident[split..]);
+    let family = match prefix.to_uppercase().as_str() {
+        "P" => SnaFamily::Parameter,
+        "RN" => SnaFamily::RandomNumber,
+        "M" => SnaFamily::Transit,
+        "MP" => SnaFamily::MarkParameter,
+        _ => return None,
+    };
+    if !number.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(Some(Lexeme::Sna {
+        family,
+        name: number.to_string(),
+    }))
 }
 
-pub fn lexer(filename: &str) -> Vec<Lexeme> {
-    let buffer = fs::read_to_string(filename).unwrap();
-    let mut ident = String::new();
-    let mut lexems = Vec::new();
-    let mut line_number: u32 = 1;
+/// Layout of statements in source
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LexMode {
+    /// Fields are separated by any count of spaces and tabs
+    FreeFormat,
+    /// Card deck: label starts at column 1, then operation and operands fields.
+    /// Anything after operands field and past column 72 is a comment.
+    FixedFormat,
+}
+
+/// Comment column of card decks
+const FIXED_FORMAT_COMMENT_COLUMN: u32 = 72;
+
+/// Settings of comments recognition and column counting
+#[derive(Clone)]
+pub struct LexerOptions {
+    /// Character, which starts comment till the end of line
+    pub comment_char: Option<char>,
+    /// Characters after this column are comment (72 for punched card decks)
+    pub comment_column: Option<u32>,
+    /// Count of columns, which tab character takes
+    pub tab_width: u32,
+    /// Spaces and tabs are returned as lexemes
+    pub emit_whitespace: bool,
+    /// Line feeds are returned as lexemes, to mark statement boundaries
+    pub emit_newlines: bool,
+    /// Scanning stops at the first error. Otherwise it resumes at the next space or line feed.
+    pub strict: bool,
+    pub mode: LexMode,
+}
+
+impl Default for LexerOptions {
+    fn default() -> LexerOptions {
+        LexerOptions {
+            comment_char: Some(';'),
+            comment_column: None,
+            tab_width: 8,
+            emit_whitespace: true,
+            emit_newlines: true,
+            strict: false,
+            mode: LexMode::FreeFormat,
+        }
+    }
+}
+
+/// Classifies accumulated identifier as keyword, number, attribute or user identity.
+/// `relop_position` means auxiliary operand of TEST block, where relational operators are written.
+fn classify_ident(
+    ident: &str,
+    relop_position: bool,
+    span: Span,
+    interner: &mut Interner,
+) -> Result<Lexeme, LexError> {
+    // Отношение блока TEST: E, NE, G, GE, L, LE
+    if let Some(relation) = Relation::from_mnemonic(ident).filter(|_| relop_position) {
+        return Ok(Lexeme::Relop(relation));
+    }
+    let lexeme = match get_keyword_code(ident) {
+        //Если сущность является ключевым словом
+        Some(code) => Lexeme::Keyword(code),
+        // Если сущность похожа на число
+        None if is_number_start(ident) => match parse_number(ident) {
+            Some(value) => Lexeme::Number(value),
+            None => {
+                return Err(LexError::MalformedNumber {
+                    text: ident.to_string(),
+                    span,
+                })
+            }
+        },
+        None => match parse_sna(ident) {
+            // Стандартный числовой атрибут
+            Some(Some(sna)) => sna,
+            Some(None) => {
+                return Err(LexError::UnknownToken {
+                    text: ident.to_string(),
+                    span,
+                })
+            }
+            // Если сущность не является ключевым словом
+            // Это может быть пользовательское название функции, переменной, блока
+            None => Lexeme::UserIdentity(interner.intern(ident)),
+        },
+    };
+    Ok(lexeme)
+}
+
+/// Characters of buffered reader, read line by line
+pub struct ReaderChars<R: BufRead> {
+    reader: R,
+    line: Vec<char>,
+    position: usize,
+}
+
+impl<R: BufRead> ReaderChars<R> {
+    pub fn new(reader: R) -> ReaderChars<R> {
+        ReaderChars {
+            reader,
+            line: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for ReaderChars<R> {
+    type Item = io::Result<char>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.position == self.line.len() {
+            let mut buffer = String::new();
+            match self.reader.read_line(&mut buffer) {
+                Ok(0) => return None,
+                Ok(_) => {
+                    self.line = buffer.chars().collect();
+                    self.position = 0;
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        self.position += 1;
+        Some(Ok(self.line[self.position - 1]))
+    }
+}
+
+/// Streaming lexer. Scans characters lazily and yields lexemes one by one,
+/// so the whole token vector never has to be materialized.
+/// Characters come from `str::chars().map(Ok)` or from `ReaderChars`.
+pub struct Lexer<I: Iterator<Item = io::Result<char>>> {
+    chars: Peekable<I>,
+    options: LexerOptions,
+    /// Accumulated identifier
+    ident: String,
+    /// Position of the first character of accumulated identifier
+    ident_span: Span,
+    /// Scanning is inside string literal
+    in_string: bool,
+    /// Content of string literal
+    string: String,
+    /// Position of opening quote of string literal
+    string_span: Span,
+    line_number: u32,
+    column: u32,
+    /// Byte offset of scanned character from start of file
+    offset: u32,
+    /// Only spaces and tabs are scanned on current line
+    line_start: bool,
+    /// Current field of line is not finished by space yet
+    in_field: bool,
+    /// Count of finished fields of current line
+    line_fields: u32,
+    /// Current line starts with label
+    line_has_label: bool,
+    /// Line is a comment as a whole, including line feed
+    line_comment: bool,
+    /// Rest of line is a comment
+    trailing_comment: bool,
+    /// Last lexeme is a value, so the next sign is binary operator
+    after_value: bool,
+    /// Last lexeme except spaces is TEST keyword, so relational operator may follow
+    after_test: bool,
+    /// Characters are skipped till the next space or line feed after error
+    recovering: bool,
+    /// No lexemes except spaces are emitted on current line
+    line_empty: bool,
+    /// Index in `pending` of identifier, which may be label definition.
+    /// Lexemes are held, until the next lexeme shows it.
+    label_candidate: Option<usize>,
+    /// Index in `pending` of the first space of line, which has no other lexemes yet.
+    /// Spaces are held, until the line shows, whether it is a comment.
+    blank_start: Option<usize>,
+    /// Rest of current line after comment character or comment column is skipped
+    line_commented: bool,
+    /// Scanned lexemes, which are not returned yet
+    pending: VecDeque<Spanned<Lexeme>>,
+    /// Names of user identities and labels
+    interner: Interner,
+    /// Index of scanned file in `SourceMap`
+    file: u32,
+    /// All characters are scanned
+    finished: bool,
+}
+
+impl<I: Iterator<Item = io::Result<char>>> Lexer<I> {
+    pub fn new(chars: I, options: LexerOptions) -> Lexer<I> {
+        Self::in_file(chars, options, 0, Interner::default())
+    }
+
+    /// Lexer of `file`, which adds names to `interner` of including file
+    pub fn in_file(chars: I, options: LexerOptions, file: u32, interner: Interner) -> Lexer<I> {
+        let span = Span {
+            file,
+            line: 1,
+            col: 1,
+            offset: 0,
+        };
+        Lexer {
+            chars: chars.peekable(),
+            options,
+            ident: String::new(),
+            ident_span: span,
+            in_string: false,
+            string: String::new(),
+            string_span: span,
+            line_number: 1,
+            column: 0,
+            offset: 0,
+            line_start: true,
+            in_field: false,
+            line_fields: 0,
+            line_has_label: false,
+            line_comment: false,
+            trailing_comment: false,
+            after_value: false,
+            after_test: false,
+            recovering: false,
+            line_empty: true,
+            label_candidate: None,
+            blank_start: None,
+            line_commented: false,
+            pending: VecDeque::new(),
+            interner,
+            file,
+            finished: false,
+        }
+    }
+
+    /// Names of scanned identities
+    pub fn interner(&self) -> &Interner {
+        &self.interner
+    }
+
+    pub fn into_interner(self) -> Interner {
+        self.interner
+    }
+
+    /// Checks next character without consuming it
+    fn next_is<P: Fn(char) -> bool>(&mut self, predicate: P) -> bool {
+        match self.chars.peek() {
+            Some(Ok(c)) => predicate(*c),
+            _ => false,
+        }
+    }
+
+    /// Queues lexeme and remembers context for the next lexemes.
+    /// Suppressed whitespace still separates lexemes and affects context.
+    fn emit(&mut self, node: Lexeme, span: Span) {
+        self.after_value = is_value(&node);
+        match node {
+            Lexeme::Special(Special::Space) | Lexeme::Special(Special::Tab) => {
+                if !self.options.emit_whitespace {
+                    return;
+                }
+                if self.line_empty && self.blank_start.is_none() {
+                    self.blank_start = Some(self.pending.len());
+                }
+            }
+            Lexeme::Special(Special::Newline) => {
+                // Строка только из комментария не дает лексем, даже перевода строки
+                let comment_line = self.line_empty && self.line_commented;
+                self.after_test = false;
+                self.line_empty = true;
+                self.line_commented = false;
+                self.label_candidate = None;
+                if comment_line {
+                    self.drop_blank_start();
+                    return;
+                }
+                self.blank_start = None;
+                if !self.options.emit_newlines {
+                    return;
+                }
+            }
+            _ => {
+                self.after_test = matches!(node, Lexeme::Keyword(Keyword::Test));
+                self.resolve_label(&node);
+                self.line_empty = false;
+                self.blank_start = None;
+            }
+        }
+        self.pending.push_back(Spanned { node, span });
+    }
+
+    /// Drops held spaces of line, which turned out to be a comment
+    fn drop_blank_start(&mut self) {
+        if let Some(start) = self.blank_start.take() {
+            self.pending.truncate(start);
+        }
+    }
+
+    /// Identifier at the start of line, followed by keyword, is label definition
+    fn resolve_label(&mut self, node: &Lexeme) {
+        match (self.label_candidate.take(), node) {
+            (Some(index), Lexeme::Keyword(_)) => {
+                let candidate = &mut self.pending[index].node;
+                if let Lexeme::UserIdentity(name) = *candidate {
+                    *candidate = Lexeme::LabelDef(name);
+                }
+            }
+            (None, Lexeme::UserIdentity(_)) if self.line_empty => {
+                self.label_candidate = Some(self.pending.len());
+            }
+            _ => {}
+        }
+    }
+
+    /// Queues accumulated identifier as keyword, number or user identity
+    fn flush_ident(&mut self) -> Result<(), LexError> {
+        //Если сущность пустая, то пропускаем
+        if self.ident.is_empty() {
+            return Ok(());
+        }
+        let lexeme = if self.options.mode == LexMode::FixedFormat && self.ident_span.col == 1 {
+            Ok(Lexeme::LabelDef(self.interner.intern(self.ident.as_str())))
+        } else {
+            classify_ident(
+                self.ident.as_str(),
+                self.after_test,
+                self.ident_span,
+                &mut self.interner,
+            )
+        };
+        self.ident.clear();
+        self.emit(lexeme?, self.ident_span);
+        Ok(())
+    }
+
+    /// Column, after which characters are comment
+    fn comment_column(&self) -> Option<u32> {
+        match self.options.mode {
+            LexMode::FreeFormat => self.options.comment_column,
+            LexMode::FixedFormat => self
+                .options
+                .comment_column
+                .or(Some(FIXED_FORMAT_COMMENT_COLUMN)),
+        }
+    }
+
+    /// Counts fields of line in fixed format. Text after operands field is a comment.
+    fn track_fixed_fields(&mut self, i: char, span: Span) {
+        if i == '\n' {
+            self.in_field = false;
+            self.line_fields = 0;
+            self.line_has_label = false;
+        } else if i == ' ' || i == '\t' {
+            if self.in_field {
+                self.in_field = false;
+                self.line_fields += 1;
+                // Метка, операция, операнды; дальше комментарий
+                let operands_end = if self.line_has_label { 3 } else { 2 };
+                self.trailing_comment = self.line_fields == operands_end;
+            }
+        } else {
+            self.in_field = true;
+            if span.col == 1 {
+                self.line_has_label = true;
+            }
+        }
+    }
+
+    /// Scans one character, queueing completed lexemes
+    fn scan_char(&mut self, i: char) -> Result<(), LexError> {
+        // Метка порядка байтов в начале файла
+        if i == '\u{feff}' && self.line_number == 1 && self.column == 0 {
+            return Ok(());
+        }
+        // Перевод строки Windows \r\n и одиночный \r старых Mac считаются одним \n
+        let i = match i {
+            '\r' if self.next_is(|c| c == '\n') => return Ok(()),
+            '\r' => '\n',
+            i => i,
+        };
+        // Строка не может переходить на следующую строку,
+        // перевод строки обрабатывается как обычно, но после ошибки
+        if i == '\n' && self.in_string {
+            self.in_string = false;
+            self.string.clear();
+            self.scan_char(i)?;
+            return Err(LexError::UnterminatedString {
+                span: self.string_span,
+            });
+        }
+        let span = Span {
+            file: self.file,
+            line: self.line_number,
+            col: self.column + 1,
+            offset: self.offset,
+        };
+        self.column += if i == '\t' { self.options.tab_width } else { 1 };
+        // Внутри строки спец-символы и комментарии не действуют
+        if self.in_string {
+            if i != '"' {
+                self.string.push(i);
+            } else if self.next_is(|c| c == '"') {
+                self.chars.next();
+                self.column += 1;
+                self.offset += 1;
+                self.string.push('"');
+            } else {
+                self.in_string = false;
+                let string = self.string.split_off(0);
+                self.emit(Lexeme::StringLit(string), self.string_span);
+            }
+            return Ok(());
+        }
+        if i == '\n' {
+            self.column = 0;
+            if self.line_comment {
+                self.line_comment = false;
+                self.drop_blank_start();
+                self.line_start = true;
+                self.line_number += 1;
+                return Ok(());
+            }
+            self.trailing_comment = false;
+        } else if self.line_comment || self.trailing_comment {
+            return Ok(());
+        } else if self.line_start && i == '*' {
+            // Звездочка первым значащим символом строки делает всю строку комментарием
+            self.line_comment = true;
+            return Ok(());
+        } else if Some(i) == self.options.comment_char
+            || self
+                .comment_column()
+                .is_some_and(|cutoff| span.col > cutoff)
+        {
+            self.trailing_comment = true;
+            self.line_commented = true;
+            return self.flush_ident();
+        }
+        self.line_start = i == '\n' || (self.line_start && (i == ' ' || i == '\t'));
+        if self.options.mode == LexMode::FixedFormat {
+            self.track_fixed_fields(i, span);
+        }
+        // После ошибки пропускаем символы до пробела или перевода строки
+        if self.recovering {
+            if !is_separator(i) {
+                return Ok(());
+            }
+            self.recovering = false;
+        }
 
-    for i in buffer.chars() {
         match get_special_code(i) {
+            // Знак перед числом является частью числа
+            Some(Special::Plus) | Some(Special::Minus)
+                if self.ident.is_empty()
+                    && !self.after_value
+                    && self.next_is(|c| c.is_ascii_digit() || c == '.') =>
+            {
+                self.ident_span = span;
+                self.ident.push(i);
+            }
+            // Знак после e в числе является знаком порядка: 1e-3, 2.5E+2
+            Some(Special::Plus) | Some(Special::Minus) if is_exponent_mark(self.ident.as_str()) => {
+                self.ident.push(i);
+            }
+            // Доллар после семейства атрибута является частью атрибута: X$CELL
+            Some(Special::Dollar) if SnaFamily::from_prefix(self.ident.as_str()).is_some() => {
+                self.ident.push(i);
+            }
             // Проверка, что сейчас спец-символ
             Some(special_code) => {
+                let flushed = self.flush_ident();
+
                 //Увеличиваем счетчик линий
-                match special_code {
-                    Special::Newline => {
-                        line_number += 1;
+                if let Special::Newline = special_code {
+                    self.line_number += 1;
+                }
+
+                // Пробелы и переводы строк сохраняются и после ошибки в сущности
+                if flushed.is_ok() || is_separator(i) {
+                    self.emit(Lexeme::Special(special_code), span);
+                }
+                flushed?;
+            }
+            // Если не спец-символ, то продолжаем накопление символов
+            None if is_ident_char(i) => {
+                if self.ident.is_empty() {
+                    self.ident_span = span;
+                }
+                self.ident.push(i);
+            }
+            // Начало строки
+            None if i == '"' => {
+                self.flush_ident()?;
+                self.in_string = true;
+                self.string_span = span;
+            }
+            None => return Err(LexError::InvalidCharacter { ch: i, span }),
+        };
+        Ok(())
+    }
+}
+
+impl<I: Iterator<Item = io::Result<char>>> Iterator for Lexer<I> {
+    type Item = Result<Spanned<Lexeme>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.label_candidate.is_none() && self.blank_start.is_none() {
+                if let Some(lexeme) = self.pending.pop_front() {
+                    return Some(Ok(lexeme));
+                }
+                if self.finished {
+                    return None;
+                }
+            }
+            let scanned = match self.chars.next() {
+                Some(Ok(i)) => {
+                    let scanned = self.scan_char(i);
+                    self.offset += i.len_utf8() as u32;
+                    if scanned.is_err() && !is_separator(i) {
+                        self.recovering = true;
+                        self.ident.clear();
                     }
-                    _ => {}
-                };
-                //Если сущность пустая, то пропускаем
-                if ident.len() != 0 {
-                    match get_keyword_code(ident.as_str()) {
-                        //Если сущность является ключевым словом
-                        Some(code) => {
-                            lexems.push(Lexeme::Keyword(code));
-                        }
-                        // Если сущность не является ключевым словом
-                        // Это может быть пользовательское название функции, переменной, блока
-                        // или просто значение
-                        None => {
-                            println!(
-                                "Lexer warning: unknown keyword: {}, line: {}",
-                                ident, line_number
-                            );
-
-                            lexems.push(Lexeme::UserIdentity(ident.clone()));
-                        }
+                    scanned
+                }
+                Some(Err(err)) => Err(LexError::Io(err)),
+                None => {
+                    self.finished = true;
+                    // Конец файла завершает последнюю сущность, как и спец-символ
+                    let flushed = if self.in_string {
+                        self.in_string = false;
+                        Err(LexError::UnterminatedString {
+                            span: self.string_span,
+                        })
+                    } else {
+                        self.flush_ident()
                     };
-                    ident.clear();
+                    // После конца файла ключевого слова уже не будет
+                    self.label_candidate = None;
+                    if self.line_comment || (self.line_empty && self.line_commented) {
+                        self.drop_blank_start();
+                    }
+                    self.blank_start = None;
+                    flushed
                 }
+            };
+            if let Err(err) = scanned {
+                if self.options.strict {
+                    self.finished = true;
+                    self.label_candidate = None;
+                    self.blank_start = None;
+                    self.pending.clear();
+                }
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+/// Lexemes with names of identities and paths of source files
+pub type Tokens = (Vec<Spanned<Lexeme>>, Interner, SourceMap);
+
+/// Maximal nesting of INCLUDE directives
+const MAX_INCLUDE_DEPTH: usize = 16;
 
-                lexems.push(Lexeme::Special(special_code));
+/// Collects all lexemes and errors. Errors are collected too, unless lexer is strict.
+fn collect_lexemes<I: Iterator<Item = io::Result<char>>>(
+    mut lexer: Lexer<I>,
+) -> (Vec<Spanned<Lexeme>>, Vec<LexError>, Interner) {
+    let mut lexemes = Vec::new();
+    let mut errors = Vec::new();
+    for lexeme in lexer.by_ref() {
+        match lexeme {
+            Ok(lexeme) => lexemes.push(lexeme),
+            Err(err) => errors.push(err),
+        }
+    }
+    (lexemes, errors, lexer.into_interner())
+}
+
+/// Preprocessor, which replaces `INCLUDE "path"` by lexemes of the named file
+struct Includes<'a> {
+    options: &'a LexerOptions,
+    files: SourceMap,
+    /// Files being lexed now, from the main one: path as written and canonical path
+    chain: Vec<(PathBuf, PathBuf)>,
+    interner: Interner,
+}
+
+impl<'a> Includes<'a> {
+    fn new(options: &'a LexerOptions) -> Includes<'a> {
+        Includes {
+            options,
+            files: SourceMap::default(),
+            chain: Vec::new(),
+            interner: Interner::default(),
+        }
+    }
+
+    /// Lexes file and expands its includes
+    fn lex<I: Iterator<Item = io::Result<char>>>(
+        &mut self,
+        chars: I,
+        path: PathBuf,
+    ) -> Result<Vec<Spanned<Lexeme>>, LexErrors> {
+        let file = self.files.add(path.clone());
+        let interner = mem::take(&mut self.interner);
+        let lexer = Lexer::in_file(chars, self.options.clone(), file, interner);
+        let (lexemes, mut errors, interner) = collect_lexemes(lexer);
+        self.interner = interner;
+        let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        self.chain.push((path.clone(), canonical));
+        let mut nested = Vec::new();
+        let lexemes = self.expand(lexemes, &mut errors, &mut nested);
+        self.chain.pop();
+        // Ошибки вложенных файлов уже помечены их путями
+        if file != 0 {
+            errors = errors
+                .into_iter()
+                .map(|error| LexError::InFile {
+                    path: path.display().to_string(),
+                    error: Box::new(error),
+                })
+                .collect();
+        }
+        errors.append(&mut nested);
+        if errors.is_empty() {
+            Ok(lexemes)
+        } else {
+            Err(LexErrors(errors))
+        }
+    }
+
+    /// Replaces INCLUDE directives by lexemes of files.
+    /// Errors of directives go to `errors`, errors of included files go to `nested`.
+    fn expand(
+        &mut self,
+        lexemes: Vec<Spanned<Lexeme>>,
+        errors: &mut Vec<LexError>,
+        nested: &mut Vec<LexError>,
+    ) -> Vec<Spanned<Lexeme>> {
+        let mut expanded = Vec::with_capacity(lexemes.len());
+        let mut lexemes = lexemes.into_iter().peekable();
+        while let Some(lexeme) = lexemes.next() {
+            match lexeme.node {
+                Lexeme::Keyword(Keyword::Include) => {}
+                _ => {
+                    expanded.push(lexeme);
+                    continue;
+                }
             }
-            // Если не спец-символ, то продолжаем накопление символов
-            None => {
-                ident.push(i);
+            while let Some(Lexeme::Special(Special::Space)) | Some(Lexeme::Special(Special::Tab)) =
+                lexemes.peek().map(|lexeme| &lexeme.node)
+            {
+                lexemes.next();
             }
+            let name = match lexemes.peek().map(|lexeme| &lexeme.node) {
+                Some(Lexeme::StringLit(name)) => name.clone(),
+                _ => {
+                    errors.push(LexError::BadInclude { span: lexeme.span });
+                    continue;
+                }
+            };
+            lexemes.next();
+            let (file, path) = match self.open_include(&name, lexeme.span) {
+                Ok(include) => include,
+                Err(err) => {
+                    errors.push(err);
+                    continue;
+                }
+            };
+            match self.lex(ReaderChars::new(BufReader::new(file)), path) {
+                Ok(included) => expanded.extend(included),
+                Err(LexErrors(mut included_errors)) => nested.append(&mut included_errors),
+            }
+        }
+        expanded
+    }
+
+    /// Opens file, which is named in INCLUDE at `span`.
+    /// Relative path is resolved against directory of including file.
+    fn open_include(&self, name: &str, span: Span) -> Result<(File, PathBuf), LexError> {
+        let directory = self
+            .chain
+            .last()
+            .and_then(|(path, _)| path.parent())
+            .unwrap_or_else(|| Path::new(""));
+        let path = directory.join(name);
+        if self.chain.len() >= MAX_INCLUDE_DEPTH {
+            return Err(LexError::IncludeTooDeep {
+                path: path.display().to_string(),
+                span,
+            });
+        }
+        let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        if let Some(start) = self.chain.iter().position(|(_, other)| *other == canonical) {
+            let chain = self.chain[start..]
+                .iter()
+                .map(|(path, _)| path)
+                .chain(Some(&path))
+                .map(|path| path.display().to_string())
+                .collect();
+            return Err(LexError::IncludeCycle { chain, span });
+        }
+        match File::open(&path) {
+            Ok(file) => Ok((file, path)),
+            Err(err) => Err(LexError::Include {
+                path: path.display().to_string(),
+                span,
+                error: err,
+            }),
+        }
+    }
+
+    fn into_tokens(self, lexemes: Vec<Spanned<Lexeme>>) -> Tokens {
+        (lexemes, self.interner, self.files)
+    }
+}
+
+/// Lexes program file. INCLUDE paths are relative to directory of the file.
+pub fn lexer(filename: &str) -> Result<Tokens, LexErrors> {
+    lexer_with_options(filename, &LexerOptions::default())
+}
+
+/// Lexes program file like `lexer`, with other comments, layout or whitespace
+pub fn lexer_with_options(filename: &str, options: &LexerOptions) -> Result<Tokens, LexErrors> {
+    let reader = BufReader::new(File::open(filename)?);
+    let mut includes = Includes::new(options);
+    let lexemes = includes.lex(ReaderChars::new(reader), PathBuf::from(filename))?;
+    Ok(includes.into_tokens(lexemes))
+}
+
+/// Lexes source text, which doesn't have to live on disk.
+/// Included files are searched relative to current directory.
+pub fn lex_source(source: &str) -> Result<Tokens, LexErrors> {
+    lex_source_with_options(source, &LexerOptions::default())
+}
+
+/// Lexes in-memory source text, the same as `lex_source`
+pub fn lexer_str(src: &str) -> Result<Tokens, LexErrors> {
+    lex_source(src)
+}
+
+/// Lexes source text like `lex_source`, with other comments, layout or whitespace
+pub fn lex_source_with_options(source: &str, options: &LexerOptions) -> Result<Tokens, LexErrors> {
+    let mut includes = Includes::new(options);
+    let lexemes = includes.lex(source.chars().map(Ok), PathBuf::new())?;
+    Ok(includes.into_tokens(lexemes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Text, line and column of lexemes of source
+    fn spans(source: &str, options: &LexerOptions) -> Vec<(String, u32, u32)> {
+        let (lexemes, interner, _) =
+            lex_source_with_options(source, options).expect("source must be lexed");
+        lexemes
+            .iter()
+            .map(|lexeme| {
+                let text = lexeme.node.display(&interner).to_string();
+                (text, lexeme.span.line, lexeme.span.col)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn spans_count_newlines_tabs_and_eof() {
+        let found = spans(
+            "GENERATE 10\n\tSEIZE\tBAR\nTERMINATE",
+            &LexerOptions::default(),
+        );
+        let expected = vec![
+            ("GENERATE", 1, 1),
+            (" ", 1, 9),
+            ("10", 1, 10),
+            ("\n", 1, 12),
+            ("\t", 2, 1),
+            ("SEIZE", 2, 9),
+            ("\t", 2, 14),
+            ("BAR", 2, 22),
+            ("\n", 2, 25),
+            ("TERMINATE", 3, 1),
+        ];
+        let expected: Vec<(String, u32, u32)> = expected
+            .into_iter()
+            .map(|(text, line, col)| (text.to_string(), line, col))
+            .collect();
+        assert_eq!(found, expected);
+        // Ширина табуляции задается настройками
+        let options = LexerOptions {
+            tab_width: 4,
+            ..LexerOptions::default()
         };
+        assert_eq!(spans("\tBAR", &options)[1], ("BAR".to_string(), 1, 5));
+    }
+
+    #[test]
+    fn lex_source_reads_keywords_specials_and_identifiers() {
+        let (lexemes, interner, _) =
+            lex_source("BAR SEIZE JOE\nADVANCE 2+X$CELL,P1\n").expect("source must be lexed");
+        let nodes: Vec<&Lexeme> = lexemes.iter().map(|lexeme| &lexeme.node).collect();
+        assert!(matches!(nodes[0], Lexeme::LabelDef(name) if interner.resolve(*name) == "BAR"));
+        assert!(matches!(nodes[1], Lexeme::Special(Special::Space)));
+        assert!(matches!(nodes[2], Lexeme::Keyword(Keyword::Seize)));
+        assert!(matches!(nodes[4], Lexeme::UserIdentity(name) if interner.resolve(*name) == "JOE"));
+        assert!(matches!(nodes[5], Lexeme::Special(Special::Newline)));
+        assert!(matches!(nodes[6], Lexeme::Keyword(Keyword::Advance)));
+        assert!(matches!(nodes[8], Lexeme::Number(GpssType::Integer(2))));
+        assert!(matches!(nodes[9], Lexeme::Special(Special::Plus)));
+        assert!(
+            matches!(nodes[10], Lexeme::Sna { family: SnaFamily::Savevalue, name } if name == "CELL")
+        );
+        assert!(matches!(nodes[11], Lexeme::Special(Special::Comma)));
+        assert!(
+            matches!(nodes[12], Lexeme::Sna { family: SnaFamily::Parameter, name } if name == "1")
+        );
+        assert!(matches!(nodes[13], Lexeme::Special(Special::Newline)));
+        assert_eq!(nodes.len(), 14);
+    }
+
+    #[test]
+    fn lex_source_reads_empty_input_and_input_without_line_feed() {
+        let (lexemes, _, _) = lex_source("").expect("empty source must be lexed");
+        assert!(lexemes.is_empty());
+        let found = spans("START 1", &LexerOptions::default());
+        let texts: Vec<&str> = found.iter().map(|(text, _, _)| text.as_str()).collect();
+        assert_eq!(texts, vec!["START", " ", "1"]);
+    }
+
+    #[test]
+    fn last_lexeme_is_kept_with_and_without_line_feed() {
+        for source in &["SEIZE JOE\nRELEASE", "SEIZE JOE\nRELEASE\n"] {
+            let (lexemes, _, _) = lex_source(source).expect("source must be lexed");
+            let last = lexemes
+                .iter()
+                .rev()
+                .find(|lexeme| !matches!(lexeme.node, Lexeme::Special(Special::Newline)))
+                .expect("keyword must be read");
+            assert!(matches!(last.node, Lexeme::Keyword(Keyword::Release)));
+            assert_eq!((last.span.line, last.span.col), (2, 1));
+        }
+        for source in &["SEIZE JOE", "SEIZE JOE\n"] {
+            let (lexemes, interner, _) = lex_source(source).expect("source must be lexed");
+            let names: Vec<&str> = lexemes
+                .iter()
+                .filter_map(|lexeme| match lexeme.node {
+                    Lexeme::UserIdentity(name) => Some(interner.resolve(name)),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(names, vec!["JOE"]);
+        }
+    }
+
+    /// Model of repeated lines, which is generated while it is read
+    struct SyntheticModel {
+        lines: usize,
+        position: usize,
     }
-    lexems
-}
\ No newline at end of file
+
+    const SYNTHETIC_LINE: &[u8] = b"WAIT    QUEUE   LINE,1          ; queue of server\n";
+
+    impl io::Read for SyntheticModel {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            if self.lines == 0 {
+                return Ok(0);
+            }
+            let rest = &SYNTHETIC_LINE[self.position..];
+            let count = rest.len().min(buffer.len());
+            buffer[..count].copy_from_slice(&rest[..count]);
+            self.position += count;
+            if self.position == SYNTHETIC_LINE.len() {
+                self.position = 0;
+                self.lines -= 1;
+            }
+            Ok(count)
+        }
+    }
+
+    #[test]
+    fn streaming_lexer_keeps_memory_bounded() {
+        let lines = 100_000;
+        // Около 5 Мб исходного текста
+        let model = SyntheticModel { lines, position: 0 };
+        let options = LexerOptions {
+            emit_whitespace: false,
+            ..LexerOptions::default()
+        };
+        let mut lexer = Lexer::new(ReaderChars::new(BufReader::new(model)), options);
+        let mut count = 0;
+        let mut most_pending = 0;
+        while let Some(lexeme) = lexer.next() {
+            lexeme.expect("synthetic model must be lexed");
+            count += 1;
+            most_pending = most_pending.max(lexer.pending.len());
+        }
+        // Очередь хранит лексемы одной строки, пока не ясно, есть ли у нее метка
+        assert!(most_pending <= 8, "{} lexemes are held", most_pending);
+        assert_eq!(lexer.interner().names.len(), 2);
+        // WAIT, QUEUE, LINE, запятая, 1 и перевод строки
+        assert_eq!(count, lines * 6);
+    }
+
+    #[test]
+    fn suppressed_whitespace_keeps_lines_and_separate_identifiers() {
+        let compact = LexerOptions {
+            emit_whitespace: false,
+            ..LexerOptions::default()
+        };
+        let found = spans("SEIZE   JOE\n\tADVANCE 2 3\n", &compact);
+        let expected = vec![
+            ("SEIZE", 1, 1),
+            ("JOE", 1, 9),
+            ("\n", 1, 12),
+            ("ADVANCE", 2, 9),
+            ("2", 2, 17),
+            ("3", 2, 19),
+            ("\n", 2, 20),
+        ];
+        let expected: Vec<(String, u32, u32)> = expected
+            .into_iter()
+            .map(|(text, line, col)| (text.to_string(), line, col))
+            .collect();
+        assert_eq!(found, expected);
+        let statements = LexerOptions {
+            emit_newlines: false,
+            ..compact
+        };
+        let texts: Vec<String> = spans("SEIZE JOE\nRELEASE JOE\n", &statements)
+            .into_iter()
+            .map(|(text, _, _)| text)
+            .collect();
+        assert_eq!(texts, vec!["SEIZE", "JOE", "RELEASE", "JOE"]);
+    }
+
+    #[test]
+    fn line_endings_and_byte_order_mark_give_the_same_lexemes() {
+        let file_spans = |filename: &str| -> Vec<(String, u32, u32)> {
+            let (lexemes, interner, _) = lexer(filename).expect("fixture must be lexed");
+            lexemes
+                .iter()
+                .map(|lexeme| {
+                    (
+                        lexeme.node.display(&interner).to_string(),
+                        lexeme.span.line,
+                        lexeme.span.col,
+                    )
+                })
+                .collect()
+        };
+        let expected = file_spans("models/line_endings/lf.gps");
+        // Строка комментария пропускается вместе с переводом строки
+        assert_eq!(expected[0], (" ".to_string(), 2, 1));
+        assert_eq!(expected.last(), Some(&("\n".to_string(), 4, 16)));
+        for fixture in &["crlf.gps", "cr.gps", "bom.gps"] {
+            let found = file_spans(&format!("models/line_endings/{}", fixture));
+            assert_eq!(found, expected, "{}", fixture);
+        }
+    }
+
+    #[test]
+    fn errors_are_collected_unless_lexer_is_strict() {
+        let source = "SEIZE 1.2.3\nSAVEVALUE \"OPEN\nRELEASE ?\nTERMINATE 1\n";
+        let LexErrors(errors) = lex_source(source).expect_err("source has errors");
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(matches!(&errors[0], LexError::MalformedNumber { text, .. } if text == "1.2.3"));
+        assert!(
+            matches!(errors[1], LexError::UnterminatedString { span } if (span.line, span.col) == (2, 11))
+        );
+        assert!(
+            matches!(errors[2], LexError::InvalidCharacter { ch: '?', span } if (span.line, span.col) == (3, 9))
+        );
+        // Незакрытая строка заканчивается с переводом строки, следующие строки читаются
+        let lines: Vec<u32> = Lexer::new(source.chars().map(Ok), LexerOptions::default())
+            .filter_map(Result::ok)
+            .filter(|lexeme| matches!(lexeme.node, Lexeme::Keyword(_)))
+            .map(|lexeme| lexeme.span.line)
+            .collect();
+        assert_eq!(lines, vec![1, 2, 3, 4]);
+        let strict = LexerOptions {
+            strict: true,
+            ..LexerOptions::default()
+        };
+        let LexErrors(errors) =
+            lex_source_with_options(source, &strict).expect_err("source has errors");
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], LexError::MalformedNumber { .. }));
+    }
+
+    #[test]
+    fn interner_stores_repeated_names_once() {
+        let lines = 100_000;
+        let source: String = (0..lines)
+            .map(|line| {
+                format!(
+                    "        SEIZE BARBER{0}\n        RELEASE BARBER{0}\n",
+                    line % 100
+                )
+            })
+            .collect();
+        let mut lexer = Lexer::new(source.chars().map(Ok), LexerOptions::default());
+        let mut occurrences = 0;
+        let mut occurrence_bytes = 0;
+        for lexeme in lexer.by_ref() {
+            if let Lexeme::UserIdentity(_) = lexeme.expect("model must be lexed").node {
+                occurrences += 1;
+            }
+        }
+        let interner = lexer.interner();
+        for line in 0..lines {
+            occurrence_bytes += 2 * format!("BARBER{}", line % 100).len();
+        }
+        let stored_bytes: usize = interner.names.iter().map(String::len).sum();
+        assert_eq!(occurrences, 2 * lines);
+        assert_eq!(interner.names.len(), 100);
+        assert_eq!(interner.resolve(interner.symbols["BARBER7"]), "BARBER7");
+        // Раньше каждое вхождение имени было отдельной строкой
+        assert!(stored_bytes * 1000 < occurrence_bytes);
+    }
+
+    #[test]
+    fn missing_file_is_an_error_and_strings_need_no_file() {
+        let LexErrors(errors) = lexer("models/no_such_model.gps").expect_err("file is missing");
+        assert!(matches!(errors[0], LexError::Io(_)));
+        let (lexemes, _, files) = lexer_str("TERMINATE 1").expect("string must be lexed");
+        assert_eq!(lexemes.len(), 3);
+        assert_eq!(
+            files.path(0).map(|path| path.as_os_str().is_empty()),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn comment_lines_give_no_lexemes() {
+        let comments = "* Barber shop\n  * indented comment\n; trailing comment form\n    ; after spaces\n\t; last line";
+        let (lexemes, _, _) = lex_source(comments).expect("comments must be lexed");
+        assert!(lexemes.is_empty(), "{:?}", lexemes);
+        // Строки комментариев учитываются в номерах следующих строк
+        let found = spans(
+            "; header\n* note\nSEIZE JOE ; take barber\n",
+            &LexerOptions::default(),
+        );
+        let texts: Vec<(&str, u32)> = found
+            .iter()
+            .map(|(text, line, _)| (text.as_str(), *line))
+            .collect();
+        assert_eq!(
+            texts,
+            vec![("SEIZE", 3), (" ", 3), ("JOE", 3), (" ", 3), ("\n", 3)]
+        );
+    }
+
+    #[test]
+    fn errors_of_included_file_have_its_span() {
+        let directory =
+            std::env::temp_dir().join(format!("rust_gpss_include_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(
+            directory.join("part.gps"),
+            "* included part\n        SEIZE ?\n",
+        )
+        .unwrap();
+        fs::write(
+            directory.join("main.gps"),
+            "        GENERATE 10\n        INCLUDE \"part.gps\"\n",
+        )
+        .unwrap();
+        let result = lexer(directory.join("main.gps").to_str().unwrap());
+        fs::remove_dir_all(&directory).unwrap();
+        let LexErrors(errors) = result.expect_err("included file has error");
+        assert_eq!(errors.len(), 1);
+        match &errors[0] {
+            LexError::InFile { path, error } => {
+                assert!(path.ends_with("part.gps"), "{}", path);
+                assert!(matches!(
+                    **error,
+                    LexError::InvalidCharacter { ch: '?', span } if (span.file, span.line, span.col) == (1, 2, 15)
+                ));
+            }
+            error => panic!("error must be in included file: {:?}", error),
+        }
+    }
+
+    #[test]
+    fn keywords_are_read_in_any_case_and_by_abbreviations() {
+        for spelling in &["generate", "GENERATE", "Gen", "GEN"] {
+            assert_eq!(
+                get_keyword_code(spelling),
+                Some(Keyword::Generate),
+                "{}",
+                spelling
+            );
+        }
+        assert_eq!(get_keyword_code("ter"), Some(Keyword::Terminate));
+        assert_eq!(get_keyword_code("GENER"), None);
+        assert_eq!(Keyword::Generate.to_string(), "GENERATE");
+        let (lexemes, _, _) = lex_source("gen 5\nTer 1\n").expect("source must be lexed");
+        assert!(matches!(
+            lexemes[0].node,
+            Lexeme::Keyword(Keyword::Generate)
+        ));
+        assert!(matches!(
+            lexemes[4].node,
+            Lexeme::Keyword(Keyword::Terminate)
+        ));
+    }
+
+    #[test]
+    fn hash_and_at_are_operators_and_star_is_left_to_parser() {
+        let (lexemes, _, _) = lex_source("X$A#3@2*P1").expect("expression must be lexed");
+        let nodes: Vec<&Lexeme> = lexemes.iter().map(|lexeme| &lexeme.node).collect();
+        assert_eq!(nodes.len(), 7);
+        assert!(matches!(nodes[1], Lexeme::Special(Special::Multiply)));
+        assert!(matches!(nodes[3], Lexeme::Special(Special::Modulus)));
+        assert!(matches!(nodes[5], Lexeme::Special(Special::Star)));
+        assert!(matches!(
+            nodes[6],
+            Lexeme::Sna {
+                family: SnaFamily::Parameter,
+                ..
+            }
+        ));
+    }
+}
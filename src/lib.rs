@@ -0,0 +1,342 @@
+#[macro_use]
+extern crate array_macro;
+#[macro_use]
+extern crate log;
+
+use std::fmt;
+
+mod interpreter;
+pub use interpreter::{
+    Event, EventObserver, FacilityReport, FacilityStats, GpssType, Interpreter, InterpreterState,
+    QueueReport, QueueStats, Relation, Report, RuntimeError, SaveMode, StepResult, StorageReport,
+    TableReport, ValidationError,
+};
+
+mod lexer;
+pub use lexer::{
+    lex_source, lex_source_with_options, lexer, lexer_str, lexer_with_options, Interner, Keyword,
+    LexError, LexErrors, LexMode, Lexeme, Lexer, LexerOptions, ReaderChars, SnaFamily, SourceMap,
+    Span, Spanned, Special, Symbol, Tokens, UnknownKeyword,
+};
+
+mod ast;
+pub use ast::{Expr, Operand, Statement};
+
+mod parser;
+pub use parser::{ParseError, ParseErrors, Symbols};
+
+mod validate;
+pub use validate::{validate, Finding, Severity};
+
+/// Error of loading GPSS model, before it runs
+#[derive(Debug)]
+pub enum ModelError {
+    Lex(LexErrors),
+    /// Errors of translation and paths of source files to locate them
+    Parse {
+        errors: ParseErrors,
+        files: SourceMap,
+    },
+    /// Findings of validation, at least one of them is an error
+    Invalid(Check),
+    /// Generated program points out of itself, it is an error of translation
+    Program(ValidationError),
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModelError::Lex(errors) => write!(f, "{}", errors),
+            ModelError::Parse { errors, files } => {
+                for error in &errors.0 {
+                    write_located(f, files, error.span(), error)?;
+                }
+                Ok(())
+            }
+            ModelError::Invalid(check) => write!(f, "{}", check),
+            ModelError::Program(error) => write!(f, "bad program: {}", error),
+        }
+    }
+}
+
+impl From<LexErrors> for ModelError {
+    fn from(errors: LexErrors) -> ModelError {
+        ModelError::Lex(errors)
+    }
+}
+
+/// Error of any stage of running GPSS program
+#[derive(Debug)]
+pub enum GpssError {
+    Model(ModelError),
+    /// Runtime error and index of instruction, which failed
+    Runtime {
+        error: RuntimeError,
+        instruction: usize,
+    },
+}
+
+impl fmt::Display for GpssError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GpssError::Model(error) => write!(f, "{}", error),
+            GpssError::Runtime { error, instruction } => {
+                write!(f, "instruction {}: {}", instruction, error)
+            }
+        }
+    }
+}
+
+/// Writes line of message with path of its source file
+fn write_located(
+    f: &mut fmt::Formatter,
+    files: &SourceMap,
+    span: Span,
+    message: &dyn fmt::Display,
+) -> fmt::Result {
+    // Путь есть у файла модели и у включенных файлов, но не у исходного текста в памяти
+    match files.path(span.file) {
+        Some(path) if !path.as_os_str().is_empty() => writeln!(f, "{}:{}", path.display(), message),
+        _ => writeln!(f, "{}", message),
+    }
+}
+
+/// Findings of validation of program file and paths of source files to locate them
+#[derive(Debug)]
+pub struct Check {
+    pub findings: Vec<Finding>,
+    files: SourceMap,
+}
+
+impl Check {
+    /// Whether model would fail or never finish
+    pub fn has_errors(&self) -> bool {
+        self.findings
+            .iter()
+            .any(|finding| finding.severity == Severity::Error)
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for finding in &self.findings {
+            write_located(f, &self.files, finding.span, finding)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<ModelError> for GpssError {
+    fn from(error: ModelError) -> GpssError {
+        GpssError::Model(error)
+    }
+}
+
+/// Loading of models: all of them are read, validated and translated the same way
+impl Interpreter {
+    /// Interpreter of GPSS program. Names of its labels and entities are in `symbols`.
+    pub fn from_source(src: &str) -> Result<Interpreter, ModelError> {
+        Self::from_tokens(lexer::lex_source(src)?, None)
+    }
+
+    /// Interpreter of GPSS program, which draws the same random numbers for the same seed
+    pub fn from_source_with_seed(src: &str, seed: u64) -> Result<Interpreter, ModelError> {
+        Self::from_tokens(lexer::lex_source(src)?, Some(seed))
+    }
+
+    /// Interpreter of GPSS program file. INCLUDE paths are relative to directory of the file.
+    pub fn from_file(filename: &str) -> Result<Interpreter, ModelError> {
+        Self::from_tokens(lexer::lexer(filename)?, None)
+    }
+
+    /// Interpreter of GPSS program file, which draws the same random numbers for the same seed
+    pub fn from_file_with_seed(filename: &str, seed: u64) -> Result<Interpreter, ModelError> {
+        Self::from_tokens(lexer::lexer(filename)?, Some(seed))
+    }
+
+    /// Interpreter of statements, which are built without source text
+    pub fn from_statements(statements: Vec<Statement>) -> Result<Interpreter, ModelError> {
+        Self::load(statements, Vec::new(), SourceMap::default(), None)
+    }
+
+    fn from_tokens(
+        (lexemes, interner, files): lexer::Tokens,
+        seed: Option<u64>,
+    ) -> Result<Interpreter, ModelError> {
+        let (statements, errors) = parser::read_statements(&lexemes, &interner);
+        Self::load(statements, errors, files, seed)
+    }
+
+    /// Validates and translates statements. Errors of reading are reported with errors of translation.
+    fn load(
+        statements: Vec<Statement>,
+        mut errors: Vec<ParseError>,
+        files: SourceMap,
+        seed: Option<u64>,
+    ) -> Result<Interpreter, ModelError> {
+        let findings = validate(&statements);
+        let program = parser::generate(statements, &mut errors);
+        // Ошибки трансляции важнее: с ними проверка находит и мнимые ошибки, например у пропущенной метки
+        if !errors.is_empty() {
+            return Err(ModelError::Parse {
+                errors: ParseErrors(errors),
+                files,
+            });
+        }
+        let check = Check { findings, files };
+        if check.has_errors() {
+            return Err(ModelError::Invalid(check));
+        }
+        for finding in &check.findings {
+            warn!("{}", finding);
+        }
+        Interpreter::from_program(program, seed).map_err(ModelError::Program)
+    }
+}
+
+/// Lexes, validates and translates GPSS program, like `Interpreter::from_source`.
+/// Interpreter is ready to run, its symbols name labels and entities of the model.
+pub fn parse_str(src: &str) -> Result<Interpreter, ModelError> {
+    Interpreter::from_source(src)
+}
+
+/// Loads GPSS program file like `parse_str`. INCLUDE paths are relative to directory of the file.
+pub fn parse_file(filename: &str) -> Result<Interpreter, ModelError> {
+    Interpreter::from_file(filename)
+}
+
+/// Lexes, parses and runs GPSS program. Returns reports of STARTs without NP operand, in order of program.
+pub fn run_source(src: &str) -> Result<Vec<Report>, GpssError> {
+    run(Interpreter::from_source(src)?)
+}
+
+/// Runs GPSS program like `run_source`. Runs with the same seed give the same reports.
+pub fn run_source_with_seed(src: &str, seed: u64) -> Result<Vec<Report>, GpssError> {
+    run(Interpreter::from_source_with_seed(src, seed)?)
+}
+
+/// Runs GPSS program from file. INCLUDE paths are relative to directory of the file.
+pub fn run_file(filename: &str) -> Result<Vec<Report>, GpssError> {
+    run(Interpreter::from_file(filename)?)
+}
+
+/// Runs GPSS program from file like `run_file`. Runs with the same seed give the same reports.
+pub fn run_file_with_seed(filename: &str, seed: u64) -> Result<Vec<Report>, GpssError> {
+    run(Interpreter::from_file_with_seed(filename, seed)?)
+}
+
+/// Generates code from statements and runs it, like `run_source`
+pub fn run_statements(statements: Vec<Statement>) -> Result<Vec<Report>, GpssError> {
+    run(Interpreter::from_statements(statements)?)
+}
+
+/// Statements of GPSS program file, for tools which analyze programs without running them
+pub fn parse_statements_file(filename: &str) -> Result<Vec<Statement>, ModelError> {
+    let (lexemes, interner, files) = lexer::lexer(filename)?;
+    parser::parse_statements(&lexemes, &interner)
+        .map_err(|errors| ModelError::Parse { errors, files })
+}
+
+/// Reads and validates program file without running it
+pub fn check_file(filename: &str) -> Result<Check, ModelError> {
+    let (lexemes, interner, files) = lexer::lexer(filename)?;
+    match parser::parse_statements(&lexemes, &interner) {
+        Ok(statements) => Ok(Check {
+            findings: validate(&statements),
+            files,
+        }),
+        Err(errors) => Err(ModelError::Parse { errors, files }),
+    }
+}
+
+/// Runs START statements of loaded model one by one
+fn run(mut interpreter: Interpreter) -> Result<Vec<Report>, GpssError> {
+    let mut reports = Vec::new();
+    loop {
+        match interpreter.run_next_start() {
+            Ok(Some(true)) => reports.push(interpreter.build_report()),
+            Ok(Some(false)) => {}
+            Ok(None) => return Ok(reports),
+            Err(error) => {
+                return Err(GpssError::Runtime {
+                    error,
+                    instruction: interpreter.current_instruction(),
+                })
+            }
+        }
+    }
+}
+
+/// Lexemes of GPSS program file, one per line with position. For debugging of lexer.
+pub fn dump_file_tokens(filename: &str) -> Result<String, LexErrors> {
+    let (lexemes, interner, files) = lexer::lexer(filename)?;
+    Ok(lexer::dump_tokens(&lexemes, &interner, &files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_program_file_runs() {
+        let reports = run_file("models/test_program.gps").expect("test program must run");
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].generated, 15);
+        assert_eq!(reports[0].terminated, 15);
+        assert!((reports[0].end_time - 0.47).abs() < 1e-6);
+    }
+
+    #[test]
+    fn parsed_program_is_ready_to_run() {
+        let mut interpreter =
+            parse_file("models/test_program.gps").expect("test program must load");
+        while interpreter
+            .run_next_start()
+            .expect("test program must run")
+            .is_some()
+        {}
+        assert_eq!(interpreter.build_report().terminated, 15);
+        let statements =
+            parse_statements_file("models/test_program.gps").expect("test program must be read");
+        assert!(!statements.is_empty());
+        assert!(parse_str("        GENERATE 1\n        SEIZE\n").is_err());
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_report() {
+        let source = "        GENERATE EXPONENTIAL 10
+        QUEUE LINE
+        SEIZE SERVER
+        DEPART LINE
+        ADVANCE 8,4
+        RELEASE SERVER
+        TERMINATE 1
+        START 200
+";
+        let first = run_source_with_seed(source, 42).expect("model must run");
+        let second = run_source_with_seed(source, 42).expect("model must run");
+        let other = run_source_with_seed(source, 43).expect("model must run");
+        assert_eq!(first[0].to_string(), second[0].to_string());
+        assert_eq!(first[0].to_json(), second[0].to_json());
+        assert_ne!(first[0].to_string(), other[0].to_string());
+    }
+
+    #[test]
+    fn barber_shop_card_deck_runs_in_fixed_format() {
+        let options = LexerOptions {
+            mode: LexMode::FixedFormat,
+            ..LexerOptions::default()
+        };
+        let tokens =
+            lexer_with_options("models/barber_shop.gps", &options).expect("deck must be lexed");
+        let interpreter =
+            Interpreter::from_tokens(tokens, Some(42)).expect("deck must be translated");
+        let reports = run(interpreter).expect("deck must run");
+        assert_eq!(reports.len(), 1);
+        assert!((reports[0].end_time - 480.0).abs() < 1e-6);
+        assert_eq!(reports[0].facilities.len(), 1);
+        assert_eq!(reports[0].queues.len(), 1);
+        // Последний клиент еще у парикмахера, когда таймер завершает прогон
+        assert_eq!(reports[0].generated, reports[0].terminated + 1);
+    }
+}
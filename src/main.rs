@@ -1,17 +1,82 @@
-#[macro_use]
-extern crate array_macro;
-#[macro_use]
-extern crate log;
 extern crate env_logger;
+extern crate rust_gpss;
 
-mod interpreter;
-use interpreter::Interpreter;
-
-mod lexer;
-use lexer::lexer;
+use std::env;
+use std::process;
 
 fn main() {
     env_logger::init();
-    let mut interpreter = Interpreter::build_test_interpreter();
-    interpreter.process();
+    // `--tokens FILE` печатает лексемы файла
+    // `--json FILE` печатает отчет модели из файла в формате JSON
+    // `--check FILE` проверяет модель из файла, не запуская ее
+    // `FILE` запускает модель из файла, без аргументов запускается тестовая программа
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.as_slice() {
+        [flag, filename] if flag == "--tokens" => {
+            match rust_gpss::dump_file_tokens(filename) {
+                Ok(tokens) => print!("{}", tokens),
+                Err(errors) => {
+                    eprint!("{}", errors);
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        [flag, filename] if flag == "--json" => {
+            match rust_gpss::run_file(filename) {
+                Ok(reports) => {
+                    for report in reports {
+                        println!("{}", report.to_json());
+                    }
+                }
+                Err(err) => {
+                    eprintln!("{}", err.to_string().trim_end());
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        [flag, filename] if flag == "--check" => {
+            match rust_gpss::check_file(filename) {
+                Ok(check) => {
+                    print!("{}", check);
+                    if check.has_errors() {
+                        process::exit(1);
+                    }
+                }
+                Err(err) => {
+                    eprintln!("{}", err.to_string().trim_end());
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        [filename] => {
+            match rust_gpss::run_file(filename) {
+                Ok(reports) => {
+                    for report in reports {
+                        print!("{}", report);
+                    }
+                }
+                Err(err) => {
+                    eprintln!("{}", err.to_string().trim_end());
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        _ => {}
+    }
+    // Тестовая программа встроена в исполняемый файл и читается как любая другая
+    match rust_gpss::run_source(include_str!("../models/test_program.gps")) {
+        Ok(reports) => {
+            for report in reports {
+                print!("{}", report);
+            }
+        }
+        Err(err) => {
+            eprintln!("{}", err.to_string().trim_end());
+            process::exit(1);
+        }
+    }
 }
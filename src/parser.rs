@@ -0,0 +1,2679 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::ast::{Expr, Operand, Statement};
+use crate::interpreter::{
+    Control, CountMode, FunctionMode, GateCondition, GpssType, Instructions, SaveMode,
+    TableArgument,
+};
+use crate::lexer::{Interner, Keyword, Lexeme, SnaFamily, Span, Spanned, Special};
+
+/// Error of translation of lexemes to instructions
+#[derive(Debug)]
+pub enum ParseError {
+    /// Token, which can not be at this place, and hint what can be there
+    UnexpectedToken {
+        found: String,
+        expected: &'static str,
+        span: Span,
+    },
+    /// Operand of wrong kind, like label instead of number
+    BadOperandType {
+        found: String,
+        expected: &'static str,
+        span: Span,
+    },
+    /// Required operand of block is omitted: its letter, like A, and meaning
+    MissingOperand {
+        block: Keyword,
+        operand: char,
+        meaning: &'static str,
+        span: Span,
+    },
+    /// Block is known to lexer, but can not be translated yet
+    UnsupportedBlock { block: Keyword, span: Span },
+    /// TRANSFER to label, which is not defined
+    UndefinedLabel { name: String, span: Span },
+    /// TEST without alternate exit must refuse entry until condition is true,
+    /// transacts can not wait on TEST yet
+    TestWithoutExit { span: Span },
+    /// Fraction of TRANSFER is not in (0,1)
+    BadFraction { span: Span },
+    /// Spread of time is greater than mean, so time could be negative
+    SpreadExceedsMean { block: Keyword, span: Span },
+    /// INITIAL sets the same savevalue twice: at `previous` and at `span`
+    DuplicateInitial {
+        name: String,
+        span: Span,
+        previous: Span,
+    },
+    /// Label is defined twice: at `previous` and at `span`
+    DuplicateLabel {
+        name: String,
+        span: Span,
+        previous: Span,
+    },
+    /// Block defines named entity, like VARIABLE, but has no label with its name
+    MissingName { block: Keyword, span: Span },
+    /// V$ refers to VARIABLE, which is not defined
+    UndefinedVariable { name: String, span: Span },
+    /// VARIABLE or FUNCTION refers to itself, directly or through other definitions
+    RecursiveReference { name: String, span: Span },
+    /// Count of points after FUNCTION differs from count in its header
+    FunctionPoints {
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    /// Arguments of FUNCTION points decrease
+    UnorderedPoints { span: Span },
+    /// Capacity of STORAGE is not a positive integer
+    BadCapacity { span: Span },
+    /// STORAGE is declared again with other capacity
+    ConflictingStorage {
+        name: String,
+        span: Span,
+        previous: Span,
+    },
+    /// Block refers to storage, which is not declared by STORAGE
+    UndefinedStorage { name: String, span: Span },
+    /// Width of TABLE classes is not positive or there are less than 2 classes
+    BadClasses { span: Span },
+    /// TABULATE refers to table, which is not defined by TABLE
+    UndefinedTable { name: String, span: Span },
+    /// MSAVEVALUE or MX$ refers to matrix, which is not defined by MATRIX
+    UndefinedMatrix { name: String, span: Span },
+    /// Termination count of START is not a positive integer
+    BadStartCount { span: Span },
+    /// Program has no START, so simulation would never run. Span is the end of program.
+    MissingStart { span: Span },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedToken {
+                found,
+                expected,
+                span,
+            } => write!(
+                f,
+                "{}:{}: expected {}, found {}",
+                span.line, span.col, expected, found
+            ),
+            ParseError::BadOperandType {
+                found,
+                expected,
+                span,
+            } => write!(
+                f,
+                "{}:{}: operand must be {}, found {}",
+                span.line, span.col, expected, found
+            ),
+            ParseError::MissingOperand {
+                block,
+                operand,
+                meaning,
+                span,
+            } => write!(
+                f,
+                "{}:{}: missing operand {} ({}) of {}",
+                span.line, span.col, operand, meaning, block
+            ),
+            ParseError::UnsupportedBlock { block, span } => {
+                write!(f, "{}:{}: block {} is not supported", span.line, span.col, block)
+            }
+            ParseError::UndefinedLabel { name, span } => {
+                write!(f, "{}:{}: undefined label {}", span.line, span.col, name)
+            }
+            ParseError::TestWithoutExit { span } => write!(
+                f,
+                "{}:{}: TEST without alternate exit is not supported, add label of exit",
+                span.line, span.col
+            ),
+            ParseError::BadFraction { span } => write!(
+                f,
+                "{}:{}: fraction of TRANSFER must be between 0 and 1",
+                span.line, span.col
+            ),
+            ParseError::SpreadExceedsMean { block, span } => write!(
+                f,
+                "{}:{}: spread of {} is greater than mean",
+                span.line, span.col, block
+            ),
+            ParseError::DuplicateInitial {
+                name,
+                span,
+                previous,
+            } => write!(
+                f,
+                "{}:{}: savevalue {} is already initialized at {}:{}",
+                span.line, span.col, name, previous.line, previous.col
+            ),
+            ParseError::DuplicateLabel {
+                name,
+                span,
+                previous,
+            } => write!(
+                f,
+                "{}:{}: duplicate label {}, first defined at {}:{}",
+                span.line, span.col, name, previous.line, previous.col
+            ),
+            ParseError::MissingName { block, span } => {
+                write!(f, "{}:{}: {} requires label with name", span.line, span.col, block)
+            }
+            ParseError::UndefinedVariable { name, span } => {
+                write!(f, "{}:{}: undefined variable {}", span.line, span.col, name)
+            }
+            ParseError::RecursiveReference { name, span } => {
+                write!(f, "{}:{}: {} refers to itself", span.line, span.col, name)
+            }
+            ParseError::FunctionPoints {
+                expected,
+                found,
+                span,
+            } => write!(
+                f,
+                "{}:{}: FUNCTION has {} points instead of {}",
+                span.line, span.col, found, expected
+            ),
+            ParseError::UnorderedPoints { span } => write!(
+                f,
+                "{}:{}: arguments of FUNCTION points must not decrease",
+                span.line, span.col
+            ),
+            ParseError::BadCapacity { span } => write!(
+                f,
+                "{}:{}: capacity of STORAGE must be a positive integer",
+                span.line, span.col
+            ),
+            ParseError::ConflictingStorage {
+                name,
+                span,
+                previous,
+            } => write!(
+                f,
+                "{}:{}: storage {} is already declared with other capacity at {}:{}",
+                span.line, span.col, name, previous.line, previous.col
+            ),
+            ParseError::UndefinedStorage { name, span } => {
+                write!(f, "{}:{}: undefined storage {}", span.line, span.col, name)
+            }
+            ParseError::BadClasses { span } => write!(
+                f,
+                "{}:{}: table needs positive width and at least 2 classes",
+                span.line, span.col
+            ),
+            ParseError::UndefinedTable { name, span } => {
+                write!(f, "{}:{}: undefined table {}", span.line, span.col, name)
+            }
+            ParseError::UndefinedMatrix { name, span } => {
+                write!(f, "{}:{}: undefined matrix {}", span.line, span.col, name)
+            }
+            ParseError::BadStartCount { span } => write!(
+                f,
+                "{}:{}: termination count of START must be a positive integer",
+                span.line, span.col
+            ),
+            ParseError::MissingStart { span } => write!(
+                f,
+                "{}:{}: program has no START, add `START n` to run simulation until n transacts terminate",
+                span.line, span.col
+            ),
+        }
+    }
+}
+
+/// All errors of translation, in order of source
+#[derive(Debug)]
+pub struct ParseErrors(pub Vec<ParseError>);
+
+impl fmt::Display for ParseErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for err in &self.0 {
+            writeln!(f, "{}", err)?;
+        }
+        Ok(())
+    }
+}
+
+impl ParseError {
+    /// Position of erroneous statement or token
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::UnexpectedToken { span, .. }
+            | ParseError::BadOperandType { span, .. }
+            | ParseError::MissingOperand { span, .. }
+            | ParseError::UnsupportedBlock { span, .. }
+            | ParseError::UndefinedLabel { span, .. }
+            | ParseError::SpreadExceedsMean { span, .. }
+            | ParseError::TestWithoutExit { span }
+            | ParseError::BadFraction { span }
+            | ParseError::DuplicateInitial { span, .. }
+            | ParseError::DuplicateLabel { span, .. }
+            | ParseError::MissingName { span, .. }
+            | ParseError::UndefinedVariable { span, .. }
+            | ParseError::RecursiveReference { span, .. }
+            | ParseError::FunctionPoints { span, .. }
+            | ParseError::UnorderedPoints { span }
+            | ParseError::BadCapacity { span }
+            | ParseError::ConflictingStorage { span, .. }
+            | ParseError::UndefinedStorage { span, .. }
+            | ParseError::BadClasses { span }
+            | ParseError::UndefinedTable { span, .. }
+            | ParseError::UndefinedMatrix { span, .. }
+            | ParseError::BadStartCount { span }
+            | ParseError::MissingStart { span } => *span,
+        }
+    }
+}
+
+/// Precedence climbing parser of VARIABLE expression.
+/// `#`, `/` and `@` bind tighter than `+` and `-`, operators of equal precedence are left associative.
+struct ExpressionParser<'a> {
+    lexemes: &'a [Spanned<Lexeme>],
+    position: usize,
+}
+
+impl<'a> ExpressionParser<'a> {
+    /// Parses the whole field as expression
+    fn parse(lexemes: &'a [Spanned<Lexeme>]) -> Result<Expr, ParseError> {
+        let mut parser = ExpressionParser {
+            lexemes,
+            position: 0,
+        };
+        let expression = parser.expression(1)?;
+        match lexemes.get(parser.position) {
+            Some(lexeme) => Err(ParseError::UnexpectedToken {
+                found: format!("{:?}", lexeme.node),
+                expected: "end of expression",
+                span: lexeme.span,
+            }),
+            None => Ok(expression),
+        }
+    }
+
+    fn precedence(operator: Special) -> Option<u8> {
+        match operator {
+            Special::Plus | Special::Minus => Some(1),
+            Special::Multiply | Special::Div | Special::Modulus => Some(2),
+            _ => None,
+        }
+    }
+
+    /// Reads operators with precedence not less than `min_precedence`
+    fn expression(&mut self, min_precedence: u8) -> Result<Expr, ParseError> {
+        let mut left = self.primary()?;
+        while let Some(Spanned {
+            node: Lexeme::Special(operator),
+            ..
+        }) = self.lexemes.get(self.position)
+        {
+            let precedence = match Self::precedence(*operator) {
+                Some(precedence) if precedence >= min_precedence => precedence,
+                _ => break,
+            };
+            self.position += 1;
+            let right = self.expression(precedence + 1)?;
+            left = Expr::Binary(Box::new(left), *operator, Box::new(right));
+        }
+        Ok(left)
+    }
+
+    /// Number, attribute, expression in parentheses or negation of one of them
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let lexeme = match self.lexemes.get(self.position) {
+            Some(lexeme) => lexeme,
+            None => {
+                return Err(ParseError::UnexpectedToken {
+                    found: "end of expression".into(),
+                    expected: "number, attribute or (",
+                    span: self.lexemes[self.lexemes.len() - 1].span,
+                })
+            }
+        };
+        self.position += 1;
+        match &lexeme.node {
+            Lexeme::Number(GpssType::Integer(value)) => {
+                Ok(Expr::Value(Operand::Number(f64::from(*value)), lexeme.span))
+            }
+            Lexeme::Number(GpssType::Float(value)) => {
+                Ok(Expr::Value(Operand::Number(f64::from(*value)), lexeme.span))
+            }
+            Lexeme::Sna { family, name } => Ok(Expr::Value(
+                Operand::Sna(*family, name.clone()),
+                lexeme.span,
+            )),
+            Lexeme::Special(Special::Minus) => {
+                // Унарный минус вычисляется как вычитание из нуля
+                let operand = self.primary()?;
+                let zero = Expr::Value(Operand::Number(0.0), lexeme.span);
+                Ok(Expr::Binary(
+                    Box::new(zero),
+                    Special::Minus,
+                    Box::new(operand),
+                ))
+            }
+            Lexeme::Special(Special::LParen) => {
+                let inner = self.expression(1)?;
+                match self.lexemes.get(self.position) {
+                    Some(Spanned {
+                        node: Lexeme::Special(Special::RParen),
+                        ..
+                    }) => {
+                        self.position += 1;
+                        Ok(inner)
+                    }
+                    Some(lexeme) => Err(ParseError::UnexpectedToken {
+                        found: format!("{:?}", lexeme.node),
+                        expected: ")",
+                        span: lexeme.span,
+                    }),
+                    None => Err(ParseError::UnexpectedToken {
+                        found: "end of expression".into(),
+                        expected: ")",
+                        span: lexeme.span,
+                    }),
+                }
+            }
+            other => Err(ParseError::UnexpectedToken {
+                found: format!("{:?}", other),
+                expected: "number, attribute or (",
+                span: lexeme.span,
+            }),
+        }
+    }
+}
+
+/// Splits line to fields, separated by spaces and tabs
+fn split_fields(line: &[Spanned<Lexeme>]) -> Vec<&[Spanned<Lexeme>]> {
+    line.split(|lexeme| {
+        matches!(
+            lexeme.node,
+            Lexeme::Special(Special::Space) | Lexeme::Special(Special::Tab)
+        )
+    })
+    .filter(|field| !field.is_empty())
+    .collect()
+}
+
+/// Value of number lexeme
+fn number_value(lexeme: &Lexeme) -> Option<f32> {
+    match lexeme {
+        Lexeme::Number(GpssType::Integer(value)) => Some(*value as f32),
+        Lexeme::Number(GpssType::Float(value)) => Some(*value),
+        _ => None,
+    }
+}
+
+/// Splits field to operands by commas outside of parentheses, like in `MX$COST(1,2),5`
+fn split_operands(field: &[Spanned<Lexeme>]) -> Vec<&[Spanned<Lexeme>]> {
+    let mut operands = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (index, lexeme) in field.iter().enumerate() {
+        match lexeme.node {
+            Lexeme::Special(Special::LParen) => depth += 1,
+            Lexeme::Special(Special::RParen) => depth -= 1,
+            Lexeme::Special(Special::Comma) if depth == 0 => {
+                operands.push(&field[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    operands.push(&field[start..]);
+    operands
+}
+
+/// Reads row or column of matrix cell: number or attribute
+fn cell_index(lexeme: &Spanned<Lexeme>, interner: &Interner) -> Result<Operand, ParseError> {
+    match &lexeme.node {
+        Lexeme::Number(GpssType::Integer(value)) => Ok(Operand::Number(f64::from(*value))),
+        Lexeme::Number(GpssType::Float(value)) => Ok(Operand::Number(f64::from(*value))),
+        Lexeme::Sna { family, name } => Ok(Operand::Sna(*family, name.clone())),
+        other => Err(ParseError::UnexpectedToken {
+            found: other.display(interner).to_string(),
+            expected: "row or column: number or attribute",
+            span: lexeme.span,
+        }),
+    }
+}
+
+/// Reads comma separated operands of field
+fn parse_operands(
+    field: &[Spanned<Lexeme>],
+    span: Span,
+    interner: &Interner,
+) -> Result<Vec<(Operand, Span)>, ParseError> {
+    split_operands(field)
+        .into_iter()
+        .map(|operand| match operand {
+            [] => Ok((Operand::Empty, span)),
+            [lexeme] => match &lexeme.node {
+                Lexeme::Number(GpssType::Integer(value)) => {
+                    Ok((Operand::Number(f64::from(*value)), lexeme.span))
+                }
+                Lexeme::Number(GpssType::Float(value)) => {
+                    Ok((Operand::Number(f64::from(*value)), lexeme.span))
+                }
+                Lexeme::UserIdentity(name) => Ok((
+                    Operand::Name(interner.resolve(*name).to_string()),
+                    lexeme.span,
+                )),
+                Lexeme::Sna { family, name } => {
+                    Ok((Operand::Sna(*family, name.clone()), lexeme.span))
+                }
+                Lexeme::StringLit(text) => Ok((Operand::Text(text.clone()), lexeme.span)),
+                other => Err(ParseError::UnexpectedToken {
+                    found: other.display(interner).to_string(),
+                    expected: "operand",
+                    span: lexeme.span,
+                }),
+            },
+            // Знак после имени задает накопление SAVEVALUE
+            [lexeme, Spanned {
+                node: Lexeme::Special(sign),
+                ..
+            }] if *sign == Special::Plus || *sign == Special::Minus => {
+                let name = match &lexeme.node {
+                    Lexeme::UserIdentity(name) => interner.resolve(*name).to_string(),
+                    Lexeme::Number(GpssType::Integer(value)) => value.to_string(),
+                    other => {
+                        return Err(ParseError::UnexpectedToken {
+                            found: other.display(interner).to_string(),
+                            expected: "name before + or -",
+                            span: lexeme.span,
+                        })
+                    }
+                };
+                let mode = if *sign == Special::Plus {
+                    SaveMode::Inc
+                } else {
+                    SaveMode::Dec
+                };
+                Ok((Operand::Accumulate(name, mode), lexeme.span))
+            }
+            // Ячейка матрицы: MX$COST(1,2)
+            [Spanned {
+                node:
+                    Lexeme::Sna {
+                        family: SnaFamily::Matrix,
+                        name,
+                    },
+                span,
+            }, Spanned {
+                node: Lexeme::Special(Special::LParen),
+                ..
+            }, row, Spanned {
+                node: Lexeme::Special(Special::Comma),
+                ..
+            }, column, Spanned {
+                node: Lexeme::Special(Special::RParen),
+                ..
+            }] => Ok((
+                Operand::MatrixCell(
+                    name.clone(),
+                    Box::new(cell_index(row, interner)?),
+                    Box::new(cell_index(column, interner)?),
+                ),
+                *span,
+            )),
+            [_, unexpected, ..] => Err(ParseError::UnexpectedToken {
+                found: unexpected.node.display(interner).to_string(),
+                expected: "',' between operands",
+                span: unexpected.span,
+            }),
+        })
+        .collect()
+}
+
+/// Distributions of time, which are written before operands of GENERATE and ADVANCE
+const DISTRIBUTIONS: &[&str] = &["EXPONENTIAL", "NORMAL"];
+
+/// Builds statement from line. Returns None for empty line.
+fn parse_statement(
+    line: &[Spanned<Lexeme>],
+    interner: &Interner,
+) -> Result<Option<Statement>, ParseError> {
+    let fields = split_fields(line);
+    let mut fields = fields.iter();
+    let mut field = match fields.next() {
+        Some(field) => field,
+        None => return Ok(None),
+    };
+    // Метку выделяет лексер: идентификатор в начале строки перед ключевым словом
+    let mut label = None;
+    if let [Spanned {
+        node: Lexeme::LabelDef(name),
+        span,
+    }] = field
+    {
+        label = Some((interner.resolve(*name).to_string(), *span));
+        field = match fields.next() {
+            Some(field) => field,
+            None => {
+                return Err(ParseError::UnexpectedToken {
+                    found: "end of line".into(),
+                    expected: "block after label",
+                    span: field[0].span,
+                })
+            }
+        };
+    }
+    let (block, span) = match field {
+        [Spanned {
+            node: Lexeme::Keyword(block),
+            span,
+        }] => (*block, *span),
+        _ => {
+            return Err(ParseError::UnexpectedToken {
+                found: field[0].node.display(interner).to_string(),
+                expected: "name of block",
+                span: field[0].span,
+            })
+        }
+    };
+    // Вспомогательный операнд есть у GATE и COUNT (режим) и у TEST (оператор отношения)
+    let mut auxiliary = None;
+    if block == Keyword::Gate || block == Keyword::Count || block == Keyword::Test {
+        let expected = match block {
+            Keyword::Test => "relational operator, like GE",
+            Keyword::Count => "condition of COUNT, U or SNE",
+            _ => "condition of GATE, like U or SNF",
+        };
+        auxiliary = match fields.next() {
+            Some([lexeme]) => match &lexeme.node {
+                Lexeme::UserIdentity(name) if block != Keyword::Test => Some((
+                    Operand::Name(interner.resolve(*name).to_string()),
+                    lexeme.span,
+                )),
+                Lexeme::Relop(relation) if block == Keyword::Test => {
+                    Some((Operand::Relation(*relation), lexeme.span))
+                }
+                other => {
+                    return Err(ParseError::UnexpectedToken {
+                        found: other.display(interner).to_string(),
+                        expected,
+                        span: lexeme.span,
+                    })
+                }
+            },
+            Some(field) => {
+                return Err(ParseError::UnexpectedToken {
+                    found: field[0].node.display(interner).to_string(),
+                    expected,
+                    span: field[0].span,
+                })
+            }
+            None => {
+                return Err(ParseError::MissingOperand {
+                    block,
+                    operand: 'X',
+                    meaning: expected,
+                    span,
+                })
+            }
+        };
+    }
+    // Распределение GENERATE и ADVANCE пишется перед операндами: `ADVANCE NORMAL 10,2`
+    if block == Keyword::Generate || block == Keyword::Advance {
+        if let Some([lexeme]) = fields.clone().next() {
+            if let Lexeme::UserIdentity(name) = lexeme.node {
+                let name = interner.resolve(name);
+                if DISTRIBUTIONS
+                    .iter()
+                    .any(|known| name.eq_ignore_ascii_case(known))
+                {
+                    auxiliary = Some((Operand::Name(name.to_uppercase()), lexeme.span));
+                    fields.next();
+                }
+            }
+        }
+    }
+    // Все, что после операндов, является комментарием.
+    // Операнд VARIABLE является выражением, а не списком через запятую.
+    let mut expression = None;
+    let operands = match fields.next() {
+        Some(field) if block == Keyword::Variable => {
+            expression = Some(ExpressionParser::parse(field)?);
+            Vec::new()
+        }
+        Some(field) => parse_operands(field, span, interner)?,
+        None => Vec::new(),
+    };
+    Ok(Some(Statement {
+        label,
+        block,
+        span,
+        auxiliary,
+        operands,
+        expression,
+        points: Vec::new(),
+    }))
+}
+
+/// Reads type of FUNCTION: letter C or D and count of points, like `C5`
+fn function_type(statement: &Statement) -> Result<(FunctionMode, usize), ParseError> {
+    match statement.operands.get(1) {
+        Some((Operand::Name(kind), span)) => {
+            let mut chars = kind.chars();
+            let mode = match chars.next() {
+                Some('C') | Some('c') => Some(FunctionMode::Continuous),
+                Some('D') | Some('d') => Some(FunctionMode::Discrete),
+                _ => None,
+            };
+            match (mode, chars.as_str().parse::<usize>()) {
+                (Some(mode), Ok(count)) if count > 0 => Ok((mode, count)),
+                _ => Err(ParseError::UnexpectedToken {
+                    found: kind.clone(),
+                    expected: "type of function, like C5 or D3",
+                    span: *span,
+                }),
+            }
+        }
+        None | Some((Operand::Empty, _)) => Err(missing_operand(statement, 1)),
+        Some((operand, span)) => Err(ParseError::BadOperandType {
+            found: operand.describe(),
+            expected: "type of function, like C5 or D3",
+            span: *span,
+        }),
+    }
+}
+
+/// Reads `X,Y/X,Y/...` points from the first field of follower lines until `count` points.
+/// Count and order of points are checked by code generation.
+fn function_points<'a>(
+    lines: &mut impl Iterator<Item = &'a [Spanned<Lexeme>]>,
+    count: usize,
+) -> Result<Vec<(f32, f32)>, ParseError> {
+    let mut points = Vec::new();
+    while points.len() < count {
+        let line = match lines.next() {
+            Some(line) => line,
+            None => break,
+        };
+        // Пустые строки и комментарии пропускаются, блок означает, что точки кончились
+        let field = match split_fields(line).first() {
+            Some(field) => *field,
+            None => continue,
+        };
+        if let Lexeme::Keyword(_) | Lexeme::LabelDef(_) = field[0].node {
+            break;
+        }
+        for pair in field.split(|lexeme| matches!(lexeme.node, Lexeme::Special(Special::Div))) {
+            match pair {
+                [x, Spanned {
+                    node: Lexeme::Special(Special::Comma),
+                    ..
+                }, y] => match (number_value(&x.node), number_value(&y.node)) {
+                    (Some(x), Some(y)) => points.push((x, y)),
+                    (None, _) => {
+                        return Err(ParseError::UnexpectedToken {
+                            found: format!("{:?}", x.node),
+                            expected: "number",
+                            span: x.span,
+                        })
+                    }
+                    (_, None) => {
+                        return Err(ParseError::UnexpectedToken {
+                            found: format!("{:?}", y.node),
+                            expected: "number",
+                            span: y.span,
+                        })
+                    }
+                },
+                _ => {
+                    let at = pair.first().unwrap_or(&field[0]);
+                    return Err(ParseError::UnexpectedToken {
+                        found: format!("{:?}", at.node),
+                        expected: "point X,Y",
+                        span: at.span,
+                    });
+                }
+            }
+        }
+    }
+    Ok(points)
+}
+
+/// Kind of operand, which schema checks before code generation
+#[derive(Clone, Copy)]
+enum OperandKind {
+    /// Number constant
+    Number,
+    /// Name of entity. Numbers are names too, like in `SEIZE 1`.
+    Name,
+    /// Label of block
+    Label,
+    /// Value, which is pushed to stack: number, string or attribute
+    Value,
+    /// Operand of several forms, like mode of TRANSFER, which code generation checks itself
+    Any,
+}
+
+/// Operand of block in schema: meaning for error messages, kind and value of omitted operand
+struct OperandSpec {
+    meaning: &'static str,
+    kind: OperandKind,
+    default: Option<f64>,
+    required: bool,
+}
+
+const fn required(meaning: &'static str, kind: OperandKind) -> OperandSpec {
+    OperandSpec {
+        meaning,
+        kind,
+        default: None,
+        required: true,
+    }
+}
+
+const fn optional(meaning: &'static str, kind: OperandKind) -> OperandSpec {
+    OperandSpec {
+        meaning,
+        kind,
+        default: None,
+        required: false,
+    }
+}
+
+const fn defaulted(meaning: &'static str, kind: OperandKind, default: f64) -> OperandSpec {
+    OperandSpec {
+        meaning,
+        kind,
+        default: Some(default),
+        required: false,
+    }
+}
+
+/// Operands A, B, C... of statements, which can be translated.
+/// New block needs an entry here and its code generation in `Parser::statement`.
+const SCHEMA: &[(Keyword, &[OperandSpec])] = {
+    use OperandKind::*;
+    &[
+        (
+            Keyword::Generate,
+            &[
+                defaulted("mean time", Number, 0.0),
+                defaulted("spread", Value, 0.0),
+                optional("offset", Number),
+                optional("limit count", Number),
+            ],
+        ),
+        (
+            Keyword::Advance,
+            &[
+                defaulted("mean time", Value, 0.0),
+                defaulted("spread", Value, 0.0),
+            ],
+        ),
+        (
+            Keyword::Terminate,
+            &[defaulted("termination count", Number, 0.0)],
+        ),
+        // Без A переход безусловный, C нужен только при режиме или доле в A
+        (
+            Keyword::Transfer,
+            &[
+                optional("mode or fraction", Any),
+                optional("label", Label),
+                optional("label", Label),
+            ],
+        ),
+        (
+            Keyword::Split,
+            &[required("count of copies", Value), required("label", Label)],
+        ),
+        (
+            Keyword::Loop,
+            &[
+                required("parameter number", Number),
+                required("label", Label),
+            ],
+        ),
+        (Keyword::Assemble, &[required("count of transacts", Number)]),
+        (Keyword::Gather, &[required("count of transacts", Number)]),
+        (Keyword::Match, &[required("label", Label)]),
+        (
+            Keyword::Test,
+            &[
+                required("value", Value),
+                required("value", Value),
+                optional("label", Label),
+            ],
+        ),
+        (
+            Keyword::Initial,
+            &[required("savevalue", Any), defaulted("value", Any, 1.0)],
+        ),
+        (
+            Keyword::Savevalue,
+            &[required("savevalue", Any), required("value", Value)],
+        ),
+        // Тип параметра в C (PB, PH, PF, PL) приводит значение к целому или дробному
+        (
+            Keyword::Assign,
+            &[
+                required("parameter number", Any),
+                required("value", Value),
+                optional("type of parameter", Name),
+            ],
+        ),
+        (
+            Keyword::Enter,
+            &[
+                required("storage", Name),
+                defaulted("count of units", Number, 1.0),
+            ],
+        ),
+        (
+            Keyword::Leave,
+            &[
+                required("storage", Name),
+                defaulted("count of units", Number, 1.0),
+            ],
+        ),
+        (Keyword::Seize, &[required("facility", Name)]),
+        (Keyword::Release, &[required("facility", Name)]),
+        (Keyword::Preempt, &[required("facility", Name)]),
+        (Keyword::Return, &[required("facility", Name)]),
+        (
+            Keyword::Queue,
+            &[
+                required("queue", Name),
+                defaulted("count of units", Number, 1.0),
+            ],
+        ),
+        (
+            Keyword::Depart,
+            &[
+                required("queue", Name),
+                defaulted("count of units", Number, 1.0),
+            ],
+        ),
+        (Keyword::Print, &[required("X$name, Pn or C1", Any)]),
+        (
+            Keyword::Tabulate,
+            &[required("table", Name), defaulted("weight", Number, 1.0)],
+        ),
+        (Keyword::Mark, &[optional("parameter number", Number)]),
+        (Keyword::Priority, &[required("priority", Number)]),
+        (
+            Keyword::Count,
+            &[
+                required("parameter number", Number),
+                required("lower entity", Name),
+                required("upper entity", Name),
+            ],
+        ),
+        (
+            Keyword::Gate,
+            &[
+                required("facility or storage", Name),
+                optional("label", Label),
+            ],
+        ),
+        (
+            Keyword::Start,
+            &[required("termination count", Number), optional("NP", Any)],
+        ),
+        (Keyword::Reset, &[]),
+        (Keyword::Clear, &[]),
+        (Keyword::Simulate, &[]),
+        (
+            Keyword::Function,
+            &[
+                required("argument", Value),
+                required("type of function", Any),
+            ],
+        ),
+        // Выражение VARIABLE хранится отдельно от операндов
+        (Keyword::Variable, &[]),
+        (Keyword::Storage, &[required("capacity", Number)]),
+        // A не используется, как и в GPSS World
+        (
+            Keyword::Matrix,
+            &[
+                optional("unused", Any),
+                required("count of rows", Number),
+                required("count of columns", Number),
+            ],
+        ),
+        (
+            Keyword::Msavevalue,
+            &[
+                required("matrix", Name),
+                required("row", Value),
+                required("column", Value),
+                required("value", Value),
+            ],
+        ),
+        (
+            Keyword::Table,
+            &[
+                required("M1, MPn or Pn", Any),
+                required("upper limit", Number),
+                required("width of classes", Number),
+                required("count of classes", Number),
+            ],
+        ),
+        (
+            Keyword::Qtable,
+            &[
+                required("queue", Name),
+                required("upper limit", Number),
+                required("width of classes", Number),
+                required("count of classes", Number),
+            ],
+        ),
+    ]
+};
+
+/// Schema of statement, None for block, which can not be translated yet
+fn operand_schema(block: Keyword) -> Option<&'static [OperandSpec]> {
+    SCHEMA
+        .iter()
+        .find(|(keyword, _)| *keyword == block)
+        .map(|(_, schema)| *schema)
+}
+
+/// Letter of operand by its index: A, B, C...
+fn operand_letter(index: usize) -> char {
+    (b'A' + index as u8) as char
+}
+
+/// Error for omitted operand, which has no default value
+fn missing_operand(statement: &Statement, index: usize) -> ParseError {
+    let meaning = operand_schema(statement.block)
+        .and_then(|schema| schema.get(index))
+        .map_or("operand", |spec| spec.meaning);
+    ParseError::MissingOperand {
+        block: statement.block,
+        operand: operand_letter(index),
+        meaning,
+        span: statement.span,
+    }
+}
+
+/// Checks operands of statement by its schema and replaces omitted operands by their defaults,
+/// so code generation gets only operands of expected kinds
+fn apply_schema(statement: &mut Statement) -> Result<(), ParseError> {
+    let schema = match operand_schema(statement.block) {
+        Some(schema) => schema,
+        None => return Ok(()),
+    };
+    if let Some((operand, span)) = statement.operands.get(schema.len()) {
+        return Err(ParseError::UnexpectedToken {
+            found: operand.describe(),
+            expected: "end of operands",
+            span: *span,
+        });
+    }
+    for (index, spec) in schema.iter().enumerate() {
+        if index == statement.operands.len() {
+            statement.operands.push((Operand::Empty, statement.span));
+        }
+        let (operand, span) = &statement.operands[index];
+        let valid = match (operand, spec.kind) {
+            (Operand::Empty, _) if spec.required => return Err(missing_operand(statement, index)),
+            (Operand::Empty, _) | (_, OperandKind::Any) => true,
+            (Operand::Number(_), OperandKind::Number)
+            | (Operand::Number(_), OperandKind::Name)
+            | (Operand::Name(_), OperandKind::Name)
+            | (Operand::Name(_), OperandKind::Label)
+            | (Operand::Number(_), OperandKind::Value)
+            | (Operand::Text(_), OperandKind::Value)
+            | (Operand::Sna(..), OperandKind::Value)
+            | (Operand::MatrixCell(..), OperandKind::Value) => true,
+            _ => false,
+        };
+        if !valid {
+            return Err(ParseError::BadOperandType {
+                found: operand.describe(),
+                expected: match spec.kind {
+                    OperandKind::Number => "number",
+                    OperandKind::Name => "name",
+                    OperandKind::Label => "label",
+                    _ => "value: number, string, X$, P, V$, FN$, RN or MX$",
+                },
+                span: *span,
+            });
+        }
+        if let (Operand::Empty, Some(default)) = (operand, spec.default) {
+            statement.operands[index].0 = Operand::Number(default);
+        }
+    }
+    Ok(())
+}
+
+/// Translator of statements to instructions and memory
+struct Parser {
+    instructions: Vec<Instructions>,
+    memory: Vec<GpssType>,
+    /// Label name to index of instruction and position of definition
+    labels: HashMap<String, (usize, Span)>,
+    /// Facility name to memory address
+    facilities: HashMap<String, usize>,
+    /// Queue name to queue id
+    queues: HashMap<String, usize>,
+    /// Storage name to storage id, capacity and position of declaration
+    storages: HashMap<String, (usize, u32, Span)>,
+    /// Savevalue name to memory address. Numbers are names too, like in `SAVEVALUE 3,5`.
+    savevalues: HashMap<String, usize>,
+    /// Savevalue name to position of its INITIAL statement
+    initialized: HashMap<String, Span>,
+    /// Jumps to patch after all labels are known: (instruction, label, span)
+    transfers: Vec<(usize, String, Span)>,
+    /// B exits of TRANSFER with fraction or BOTH, which are patched into its B operand
+    alternate_exits: Vec<(usize, String, Span)>,
+    /// VARIABLE name to its expression and position of definition
+    variables: HashMap<String, (Expr, Span)>,
+    /// FUNCTION name to its id, argument and position of definition
+    functions: HashMap<String, (usize, Expr, Span)>,
+    /// Capacities of storages, by storage id
+    storage_capacities: Vec<u32>,
+    /// Table name to table id, TABLE or QTABLE keyword and position of definition
+    tables: HashMap<String, (usize, Keyword, Span)>,
+    /// Arguments, upper limits of the first class, widths and counts of classes, by table id
+    table_definitions: Vec<(TableArgument, f32, f32, usize)>,
+    /// Points and modes of functions, by function id
+    function_definitions: Vec<(Vec<(f32, f32)>, FunctionMode)>,
+    /// Matrix name to matrix id and position of definition
+    matrices: HashMap<String, (usize, Span)>,
+    /// Counts of rows and columns of matrices, by matrix id
+    matrix_definitions: Vec<(usize, usize)>,
+    /// START, RESET and CLEAR statements in order of program
+    controls: Vec<Control>,
+    /// Variables and functions, which are being inlined now, to detect recursion
+    expanding: Vec<String>,
+}
+
+impl Parser {
+    fn new() -> Parser {
+        Parser {
+            instructions: Vec::new(),
+            memory: Vec::new(),
+            labels: HashMap::new(),
+            facilities: HashMap::new(),
+            queues: HashMap::new(),
+            storages: HashMap::new(),
+            savevalues: HashMap::new(),
+            initialized: HashMap::new(),
+            transfers: Vec::new(),
+            alternate_exits: Vec::new(),
+            variables: HashMap::new(),
+            functions: HashMap::new(),
+            storage_capacities: Vec::new(),
+            tables: HashMap::new(),
+            table_definitions: Vec::new(),
+            function_definitions: Vec::new(),
+            matrices: HashMap::new(),
+            matrix_definitions: Vec::new(),
+            controls: Vec::new(),
+            expanding: Vec::new(),
+        }
+    }
+
+    /// Allocates memory cell with constant and pushes it to stack
+    fn push_constant(&mut self, value: GpssType) {
+        self.memory.push(value);
+        self.instructions
+            .push(Instructions::Push(self.memory.len() - 1));
+    }
+
+    fn operand(statement: &Statement, index: usize) -> &Operand {
+        statement
+            .operands
+            .get(index)
+            .map_or(&Operand::Empty, |(operand, _)| operand)
+    }
+
+    /// Reads numeric operand. Omitted operands with defaults are already filled by schema.
+    fn number(statement: &Statement, index: usize) -> Result<f64, ParseError> {
+        match Self::operand(statement, index) {
+            Operand::Number(value) => Ok(*value),
+            Operand::Empty => Err(missing_operand(statement, index)),
+            operand => Err(ParseError::BadOperandType {
+                found: operand.describe(),
+                expected: "number",
+                span: statement.operands[index].1,
+            }),
+        }
+    }
+
+    /// Pushes value of operand to stack: constant, string, savevalue, parameter of transact
+    /// or variable
+    fn push_operand(&mut self, statement: &Statement, index: usize) -> Result<(), ParseError> {
+        match Self::operand(statement, index) {
+            operand @ Operand::Sna(..)
+            | operand @ Operand::Text(_)
+            | operand @ Operand::MatrixCell(..) => {
+                self.push_value(operand, statement.operands[index].1)
+            }
+            _ => {
+                let value = Self::number(statement, index)?;
+                self.push_constant(Self::numeric_constant(value));
+                Ok(())
+            }
+        }
+    }
+
+    fn push_value(&mut self, operand: &Operand, span: Span) -> Result<(), ParseError> {
+        match operand {
+            Operand::Number(value) => self.push_constant(Self::numeric_constant(*value)),
+            Operand::Text(text) => self.push_constant(GpssType::String(text.clone())),
+            Operand::Sna(SnaFamily::Savevalue, name) => {
+                let var_id = self.savevalue(name.clone());
+                self.instructions.push(Instructions::Push(var_id));
+            }
+            Operand::Sna(SnaFamily::Parameter, name) => match name.parse::<usize>() {
+                Ok(param_id) => self.instructions.push(Instructions::LoadParam(param_id)),
+                Err(_) => {
+                    return Err(ParseError::UnexpectedToken {
+                        found: format!("P${}", name),
+                        expected: "parameter number, like P1",
+                        span,
+                    })
+                }
+            },
+            Operand::Sna(SnaFamily::Variable, name) => return self.push_variable(name, span),
+            Operand::Sna(SnaFamily::Function, name) => return self.push_function(name, span),
+            Operand::Sna(SnaFamily::RandomNumber, _) => {
+                self.instructions.push(Instructions::Random)
+            }
+            Operand::Sna(SnaFamily::Transit, number) if number == "1" => {
+                self.instructions.push(Instructions::PushTransit(None))
+            }
+            Operand::Sna(SnaFamily::MarkParameter, param) if param.parse::<usize>().is_ok() => self
+                .instructions
+                .push(Instructions::PushTransit(param.parse().ok())),
+            Operand::MatrixCell(name, row, column) => {
+                let matrix_id = self.matrix(name.clone(), span)?;
+                self.push_value(row, span)?;
+                self.push_value(column, span)?;
+                self.instructions.push(Instructions::PushMatrix(matrix_id));
+            }
+            Operand::Sna(..)
+            | Operand::Empty
+            | Operand::Name(_)
+            | Operand::Relation(_)
+            | Operand::Accumulate(..) => {
+                return Err(ParseError::BadOperandType {
+                    found: operand.describe(),
+                    expected: "value: number, string, X$, P, V$, FN$, RN, M1, MPn or MX$",
+                    span,
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// Inlines expression of VARIABLE at the place of use
+    fn push_variable(&mut self, name: &str, span: Span) -> Result<(), ParseError> {
+        let expression = match self.variables.get(name) {
+            Some((expression, _)) => expression.clone(),
+            None => {
+                return Err(ParseError::UndefinedVariable {
+                    name: name.to_string(),
+                    span,
+                })
+            }
+        };
+        self.push_definition(format!("V${}", name), span, &expression)
+    }
+
+    /// Pushes argument of FUNCTION and calls it
+    fn push_function(&mut self, name: &str, span: Span) -> Result<(), ParseError> {
+        let (function_id, argument) = match self.functions.get(name) {
+            Some((function_id, argument, _)) => (*function_id, argument.clone()),
+            None => {
+                return Err(ParseError::UndefinedVariable {
+                    name: format!("FN${}", name),
+                    span,
+                })
+            }
+        };
+        self.push_definition(format!("FN${}", name), span, &argument)?;
+        self.instructions
+            .push(Instructions::CallFunction(function_id));
+        Ok(())
+    }
+
+    /// Pushes expression of definition, which must not refer to itself
+    fn push_definition(
+        &mut self,
+        reference: String,
+        span: Span,
+        expression: &Expr,
+    ) -> Result<(), ParseError> {
+        if self.expanding.contains(&reference) {
+            return Err(ParseError::RecursiveReference {
+                name: reference,
+                span,
+            });
+        }
+        self.expanding.push(reference);
+        let result = self.push_expression(expression);
+        self.expanding.pop();
+        result
+    }
+
+    /// Pushes both operands, then replaces them by result of operator
+    fn push_expression(&mut self, expression: &Expr) -> Result<(), ParseError> {
+        match expression {
+            Expr::Value(operand, span) => self.push_value(operand, *span),
+            Expr::Binary(left, operator, right) => {
+                self.push_expression(left)?;
+                self.push_expression(right)?;
+                self.instructions.push(match operator {
+                    Special::Plus => Instructions::Add,
+                    Special::Minus => Instructions::Sub,
+                    Special::Multiply => Instructions::Mul,
+                    Special::Div => Instructions::Div,
+                    _ => Instructions::Mod,
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// Defines function by header `NAME FUNCTION A,Cn` or `NAME FUNCTION A,Dn` and its n points.
+    /// Functions are defined in interpreter and produce no instructions by themselves.
+    fn define_function(&mut self, statement: Statement) -> Result<(), ParseError> {
+        let (name, span) = match &statement.label {
+            Some(label) => label.clone(),
+            None => {
+                return Err(ParseError::MissingName {
+                    block: statement.block,
+                    span: statement.span,
+                })
+            }
+        };
+        let argument = match Self::operand(&statement, 0) {
+            operand @ Operand::Number(_) | operand @ Operand::Sna(..) => {
+                Expr::Value(operand.clone(), statement.operands[0].1)
+            }
+            operand => {
+                return Err(ParseError::BadOperandType {
+                    found: operand.describe(),
+                    expected: "argument: number or attribute",
+                    span: statement.operands[0].1,
+                })
+            }
+        };
+        // Тип функции задается буквой C или D, за которой следует число точек
+        let (mode, count) = function_type(&statement)?;
+        let points = statement.points;
+        if points.len() != count {
+            return Err(ParseError::FunctionPoints {
+                expected: count,
+                found: points.len(),
+                span: statement.span,
+            });
+        }
+        if points.windows(2).any(|pair| pair[1].0 < pair[0].0) {
+            return Err(ParseError::UnorderedPoints {
+                span: statement.span,
+            });
+        }
+        let function_id = self.function_definitions.len();
+        self.function_definitions.push((points, mode));
+        if let Some((_, _, previous)) = self
+            .functions
+            .insert(name.clone(), (function_id, argument, span))
+        {
+            return Err(ParseError::DuplicateLabel {
+                name,
+                span,
+                previous,
+            });
+        }
+        Ok(())
+    }
+
+    /// Declares storage `NAME STORAGE capacity`. Repeated declaration must have the same capacity.
+    fn define_storage(&mut self, statement: Statement) -> Result<(), ParseError> {
+        let (name, span) = match &statement.label {
+            Some(label) => label.clone(),
+            None => {
+                return Err(ParseError::MissingName {
+                    block: statement.block,
+                    span: statement.span,
+                })
+            }
+        };
+        let capacity = Self::number(&statement, 0)?;
+        if capacity < 1.0 || capacity.fract() != 0.0 || capacity > f64::from(u32::MAX) {
+            return Err(ParseError::BadCapacity {
+                span: statement.operands[0].1,
+            });
+        }
+        let capacity = capacity as u32;
+        match self.storages.get(&name) {
+            Some(&(_, previous_capacity, _)) if previous_capacity == capacity => {}
+            Some(&(_, _, previous)) => {
+                return Err(ParseError::ConflictingStorage {
+                    name,
+                    span,
+                    previous,
+                })
+            }
+            None => {
+                let storage_id = self.storage_capacities.len();
+                self.storage_capacities.push(capacity);
+                self.storages.insert(name, (storage_id, capacity, span));
+            }
+        }
+        Ok(())
+    }
+
+    /// Defines matrix savevalue by `NAME MATRIX ,rows,columns`. Cells are zero at start.
+    fn define_matrix(&mut self, statement: Statement) -> Result<(), ParseError> {
+        let (name, span) = match &statement.label {
+            Some(label) => label.clone(),
+            None => {
+                return Err(ParseError::MissingName {
+                    block: statement.block,
+                    span: statement.span,
+                })
+            }
+        };
+        let rows = Self::units(&statement, 1)? as usize;
+        let columns = Self::units(&statement, 2)? as usize;
+        let matrix_id = self.matrix_definitions.len();
+        self.matrix_definitions.push((rows, columns));
+        if let Some((_, previous)) = self.matrices.insert(name.clone(), (matrix_id, span)) {
+            return Err(ParseError::DuplicateLabel {
+                name,
+                span,
+                previous,
+            });
+        }
+        Ok(())
+    }
+
+    /// Defines `NAME TABLE A,B,C,D` or `NAME QTABLE queue,B,C,D`. A is M1, MPn or Pn,
+    /// B is upper limit of the first class, C is width of classes and D is count of classes.
+    fn define_table(&mut self, statement: Statement) -> Result<(), ParseError> {
+        let (name, span) = match &statement.label {
+            Some(label) => label.clone(),
+            None => {
+                return Err(ParseError::MissingName {
+                    block: statement.block,
+                    span: statement.span,
+                })
+            }
+        };
+        let argument = match Self::operand(&statement, 0) {
+            _ if statement.block == Keyword::Qtable => {
+                TableArgument::Queue(self.queue(Self::entity_name(&statement, 0)?))
+            }
+            Operand::Sna(SnaFamily::Transit, number) if number == "1" => {
+                TableArgument::Transit(None)
+            }
+            Operand::Sna(SnaFamily::MarkParameter, param) if param.parse::<usize>().is_ok() => {
+                TableArgument::Transit(param.parse().ok())
+            }
+            Operand::Sna(SnaFamily::Parameter, param) if param.parse::<usize>().is_ok() => {
+                TableArgument::Parameter(param.parse().unwrap())
+            }
+            operand => {
+                return Err(ParseError::BadOperandType {
+                    found: operand.describe(),
+                    expected: "M1, MPn or Pn",
+                    span: statement.operands[0].1,
+                })
+            }
+        };
+        let upper_limit = Self::number(&statement, 1)?;
+        let width = Self::number(&statement, 2)?;
+        let count = Self::number(&statement, 3)?;
+        if width <= 0.0 {
+            return Err(ParseError::BadClasses {
+                span: statement.operands[2].1,
+            });
+        }
+        if count < 2.0 || count.fract() != 0.0 {
+            return Err(ParseError::BadClasses {
+                span: statement.operands[3].1,
+            });
+        }
+        let table_id = self.table_definitions.len();
+        self.table_definitions
+            .push((argument, upper_limit as f32, width as f32, count as usize));
+        if let Some((_, _, previous)) = self
+            .tables
+            .insert(name.clone(), (table_id, statement.block, span))
+        {
+            return Err(ParseError::DuplicateLabel {
+                name,
+                span,
+                previous,
+            });
+        }
+        Ok(())
+    }
+
+    /// Remembers expression of VARIABLE. Variables produce no instructions by themselves.
+    fn define_variable(&mut self, statement: Statement) -> Result<(), ParseError> {
+        let (name, span) = match statement.label {
+            Some(label) => label,
+            None => {
+                return Err(ParseError::MissingName {
+                    block: statement.block,
+                    span: statement.span,
+                })
+            }
+        };
+        let expression = match statement.expression {
+            Some(expression) => expression,
+            None => {
+                return Err(ParseError::MissingOperand {
+                    block: statement.block,
+                    operand: 'A',
+                    meaning: "expression",
+                    span: statement.span,
+                })
+            }
+        };
+        if let Some((_, previous)) = self.variables.insert(name.clone(), (expression, span)) {
+            return Err(ParseError::DuplicateLabel {
+                name,
+                span,
+                previous,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that operand is FN$, which modifies mean time A of GENERATE and ADVANCE
+    fn is_function(statement: &Statement, index: usize) -> bool {
+        matches!(
+            Self::operand(statement, index),
+            Operand::Sna(SnaFamily::Function, _)
+        )
+    }
+
+    /// Integer constant for whole number, Float constant otherwise.
+    /// Целые числа остаются целыми, чтобы их можно было сравнивать с целыми.
+    fn numeric_constant(value: f64) -> GpssType {
+        if value.fract() == 0.0 && value.abs() <= f64::from(i32::MAX) {
+            GpssType::Integer(value as i32)
+        } else {
+            GpssType::Float(value as f32)
+        }
+    }
+
+    /// Reads name of entity. Numbers are names too, like in `SEIZE 1`.
+    fn entity_name(statement: &Statement, index: usize) -> Result<String, ParseError> {
+        match Self::operand(statement, index) {
+            Operand::Number(value) => Ok(value.to_string()),
+            Operand::Name(name) => Ok(name.clone()),
+            Operand::Empty => Err(missing_operand(statement, index)),
+            operand => Err(ParseError::BadOperandType {
+                found: operand.describe(),
+                expected: "name",
+                span: statement.operands[index].1,
+            }),
+        }
+    }
+
+    /// Reads non-negative integer, like limit D of GENERATE or parameter number
+    fn count(statement: &Statement, index: usize) -> Result<u32, ParseError> {
+        let value = Self::number(statement, index)?;
+        let error = || ParseError::BadOperandType {
+            found: value.to_string(),
+            expected: "non-negative integer",
+            span: statement.operands[index].1,
+        };
+        if value.fract() != 0.0 {
+            return Err(error());
+        }
+        u32::try_from(value as i64).map_err(|_| error())
+    }
+
+    /// Reads count of units, like B of QUEUE, which must be a positive integer
+    fn units(statement: &Statement, index: usize) -> Result<u32, ParseError> {
+        match Self::count(statement, index) {
+            Ok(units) if units > 0 => Ok(units),
+            _ => Err(ParseError::BadOperandType {
+                found: Self::number(statement, index)?.to_string(),
+                expected: "positive integer count of units",
+                span: statement.operands[index].1,
+            }),
+        }
+    }
+
+    /// Reads label of jumping block with its position
+    fn label(statement: &Statement, index: usize) -> Result<(String, Span), ParseError> {
+        match Self::operand(statement, index) {
+            Operand::Name(label) => Ok((label.clone(), statement.operands[index].1)),
+            Operand::Empty => Err(missing_operand(statement, index)),
+            operand => Err(ParseError::BadOperandType {
+                found: operand.describe(),
+                expected: "label",
+                span: statement.operands[index].1,
+            }),
+        }
+    }
+
+    /// Memory address of facility, allocated at first reference
+    fn facility(&mut self, name: String) -> usize {
+        let memory = &mut self.memory;
+        *self.facilities.entry(name).or_insert_with(|| {
+            memory.push(GpssType::Facility(0));
+            memory.len() - 1
+        })
+    }
+
+    /// Memory address of savevalue, allocated at first reference with zero value
+    fn savevalue(&mut self, name: String) -> usize {
+        let memory = &mut self.memory;
+        *self.savevalues.entry(name).or_insert_with(|| {
+            memory.push(GpssType::Integer(0));
+            memory.len() - 1
+        })
+    }
+
+    /// Memory address of operand: savevalue `X$name` or cell with constant
+    fn memory_operand(&mut self, statement: &Statement, index: usize) -> Result<usize, ParseError> {
+        match Self::operand(statement, index) {
+            Operand::Sna(SnaFamily::Savevalue, name) => Ok(self.savevalue(name.clone())),
+            Operand::Number(value) => {
+                self.memory.push(Self::numeric_constant(*value));
+                Ok(self.memory.len() - 1)
+            }
+            operand => Err(ParseError::BadOperandType {
+                found: operand.describe(),
+                expected: "number or X$name",
+                span: statement.operands[index].1,
+            }),
+        }
+    }
+
+    /// Id of storage, which must be declared by STORAGE
+    fn storage(&self, statement: &Statement, index: usize) -> Result<usize, ParseError> {
+        let name = Self::entity_name(statement, index)?;
+        match self.storages.get(&name) {
+            Some(&(storage_id, _, _)) => Ok(storage_id),
+            None => Err(ParseError::UndefinedStorage {
+                name,
+                span: statement.operands[index].1,
+            }),
+        }
+    }
+
+    /// Id of matrix, which must be defined by MATRIX
+    fn matrix(&self, name: String, span: Span) -> Result<usize, ParseError> {
+        match self.matrices.get(&name) {
+            Some(&(matrix_id, _)) => Ok(matrix_id),
+            None => Err(ParseError::UndefinedMatrix { name, span }),
+        }
+    }
+
+    /// Id of queue, allocated at first reference
+    fn queue(&mut self, name: String) -> usize {
+        let count = self.queues.len();
+        *self.queues.entry(name).or_insert(count)
+    }
+
+    fn statement(&mut self, mut statement: Statement) -> Result<(), ParseError> {
+        let begin = self.instructions.len();
+        if let Some((label, span)) = &statement.label {
+            if let Some((_, previous)) = self.labels.insert(label.clone(), (begin, *span)) {
+                return Err(ParseError::DuplicateLabel {
+                    name: label.clone(),
+                    span: *span,
+                    previous,
+                });
+            }
+        }
+        apply_schema(&mut statement)?;
+        let distribution = match &statement.auxiliary {
+            Some((Operand::Name(name), span))
+                if statement.block == Keyword::Generate || statement.block == Keyword::Advance =>
+            {
+                Some((name.as_str(), *span))
+            }
+            _ => None,
+        };
+        match statement.block {
+            Keyword::Generate => {
+                let time = Self::number(&statement, 0)?;
+                let function = Self::is_function(&statement, 1);
+                let spread = if function {
+                    0.0
+                } else {
+                    Self::number(&statement, 1)?
+                };
+                // Смещение C и предел D передаются интерпретатору отдельной инструкцией перед GENERATE
+                let offset = match Self::operand(&statement, 2) {
+                    Operand::Empty => None,
+                    _ => {
+                        let offset = Self::number(&statement, 2)?;
+                        self.memory.push(Self::numeric_constant(offset));
+                        Some(self.memory.len() - 1)
+                    }
+                };
+                let limit = match Self::operand(&statement, 3) {
+                    Operand::Empty => None,
+                    _ => Some(Self::count(&statement, 3)?),
+                };
+                if offset.is_some() || limit.is_some() {
+                    self.instructions
+                        .push(Instructions::GenerateLimits(offset, limit));
+                }
+                let begin = self.instructions.len();
+                self.push_constant(Self::numeric_constant(time));
+                // Функция B умножается на среднее A, разброс B задает равномерное распределение в [A-B, A+B]
+                if let Some((name, span)) = distribution {
+                    if name != "EXPONENTIAL" {
+                        return Err(ParseError::UnexpectedToken {
+                            found: name.to_string(),
+                            expected: "EXPONENTIAL",
+                            span,
+                        });
+                    }
+                    // Экспоненциальное распределение задается только средним A
+                    if function || spread != 0.0 {
+                        return Err(ParseError::UnexpectedToken {
+                            found: Self::operand(&statement, 1).describe(),
+                            expected: "no spread B with EXPONENTIAL",
+                            span: statement.operands[1].1,
+                        });
+                    }
+                    self.instructions.push(Instructions::GenerateExp(begin));
+                } else if function {
+                    self.push_operand(&statement, 1)?;
+                    self.instructions.push(Instructions::Mul);
+                    self.instructions.push(Instructions::Generate(begin));
+                } else if spread != 0.0 {
+                    self.push_constant(Self::numeric_constant(spread));
+                    self.instructions.push(Instructions::GenerateUniform(begin));
+                } else {
+                    self.instructions.push(Instructions::Generate(begin));
+                }
+            }
+            Keyword::Advance => {
+                // ADVANCE NORMAL A,B: среднее A и отклонение B читаются из памяти при входе в блок
+                if let Some((name, span)) = distribution {
+                    if name != "NORMAL" {
+                        return Err(ParseError::UnexpectedToken {
+                            found: name.to_string(),
+                            expected: "NORMAL",
+                            span,
+                        });
+                    }
+                    let mean = self.memory_operand(&statement, 0)?;
+                    let std_dev = self.memory_operand(&statement, 1)?;
+                    self.instructions
+                        .push(Instructions::AdvanceNormal(mean, std_dev));
+                    return Ok(());
+                }
+                let function = Self::is_function(&statement, 1);
+                let spread = if function {
+                    0.0
+                } else {
+                    Self::number(&statement, 1)?
+                };
+                match Self::operand(&statement, 0) {
+                    // Время из параметра или сохраняемой величины известно только при входе в блок
+                    Operand::Sna(..) => self.push_operand(&statement, 0)?,
+                    _ => {
+                        let time = Self::number(&statement, 0)?;
+                        if spread > time {
+                            return Err(ParseError::SpreadExceedsMean {
+                                block: statement.block,
+                                span: statement.operands[1].1,
+                            });
+                        }
+                        self.push_constant(Self::numeric_constant(time));
+                    }
+                }
+                if function {
+                    self.push_operand(&statement, 1)?;
+                    self.instructions.push(Instructions::Mul);
+                    self.instructions.push(Instructions::Advance(begin));
+                } else if spread != 0.0 {
+                    self.push_constant(Self::numeric_constant(spread));
+                    self.instructions.push(Instructions::AdvanceUniform(begin));
+                } else {
+                    self.instructions.push(Instructions::Advance(begin));
+                }
+            }
+            Keyword::Terminate => {
+                let count = Self::count(&statement, 0)?;
+                self.push_constant(GpssType::UnsignedInteger(count));
+                self.instructions.push(Instructions::Terminate(begin));
+            }
+            Keyword::Transfer => match Self::operand(&statement, 0) {
+                // TRANSFER ,B - безусловный переход
+                Operand::Empty => {
+                    let (label, span) = Self::label(&statement, 1)?;
+                    self.transfers.push((begin, label, span));
+                    self.instructions.push(Instructions::Transfer(0));
+                }
+                // TRANSFER BOTH,B,C - в B, если он примет транзакт, иначе в C
+                Operand::Name(mode) if mode.eq_ignore_ascii_case("BOTH") => {
+                    let (label, span) = Self::label(&statement, 2)?;
+                    self.transfers.push((begin, label, span));
+                    if let Operand::Name(label) = Self::operand(&statement, 1) {
+                        self.alternate_exits
+                            .push((begin, label.clone(), statement.operands[1].1));
+                    }
+                    self.instructions
+                        .push(Instructions::TransferBoth(begin + 1, 0));
+                }
+                // TRANSFER A,B,C - в C с вероятностью A, иначе в B или в следующий блок
+                _ => {
+                    let fraction = Self::number(&statement, 0)?;
+                    if fraction <= 0.0 || fraction >= 1.0 {
+                        return Err(ParseError::BadFraction {
+                            span: statement.operands[0].1,
+                        });
+                    }
+                    let (label, span) = Self::label(&statement, 2)?;
+                    self.transfers.push((begin, label, span));
+                    if let Operand::Name(label) = Self::operand(&statement, 1) {
+                        self.alternate_exits
+                            .push((begin, label.clone(), statement.operands[1].1));
+                    }
+                    self.instructions
+                        .push(Instructions::TransferFraction(fraction, 0, begin + 1));
+                }
+            },
+            Keyword::Split => {
+                let (label, span) = Self::label(&statement, 1)?;
+                self.push_operand(&statement, 0)?;
+                self.transfers.push((self.instructions.len(), label, span));
+                self.instructions.push(Instructions::Split(0));
+            }
+            Keyword::Loop => {
+                let param_id = Self::count(&statement, 0)? as usize;
+                let (label, span) = Self::label(&statement, 1)?;
+                self.transfers.push((begin, label, span));
+                self.instructions.push(Instructions::Loop(param_id, 0));
+            }
+            Keyword::Assemble => {
+                let count = Self::units(&statement, 0)? as usize;
+                self.instructions.push(Instructions::Assemble(count));
+            }
+            Keyword::Gather => {
+                let count = Self::units(&statement, 0)? as usize;
+                self.instructions.push(Instructions::Gather(count));
+            }
+            Keyword::Match => {
+                let (label, span) = Self::label(&statement, 0)?;
+                self.transfers.push((begin, label, span));
+                self.instructions.push(Instructions::Match(0));
+            }
+            Keyword::Test => {
+                let relation = match statement.auxiliary {
+                    Some((Operand::Relation(relation), _)) => relation,
+                    _ => {
+                        return Err(ParseError::MissingOperand {
+                            block: statement.block,
+                            operand: 'X',
+                            meaning: "relational operator",
+                            span: statement.span,
+                        })
+                    }
+                };
+                // TEST без метки должен ждать выполнения условия, это пока не поддерживается
+                let label = match Self::operand(&statement, 2) {
+                    Operand::Name(label) => label.clone(),
+                    Operand::Empty => {
+                        return Err(ParseError::TestWithoutExit {
+                            span: statement.span,
+                        })
+                    }
+                    operand => {
+                        return Err(ParseError::BadOperandType {
+                            found: operand.describe(),
+                            expected: "label",
+                            span: statement.operands[2].1,
+                        })
+                    }
+                };
+                self.push_operand(&statement, 0)?;
+                self.push_operand(&statement, 1)?;
+                self.transfers
+                    .push((self.instructions.len(), label, statement.operands[2].1));
+                self.instructions.push(Instructions::Test(relation, 0));
+            }
+            // INITIAL задает значение ячейки до начала моделирования, инструкций не создает
+            Keyword::Initial => {
+                let name = match Self::operand(&statement, 0) {
+                    Operand::Sna(SnaFamily::Savevalue, name) => name.clone(),
+                    _ => Self::entity_name(&statement, 0)?,
+                };
+                let value = match Self::operand(&statement, 1) {
+                    Operand::Text(text) => GpssType::String(text.clone()),
+                    _ => Self::numeric_constant(Self::number(&statement, 1)?),
+                };
+                let span = statement.operands[0].1;
+                if let Some(previous) = self.initialized.insert(name.clone(), span) {
+                    return Err(ParseError::DuplicateInitial {
+                        name,
+                        span,
+                        previous,
+                    });
+                }
+                let var_id = self.savevalue(name);
+                self.memory[var_id] = value;
+            }
+            Keyword::Savevalue => match Self::operand(&statement, 0) {
+                Operand::Accumulate(name, mode) => {
+                    let var_id = self.savevalue(name.clone());
+                    let mode = *mode;
+                    self.push_operand(&statement, 1)?;
+                    self.instructions
+                        .push(Instructions::SaveValueOp(var_id, mode));
+                }
+                _ => {
+                    let var_id = self.savevalue(Self::entity_name(&statement, 0)?);
+                    self.push_operand(&statement, 1)?;
+                    self.instructions.push(Instructions::SaveValue(var_id));
+                }
+            },
+            // Строка и столбец вычисляются раньше значения, SaveMatrix снимает их последними
+            Keyword::Msavevalue => {
+                let matrix_id =
+                    self.matrix(Self::entity_name(&statement, 0)?, statement.operands[0].1)?;
+                self.push_operand(&statement, 1)?;
+                self.push_operand(&statement, 2)?;
+                self.push_operand(&statement, 3)?;
+                self.instructions.push(Instructions::SaveMatrix(matrix_id));
+            }
+            Keyword::Assign => {
+                let (param_id, mode) = match Self::operand(&statement, 0) {
+                    Operand::Number(_) => (Self::count(&statement, 0)? as usize, SaveMode::Set),
+                    Operand::Accumulate(name, mode) => match name.parse::<usize>() {
+                        Ok(param_id) => (param_id, *mode),
+                        Err(_) => {
+                            return Err(ParseError::UnexpectedToken {
+                                found: Self::operand(&statement, 0).describe(),
+                                expected: "parameter number, like 5+",
+                                span: statement.operands[0].1,
+                            })
+                        }
+                    },
+                    operand => {
+                        return Err(ParseError::BadOperandType {
+                            found: operand.describe(),
+                            expected: "parameter number",
+                            span: statement.operands[0].1,
+                        })
+                    }
+                };
+                self.push_operand(&statement, 1)?;
+                // Тип параметра C: PL хранит дробное значение, PF, PH и PB - целое с отбрасыванием дробной части
+                match Self::operand(&statement, 2) {
+                    Operand::Empty => {}
+                    Operand::Name(kind) if kind.eq_ignore_ascii_case("PL") => {
+                        self.instructions.push(Instructions::ToFloat)
+                    }
+                    Operand::Name(kind)
+                        if matches!(kind.to_uppercase().as_str(), "PF" | "PH" | "PB") =>
+                    {
+                        self.instructions.push(Instructions::ToInt)
+                    }
+                    operand => {
+                        return Err(ParseError::UnexpectedToken {
+                            found: operand.describe(),
+                            expected: "type of parameter: PB, PH, PF or PL",
+                            span: statement.operands[2].1,
+                        })
+                    }
+                }
+                match mode {
+                    SaveMode::Set => self.instructions.push(Instructions::Assign(param_id)),
+                    mode => self
+                        .instructions
+                        .push(Instructions::AssignOp(param_id, mode)),
+                }
+            }
+            Keyword::Enter => {
+                let storage_id = self.storage(&statement, 0)?;
+                let units = Self::units(&statement, 1)?;
+                self.instructions
+                    .push(Instructions::Enter(storage_id, units));
+            }
+            Keyword::Leave => {
+                let storage_id = self.storage(&statement, 0)?;
+                let units = Self::units(&statement, 1)?;
+                self.instructions
+                    .push(Instructions::Leave(storage_id, units));
+            }
+            Keyword::Seize => {
+                let fac_id = self.facility(Self::entity_name(&statement, 0)?);
+                self.instructions.push(Instructions::Seize(fac_id));
+            }
+            Keyword::Release => {
+                let fac_id = self.facility(Self::entity_name(&statement, 0)?);
+                self.instructions.push(Instructions::Release(fac_id));
+            }
+            Keyword::Preempt => {
+                let fac_id = self.facility(Self::entity_name(&statement, 0)?);
+                self.instructions.push(Instructions::Preempt(fac_id));
+            }
+            Keyword::Return => {
+                let fac_id = self.facility(Self::entity_name(&statement, 0)?);
+                self.instructions.push(Instructions::Return(fac_id));
+            }
+            Keyword::Queue => {
+                let queue_id = self.queue(Self::entity_name(&statement, 0)?);
+                let units = Self::units(&statement, 1)?;
+                self.instructions.push(Instructions::Queue(queue_id, units));
+            }
+            Keyword::Depart => {
+                let queue_id = self.queue(Self::entity_name(&statement, 0)?);
+                let units = Self::units(&statement, 1)?;
+                self.instructions
+                    .push(Instructions::Depart(queue_id, units));
+            }
+            // PRINT выводит сохраняемую величину, параметр транзакта или часы C1
+            Keyword::Print => match Self::operand(&statement, 0) {
+                Operand::Sna(SnaFamily::Savevalue, name) => {
+                    let var_id = self.savevalue(name.clone());
+                    self.instructions.push(Instructions::Print(var_id));
+                }
+                Operand::Sna(SnaFamily::Parameter, name) => match name.parse::<usize>() {
+                    Ok(param_id) => self.instructions.push(Instructions::PrintParam(param_id)),
+                    Err(_) => {
+                        return Err(ParseError::UnexpectedToken {
+                            found: format!("P${}", name),
+                            expected: "parameter number, like P1",
+                            span: statement.operands[0].1,
+                        })
+                    }
+                },
+                Operand::Name(name) if name.eq_ignore_ascii_case("C1") => {
+                    self.instructions.push(Instructions::PrintClock)
+                }
+                operand => {
+                    return Err(ParseError::BadOperandType {
+                        found: operand.describe(),
+                        expected: "X$name, Pn or C1",
+                        span: statement.operands[0].1,
+                    })
+                }
+            },
+            Keyword::Tabulate => {
+                let name = Self::entity_name(&statement, 0)?;
+                let span = statement.operands[0].1;
+                let weight = Self::units(&statement, 1)?;
+                match self.tables.get(&name) {
+                    Some(&(table_id, Keyword::Table, _)) => self
+                        .instructions
+                        .push(Instructions::Tabulate(table_id, weight)),
+                    // Таблицу очереди заполняет DEPART
+                    Some(_) => {
+                        return Err(ParseError::UnexpectedToken {
+                            found: format!("QTABLE {}", name),
+                            expected: "TABLE, QTABLE is filled by DEPART",
+                            span,
+                        })
+                    }
+                    None => return Err(ParseError::UndefinedTable { name, span }),
+                }
+            }
+            Keyword::Mark => {
+                let param_id = match Self::operand(&statement, 0) {
+                    Operand::Empty => None,
+                    _ => Some(Self::count(&statement, 0)? as usize),
+                };
+                self.instructions.push(Instructions::Mark(param_id));
+            }
+            Keyword::Priority => {
+                let priority = Self::count(&statement, 0)? as usize;
+                self.instructions.push(Instructions::Priority(priority));
+            }
+            Keyword::Gate => {
+                let (mnemonic, mnemonic_span) = match &statement.auxiliary {
+                    Some((Operand::Name(mnemonic), span)) => (mnemonic.clone(), *span),
+                    _ => {
+                        return Err(ParseError::MissingOperand {
+                            block: statement.block,
+                            operand: 'X',
+                            meaning: "condition",
+                            span: statement.span,
+                        })
+                    }
+                };
+                let condition = match mnemonic.to_uppercase().as_str() {
+                    "U" | "NU" => {
+                        let fac_id = self.facility(Self::entity_name(&statement, 0)?);
+                        GateCondition::from_mnemonic(&mnemonic, fac_id)
+                    }
+                    "SF" | "SNF" | "SE" | "SNE" => {
+                        let storage_id = self.storage(&statement, 0)?;
+                        GateCondition::from_mnemonic(&mnemonic, storage_id)
+                    }
+                    _ => None,
+                }
+                .ok_or(ParseError::UnexpectedToken {
+                    found: mnemonic.clone(),
+                    expected: "condition of GATE, like U or SNF",
+                    span: mnemonic_span,
+                })?;
+                // Без метки транзакт ждет, пока условие не станет истинным
+                match Self::operand(&statement, 1) {
+                    Operand::Name(label) => {
+                        self.transfers
+                            .push((begin, label.clone(), statement.operands[1].1));
+                        self.instructions.push(Instructions::Gate(condition, 0));
+                    }
+                    _ => self.instructions.push(Instructions::GateWait(condition)),
+                }
+            }
+            // Сущности от B до C считаются в порядке первого упоминания (приборы)
+            // или объявления (памяти)
+            Keyword::Count => {
+                let (mnemonic, mnemonic_span) = match &statement.auxiliary {
+                    Some((Operand::Name(mnemonic), span)) => (mnemonic.to_uppercase(), *span),
+                    _ => {
+                        return Err(ParseError::MissingOperand {
+                            block: statement.block,
+                            operand: 'X',
+                            meaning: "condition",
+                            span: statement.span,
+                        })
+                    }
+                };
+                let param_id = Self::count(&statement, 0)? as usize;
+                let (mode, low, high) = match mnemonic.as_str() {
+                    "U" => {
+                        let low = self.facility(Self::entity_name(&statement, 1)?);
+                        let high = self.facility(Self::entity_name(&statement, 2)?);
+                        (CountMode::FacilityUsed, low, high)
+                    }
+                    "SNE" => {
+                        let low = self.storage(&statement, 1)?;
+                        let high = self.storage(&statement, 2)?;
+                        (CountMode::StorageNotEmpty, low, high)
+                    }
+                    _ => {
+                        return Err(ParseError::UnexpectedToken {
+                            found: mnemonic,
+                            expected: "condition of COUNT, U or SNE",
+                            span: mnemonic_span,
+                        })
+                    }
+                };
+                if high < low {
+                    return Err(ParseError::UnexpectedToken {
+                        found: Self::entity_name(&statement, 2)?,
+                        expected: "entity, which is defined after lower entity B",
+                        span: statement.operands[2].1,
+                    });
+                }
+                self.instructions
+                    .push(Instructions::Count(mode, low..=high, param_id));
+            }
+            Keyword::Start => {
+                let count = match Self::count(&statement, 0) {
+                    Ok(count) if count > 0 => count,
+                    _ => {
+                        return Err(ParseError::BadStartCount {
+                            span: statement.operands[0].1,
+                        })
+                    }
+                };
+                // Операнд NP отключает стандартный отчет
+                let report = match Self::operand(&statement, 1) {
+                    Operand::Empty => true,
+                    Operand::Name(name) if name.eq_ignore_ascii_case("NP") => false,
+                    operand => {
+                        return Err(ParseError::BadOperandType {
+                            found: operand.describe(),
+                            expected: "NP",
+                            span: statement.operands[1].1,
+                        })
+                    }
+                };
+                self.controls.push(Control::Start(count, report));
+            }
+            Keyword::Reset => self.controls.push(Control::Reset),
+            Keyword::Clear => self.controls.push(Control::Clear),
+            // SIMULATE только открывает модель
+            Keyword::Simulate => {}
+            _ => {
+                return Err(ParseError::UnsupportedBlock {
+                    block: statement.block,
+                    span: statement.span,
+                })
+            }
+        }
+        Ok(())
+    }
+
+    fn label_target(
+        labels: &HashMap<String, (usize, Span)>,
+        label: String,
+        span: Span,
+    ) -> Result<usize, ParseError> {
+        match labels.get(&label) {
+            Some(&(target, _)) => Ok(target),
+            None => Err(ParseError::UndefinedLabel { name: label, span }),
+        }
+    }
+
+    /// Replaces label names of jumping blocks by instruction indices.
+    /// Undefined labels go to `errors`, so all of them are reported.
+    fn resolve_labels(&mut self, errors: &mut Vec<ParseError>) {
+        for (instruction_id, label, span) in self.alternate_exits.drain(..) {
+            let next = match Self::label_target(&self.labels, label, span) {
+                Ok(next) => next,
+                Err(error) => {
+                    errors.push(error);
+                    continue;
+                }
+            };
+            self.instructions[instruction_id] = match self.instructions[instruction_id] {
+                Instructions::TransferFraction(fraction, target, _) => {
+                    Instructions::TransferFraction(fraction, target, next)
+                }
+                Instructions::TransferBoth(_, second) => Instructions::TransferBoth(next, second),
+                _ => continue,
+            };
+        }
+        for (instruction_id, label, span) in self.transfers.drain(..) {
+            let target = match Self::label_target(&self.labels, label, span) {
+                Ok(target) => target,
+                Err(error) => {
+                    errors.push(error);
+                    continue;
+                }
+            };
+            self.instructions[instruction_id] = match self.instructions[instruction_id] {
+                Instructions::Split(_) => Instructions::Split(target),
+                Instructions::Match(_) => Instructions::Match(target),
+                Instructions::Gate(condition, _) => Instructions::Gate(condition, target),
+                Instructions::Loop(param_id, _) => Instructions::Loop(param_id, target),
+                Instructions::Test(relation, _) => Instructions::Test(relation, target),
+                Instructions::TransferFraction(fraction, _, next) => {
+                    Instructions::TransferFraction(fraction, target, next)
+                }
+                Instructions::TransferBoth(first, _) => Instructions::TransferBoth(first, target),
+                _ => Instructions::Transfer(target),
+            };
+        }
+    }
+}
+
+/// Names of model with ids, which interpreter uses for them, to inspect model after loading
+#[derive(Clone, Debug, Default)]
+pub struct Symbols {
+    /// Label names with indices of instructions
+    pub labels: HashMap<String, usize>,
+    /// Savevalue names with memory addresses. Numbers are names too, like `3` in `SAVEVALUE 3,5`.
+    pub savevalues: HashMap<String, usize>,
+    /// Facility names with memory addresses
+    pub facilities: HashMap<String, usize>,
+    /// Queue names with queue ids
+    pub queues: HashMap<String, usize>,
+    /// Storage names with storage ids
+    pub storages: HashMap<String, usize>,
+    /// Table names with table ids
+    pub tables: HashMap<String, usize>,
+    /// Function names with function ids
+    pub functions: HashMap<String, usize>,
+    /// Matrix names with matrix ids
+    pub matrices: HashMap<String, usize>,
+}
+
+/// Program for interpreter
+pub struct Program {
+    pub instructions: Vec<Instructions>,
+    /// Initial memory: constants, facilities and savevalues
+    pub memory: Vec<GpssType>,
+    /// Names of labels and entities
+    pub symbols: Symbols,
+    /// Capacities of storages, by storage id
+    pub storages: Vec<u32>,
+    /// Arguments, upper limits of the first class, widths and counts of classes, by table id
+    pub tables: Vec<(TableArgument, f32, f32, usize)>,
+    /// Points and modes of functions, by id of CallFunction
+    pub functions: Vec<(Vec<(f32, f32)>, FunctionMode)>,
+    /// Counts of rows and columns, by matrix id
+    pub matrices: Vec<(usize, usize)>,
+    /// START, RESET and CLEAR statements in order of program
+    pub controls: Vec<Control>,
+}
+
+/// Reads statements of program from lexemes. FUNCTION takes its points from follower lines,
+/// END finishes program and statements after it are skipped.
+/// Erroneous statement is skipped up to the end of its line, so all errors of program are reported.
+pub fn parse_statements(
+    lexemes: &[Spanned<Lexeme>],
+    interner: &Interner,
+) -> Result<Vec<Statement>, ParseErrors> {
+    let (statements, errors) = read_statements(lexemes, interner);
+    if errors.is_empty() {
+        Ok(statements)
+    } else {
+        Err(ParseErrors(errors))
+    }
+}
+
+/// Statements, which are read successfully, and errors of the others
+pub(crate) fn read_statements(
+    lexemes: &[Spanned<Lexeme>],
+    interner: &Interner,
+) -> (Vec<Statement>, Vec<ParseError>) {
+    let mut lines =
+        lexemes.split(|lexeme| matches!(lexeme.node, Lexeme::Special(Special::Newline)));
+    let mut statements = Vec::new();
+    let mut errors = Vec::new();
+    while let Some(line) = lines.next() {
+        let mut statement = match parse_statement(line, interner) {
+            Ok(Some(statement)) => statement,
+            Ok(None) => continue,
+            Err(error) => {
+                errors.push(error);
+                continue;
+            }
+        };
+        if statement.block == Keyword::End {
+            break;
+        }
+        // За FUNCTION следуют строки с точками
+        if statement.block == Keyword::Function {
+            let points =
+                function_type(&statement).and_then(|(_, count)| function_points(&mut lines, count));
+            match points {
+                Ok(points) => statement.points = points,
+                Err(error) => {
+                    errors.push(error);
+                    continue;
+                }
+            }
+        }
+        statements.push(statement);
+    }
+    (statements, errors)
+}
+
+/// Generates program for interpreter from statements.
+/// Labels are collected by the first pass over statements and patched into jumps by the second one,
+/// so jumps forward work as well as backward. VARIABLE, FUNCTION, STORAGE and TABLE statements are collected
+/// before the others, so blocks can refer to definitions below. Program must have at least one START.
+/// Errors are added to `errors`, which can hold errors of reading already, and all of them are sorted
+/// in order of source text.
+pub(crate) fn generate(statements: Vec<Statement>, errors: &mut Vec<ParseError>) -> Program {
+    let mut parser = Parser::new();
+    let end = statements.last().map_or(
+        Span {
+            file: 0,
+            line: 1,
+            col: 1,
+            offset: 0,
+        },
+        |statement| statement.span,
+    );
+    let (definitions, statements): (Vec<_>, Vec<_>) =
+        statements.into_iter().partition(|statement| {
+            matches!(
+                statement.block,
+                Keyword::Variable
+                    | Keyword::Function
+                    | Keyword::Storage
+                    | Keyword::Matrix
+                    | Keyword::Table
+                    | Keyword::Qtable
+            )
+        });
+    for mut definition in definitions {
+        if let Err(error) = apply_schema(&mut definition) {
+            errors.push(error);
+            continue;
+        }
+        let result = match definition.block {
+            Keyword::Function => parser.define_function(definition),
+            Keyword::Storage => parser.define_storage(definition),
+            Keyword::Matrix => parser.define_matrix(definition),
+            Keyword::Table | Keyword::Qtable => parser.define_table(definition),
+            _ => parser.define_variable(definition),
+        };
+        if let Err(error) = result {
+            errors.push(error);
+        }
+    }
+    for statement in statements {
+        if let Err(error) = parser.statement(statement) {
+            errors.push(error);
+        }
+    }
+    parser.resolve_labels(errors);
+    let has_start = parser
+        .controls
+        .iter()
+        .any(|control| matches!(control, Control::Start(..)));
+    if !has_start {
+        errors.push(ParseError::MissingStart { span: end });
+    }
+    // Определения разбираются раньше блоков, восстанавливаем порядок исходного текста.
+    // Ошибки чтения операторов уже в списке, поэтому сортируются вместе с ними.
+    errors.sort_by_key(|error| {
+        let span = error.span();
+        (span.file, span.offset)
+    });
+    let labels = parser
+        .labels
+        .into_iter()
+        .map(|(name, (instruction_id, _))| (name, instruction_id))
+        .collect();
+    let symbols = Symbols {
+        labels,
+        savevalues: parser.savevalues,
+        facilities: parser.facilities,
+        queues: parser.queues,
+        storages: parser
+            .storages
+            .into_iter()
+            .map(|(name, (storage_id, _, _))| (name, storage_id))
+            .collect(),
+        tables: parser
+            .tables
+            .into_iter()
+            .map(|(name, (table_id, _, _))| (name, table_id))
+            .collect(),
+        functions: parser
+            .functions
+            .into_iter()
+            .map(|(name, (function_id, _, _))| (name, function_id))
+            .collect(),
+        matrices: parser
+            .matrices
+            .into_iter()
+            .map(|(name, (matrix_id, _))| (name, matrix_id))
+            .collect(),
+    };
+    Program {
+        instructions: parser.instructions,
+        memory: parser.memory,
+        symbols,
+        storages: parser.storage_capacities,
+        tables: parser.table_definitions,
+        functions: parser.function_definitions,
+        matrices: parser.matrix_definitions,
+        controls: parser.controls,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Relation;
+    use crate::lexer::lex_source;
+
+    /// Program of source, which must be translated without errors
+    fn program(source: &str) -> Program {
+        let (lexemes, interner, _) = lex_source(source).expect("source must be lexed");
+        let (statements, mut errors) = read_statements(&lexemes, &interner);
+        let program = generate(statements, &mut errors);
+        assert!(errors.is_empty(), "{}", ParseErrors(errors));
+        program
+    }
+
+    /// Errors of translation of source, which must be lexed and read
+    fn errors(source: &str) -> Vec<ParseError> {
+        let (lexemes, interner, _) = lex_source(source).expect("source must be lexed");
+        let (statements, mut errors) = read_statements(&lexemes, &interner);
+        generate(statements, &mut errors);
+        errors
+    }
+
+    #[test]
+    fn integer_operands_are_checked() {
+        let errors = errors(
+            "        GENERATE 1,,,2.5
+        PRIORITY 1.5
+        GATHER 2.5
+        ASSEMBLE 0
+        TERMINATE 5000000000.0
+        START 1
+",
+        );
+        let lines: Vec<(u32, u32)> = errors
+            .iter()
+            .map(|error| match error {
+                ParseError::BadOperandType { span, .. } => (span.line, span.col),
+                other => panic!("unexpected error {}", other),
+            })
+            .collect();
+        assert_eq!(lines, vec![(1, 22), (2, 18), (3, 16), (4, 18), (5, 19)]);
+    }
+
+    #[test]
+    fn errors_name_blocks_as_in_gpss() {
+        let errors = errors(
+            "        GENERATE 1
+        ADVANCE 2,5
+        TERMINATE 1
+        START 1
+",
+        );
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0],
+            ParseError::SpreadExceedsMean {
+                block: Keyword::Advance,
+                span: Span {
+                    line: 2,
+                    col: 19,
+                    ..
+                },
+            }
+        ));
+    }
+
+    #[test]
+    fn errors_of_several_lines_are_reported() {
+        let errors = errors(
+            "        GENERATE 1
+HERE    ADVANCE 2
+HERE    ADVANCE 3
+        TRANSFER ,NOWHERE
+        SEIZE
+        TERMINATE 1
+        START 1
+",
+        );
+        // Ошибки в порядке строк, после каждой чтение продолжается со следующей строки
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(matches!(
+            &errors[0],
+            ParseError::DuplicateLabel {
+                name,
+                span: Span { line: 3, .. },
+                previous: Span { line: 2, .. },
+            } if name == "HERE"
+        ));
+        assert!(matches!(
+            &errors[1],
+            ParseError::UndefinedLabel {
+                name,
+                span: Span { line: 4, col: 19, .. },
+            } if name == "NOWHERE"
+        ));
+        assert!(matches!(
+            &errors[2],
+            ParseError::MissingOperand {
+                block: Keyword::Seize,
+                operand: 'A',
+                span: Span { line: 5, .. },
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn every_generate_computes_its_own_operands() {
+        let program = program(
+            "        GENERATE 2
+        TERMINATE 1
+        GENERATE 3,1
+        TERMINATE 1
+        START 1
+",
+        );
+        let generators: Vec<(usize, usize)> = program
+            .instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(instruction_id, instruction)| match *instruction {
+                Instructions::Generate(begin) | Instructions::GenerateUniform(begin) => {
+                    Some((begin, instruction_id))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(generators.len(), 2);
+        // Операнды второго GENERATE начинаются после TERMINATE первого сегмента
+        assert_eq!(generators[0], (0, 1));
+        assert!(matches!(
+            program.instructions[3],
+            Instructions::Terminate(2)
+        ));
+        assert_eq!(generators[1], (4, 6));
+        assert!(matches!(program.instructions[4], Instructions::Push(_)));
+        assert!(matches!(program.instructions[5], Instructions::Push(_)));
+    }
+
+    #[test]
+    fn generate_exponential_is_written_before_operands() {
+        let program = program(
+            "        GENERATE EXPONENTIAL 10
+        TERMINATE 1
+        START 1
+",
+        );
+        assert!(matches!(
+            program.instructions[1],
+            Instructions::GenerateExp(0)
+        ));
+        let errors = errors(
+            "        GENERATE EXPONENTIAL 10,2
+        TERMINATE 1
+        START 1
+",
+        );
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn advance_normal_takes_memory_addresses() {
+        let program = program(
+            "        GENERATE 10
+        ADVANCE NORMAL X$MEAN,2
+        TERMINATE 1
+        START 1
+",
+        );
+        let mean = program.symbols.savevalues["MEAN"];
+        match program.instructions[2] {
+            Instructions::AdvanceNormal(address, std_dev) => {
+                assert_eq!(address, mean);
+                assert_eq!(program.memory[std_dev], GpssType::Integer(2));
+            }
+            _ => panic!("ADVANCE NORMAL must be translated to AdvanceNormal"),
+        }
+        let errors = errors(
+            "        GENERATE NORMAL 10,2
+        ADVANCE EXPONENTIAL 10
+        ADVANCE NORMAL P1,2
+        START 1
+",
+        );
+        assert_eq!(errors.len(), 3);
+    }
+
+    #[test]
+    fn print_shows_clock() {
+        let program = program(
+            "        GENERATE 1,,,1
+        PRINT C1
+        TERMINATE 1
+        START 1
+",
+        );
+        assert!(matches!(program.instructions[3], Instructions::PrintClock));
+    }
+
+    #[test]
+    fn test_reads_six_relations_and_requires_exit() {
+        for (mnemonic, relation) in &[
+            ("E", Relation::E),
+            ("NE", Relation::NE),
+            ("G", Relation::G),
+            ("GE", Relation::GE),
+            ("L", Relation::L),
+            ("LE", Relation::LE),
+        ] {
+            let program = program(&format!(
+                "        GENERATE 1
+        TEST {} X$BACKLOG,50,OVERFLOW
+        TERMINATE 1
+OVERFLOW TERMINATE 1
+        START 1
+",
+                mnemonic
+            ));
+            let overflow = program.symbols.labels["OVERFLOW"];
+            assert!(
+                program.instructions.iter().any(
+                    |instruction| matches!(instruction, Instructions::Test(found, target)
+                        if found == relation && *target == overflow)
+                ),
+                "TEST {}",
+                mnemonic
+            );
+        }
+        let found = errors("        GENERATE 1\n        TEST GE X$BACKLOG,50\n        TERMINATE 1\n        START 1\n");
+        assert!(
+            matches!(found[..], [ParseError::TestWithoutExit { .. }]),
+            "{:?}",
+            found
+        );
+    }
+
+    #[test]
+    fn transfer_is_unconditional_or_fractional() {
+        let program = program(
+            "        GENERATE 1
+        TRANSFER ,NEXT
+NEXT    TRANSFER .25,,REJECT
+        TERMINATE 1
+REJECT  TERMINATE 1
+        START 1
+",
+        );
+        let labels = &program.symbols.labels;
+        let next = labels["NEXT"];
+        assert!(
+            matches!(program.instructions[next - 1], Instructions::Transfer(target) if target == next)
+        );
+        assert!(matches!(
+            program.instructions[next],
+            Instructions::TransferFraction(fraction, target, other)
+                if fraction == 0.25 && target == labels["REJECT"] && other == next + 1
+        ));
+        for fraction in &["0", "1", "1.5", "-.25"] {
+            let source = format!(
+                "        GENERATE 1\n        TRANSFER {},,REJECT\nREJECT  TERMINATE 1\n        START 1\n",
+                fraction
+            );
+            let found = errors(&source);
+            assert!(
+                matches!(found[..], [ParseError::BadFraction { .. }]),
+                "{}: {:?}",
+                fraction,
+                found
+            );
+        }
+    }
+}
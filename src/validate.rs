@@ -0,0 +1,335 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ast::{Operand, Statement};
+use crate::lexer::{Keyword, Span};
+
+/// Whether model can run with the finding
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    /// Model runs, but probably not as intended
+    Warning,
+    /// Model fails at runtime or never finishes
+    Error,
+}
+
+/// Semantic problem of syntactically correct model
+#[derive(Debug)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.span.line, self.span.col, severity, self.message
+        )
+    }
+}
+
+/// Name of entity in operand. Numbers are names too, like in `SEIZE 1`.
+fn entity_name(statement: &Statement, index: usize) -> Option<(String, Span)> {
+    match statement.operands.get(index) {
+        Some((Operand::Name(name), span)) => Some((name.clone(), *span)),
+        Some((Operand::Number(value), span)) => Some((value.to_string(), *span)),
+        _ => None,
+    }
+}
+
+/// Indices of operands, which are labels of blocks
+fn label_operands(statement: &Statement) -> &'static [usize] {
+    match statement.block {
+        Keyword::Transfer => &[1, 2],
+        Keyword::Test => &[2],
+        Keyword::Gate | Keyword::Split | Keyword::Loop => &[1],
+        Keyword::Match => &[0],
+        _ => &[],
+    }
+}
+
+/// Checks model before run: targets of jumps and MATCH, pairs of SEIZE and RELEASE, QUEUE and DEPART,
+/// declarations and capacities of storages, sources and sinks of transacts.
+/// Findings are in order of source text.
+pub fn validate(statements: &[Statement]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut labels = HashSet::new();
+    let mut storages = HashMap::new();
+    let mut seized = HashMap::new();
+    let mut released = HashSet::new();
+    let mut queued = HashSet::new();
+    let mut matches = HashSet::new();
+    for statement in statements {
+        match (statement.block, &statement.label) {
+            (Keyword::Storage, Some((name, _))) => {
+                let capacity = match statement.operands.first() {
+                    Some((Operand::Number(capacity), _)) => Some(*capacity),
+                    _ => None,
+                };
+                storages.insert(name.clone(), capacity);
+            }
+            (Keyword::Variable, _)
+            | (Keyword::Function, _)
+            | (Keyword::Table, _)
+            | (Keyword::Qtable, _) => {}
+            (Keyword::Match, Some((name, _))) => {
+                labels.insert(name.clone());
+                matches.insert(name.clone());
+            }
+            (_, Some((name, _))) => {
+                labels.insert(name.clone());
+            }
+            _ => {}
+        }
+        match (statement.block, entity_name(statement, 0)) {
+            (Keyword::Seize, Some((name, span))) | (Keyword::Preempt, Some((name, span))) => {
+                seized.entry(name).or_insert(span);
+            }
+            (Keyword::Release, Some((name, _))) | (Keyword::Return, Some((name, _))) => {
+                released.insert(name);
+            }
+            (Keyword::Queue, Some((name, _))) => {
+                queued.insert(name);
+            }
+            _ => {}
+        }
+    }
+    for statement in statements {
+        for &index in label_operands(statement) {
+            if let Some((Operand::Name(label), span)) = statement.operands.get(index) {
+                if !labels.contains(label) {
+                    findings.push(Finding {
+                        severity: Severity::Error,
+                        message: format!(
+                            "target {} of {} is not a label of block",
+                            label, statement.block
+                        ),
+                        span: *span,
+                    });
+                }
+            }
+        }
+        match (statement.block, entity_name(statement, 0)) {
+            // Непарный MATCH ждет вечно
+            (Keyword::Match, Some((label, span)))
+                if labels.contains(&label) && !matches.contains(&label) =>
+            {
+                findings.push(Finding {
+                    severity: Severity::Error,
+                    message: format!(
+                        "target {} of {} is not a MATCH block",
+                        label, statement.block
+                    ),
+                    span,
+                })
+            }
+            (Keyword::Depart, Some((name, span))) if !queued.contains(&name) => {
+                findings.push(Finding {
+                    severity: Severity::Error,
+                    message: format!("queue {} is departed, but never entered by QUEUE", name),
+                    span,
+                })
+            }
+            (Keyword::Enter, Some((name, span))) | (Keyword::Leave, Some((name, span))) => {
+                match (storages.get(&name), statement.operands.get(1)) {
+                    (None, _) => findings.push(Finding {
+                        severity: Severity::Error,
+                        message: format!("storage {} is not declared by STORAGE", name),
+                        span,
+                    }),
+                    // Такой транзакт не войдет никогда, даже в пустую память
+                    (Some(&Some(capacity)), Some((Operand::Number(units), span)))
+                        if *units > capacity =>
+                    {
+                        findings.push(Finding {
+                            severity: Severity::Error,
+                            message: format!(
+                                "{} of {} units exceeds capacity {} of storage {}",
+                                statement.block, units, capacity, name
+                            ),
+                            span: *span,
+                        })
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    for (name, span) in seized {
+        if !released.contains(&name) {
+            findings.push(Finding {
+                severity: Severity::Warning,
+                message: format!("facility {} is seized, but never released", name),
+                span,
+            });
+        }
+    }
+    // Модель целиком: ошибка указывает на START, который никогда не закончится
+    let start = statements
+        .iter()
+        .find(|statement| statement.block == Keyword::Start)
+        .or_else(|| statements.last())
+        .map(|statement| statement.span);
+    if let Some(span) = start {
+        if !statements
+            .iter()
+            .any(|statement| statement.block == Keyword::Generate)
+        {
+            findings.push(Finding {
+                severity: Severity::Error,
+                message: "model has no GENERATE, so no transacts are created".into(),
+                span,
+            });
+        }
+        // Операнд-атрибут может оказаться положительным, такой TERMINATE считается уменьшающим
+        let decrements = statements.iter().any(|statement| {
+            statement.block == Keyword::Terminate
+                && match statement.operands.first() {
+                    Some((Operand::Number(count), _)) => *count > 0.0,
+                    None | Some((Operand::Empty, _)) => false,
+                    Some(_) => true,
+                }
+        });
+        if !decrements {
+            findings.push(Finding {
+                severity: Severity::Error,
+                message: "no TERMINATE decrements termination count, so START never ends".into(),
+                span,
+            });
+        }
+    }
+    findings.sort_by_key(|finding| (finding.span.file, finding.span.offset));
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex_source;
+    use crate::parser::read_statements;
+
+    /// Findings of model, which must be lexed and read without errors
+    fn findings(source: &str) -> Vec<Finding> {
+        let (lexemes, interner, _) = lex_source(source).expect("source must be lexed");
+        let (statements, errors) = read_statements(&lexemes, &interner);
+        assert!(errors.is_empty(), "{:?}", errors);
+        validate(&statements)
+    }
+
+    /// The only finding of model
+    fn finding(source: &str) -> Finding {
+        let mut findings = findings(source);
+        assert_eq!(findings.len(), 1, "{:?}", findings);
+        findings.remove(0)
+    }
+
+    #[test]
+    fn jump_to_undefined_label_is_error() {
+        let finding = finding(
+            "        GENERATE 2
+        TRANSFER ,NOWHERE
+        TERMINATE 1
+        START 10
+",
+        );
+        assert_eq!(finding.severity, Severity::Error);
+        assert_eq!(finding.span.line, 2);
+        assert_eq!(
+            finding.message,
+            "target NOWHERE of TRANSFER is not a label of block"
+        );
+    }
+
+    #[test]
+    fn match_with_block_other_than_match_is_error() {
+        let finding = finding(
+            "        GENERATE 2
+HERE    MATCH THERE
+THERE   ADVANCE 1
+        TERMINATE 1
+        START 10
+",
+        );
+        assert_eq!(finding.severity, Severity::Error);
+        assert_eq!(finding.span.line, 2);
+        assert_eq!(
+            finding.message,
+            "target THERE of MATCH is not a MATCH block"
+        );
+    }
+
+    #[test]
+    fn depart_without_queue_is_error() {
+        let finding = finding(
+            "        GENERATE 2
+        DEPART LINE
+        TERMINATE 1
+        START 10
+",
+        );
+        assert_eq!(finding.severity, Severity::Error);
+        assert_eq!(finding.span.line, 2);
+    }
+
+    #[test]
+    fn seize_without_release_is_warning() {
+        let finding = finding(
+            "        GENERATE 2
+        SEIZE MACHINE
+        TERMINATE 1
+        START 10
+",
+        );
+        assert_eq!(finding.severity, Severity::Warning);
+        assert_eq!(finding.span.line, 2);
+    }
+
+    #[test]
+    fn model_without_generate_is_error() {
+        let finding = finding(
+            "        TERMINATE 1
+        START 10
+",
+        );
+        assert_eq!(finding.severity, Severity::Error);
+        assert_eq!(finding.span.line, 2);
+    }
+
+    #[test]
+    fn model_without_decrementing_terminate_is_error() {
+        let finding = finding(
+            "        GENERATE 2
+        TERMINATE
+        START 10
+",
+        );
+        assert_eq!(finding.severity, Severity::Error);
+        assert_eq!(finding.span.line, 3);
+    }
+
+    #[test]
+    fn units_over_capacity_are_error() {
+        let findings = findings(
+            "SERVERS STORAGE 3
+        GENERATE 2
+        ENTER SERVERS,4
+        ADVANCE 5
+        LEAVE SERVERS,3
+        TERMINATE 1
+        START 10
+",
+        );
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::Error);
+        assert_eq!((findings[0].span.line, findings[0].span.col), (3, 23));
+        assert!(findings[0].message.starts_with("ENTER of 4 units"));
+    }
+}
@@ -0,0 +1,47 @@
+extern crate rust_gpss;
+
+use std::process::Command;
+
+use rust_gpss::{check_file, Severity};
+
+#[test]
+fn check_file_reports_findings_in_source_order() {
+    let check = check_file("models/invalid.gps").expect("model must be read");
+    assert!(check.has_errors());
+    let findings: Vec<(Severity, u32)> = check
+        .findings
+        .iter()
+        .map(|finding| (finding.severity, finding.span.line))
+        .collect();
+    assert_eq!(
+        findings,
+        vec![
+            (Severity::Warning, 3),
+            (Severity::Error, 4),
+            (Severity::Error, 5),
+        ]
+    );
+    assert!(check_file("models/test_program.gps")
+        .expect("test program must be read")
+        .findings
+        .is_empty());
+}
+
+#[test]
+fn check_mode_prints_findings_and_fails_without_running() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rust_gpss"))
+        .args(["--check", "models/invalid.gps"])
+        .output()
+        .expect("binary must start");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 3, "{}", stdout);
+    assert!(stdout.contains("models/invalid.gps:5:"), "{}", stdout);
+    assert!(!stdout.contains("END TIME"), "{}", stdout);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rust_gpss"))
+        .args(["--check", "models/test_program.gps"])
+        .output()
+        .expect("binary must start");
+    assert!(output.status.success());
+}
@@ -0,0 +1,45 @@
+extern crate rust_gpss;
+
+use rust_gpss::{FacilityStats, GpssType, Interpreter, QueueStats, StepResult};
+
+#[test]
+fn debugger_steps_model_through_public_api() {
+    let mut interpreter = Interpreter::from_source(
+        "        GENERATE 4,,,2
+        QUEUE LINE
+        SEIZE BARBER
+        DEPART LINE
+        ADVANCE 3
+        RELEASE BARBER
+        TERMINATE 1
+        START 2
+",
+    )
+    .expect("model must be valid");
+    interpreter.set_start_count(2);
+    let mut steps = 0;
+    let mut saw_operand = false;
+    loop {
+        match interpreter.step() {
+            StepResult::Running => {}
+            StepResult::Halted => break,
+            StepResult::Exhausted(remaining) => {
+                panic!("{} transacts are not terminated", remaining)
+            }
+            StepResult::Error(error) => panic!("step failed: {}", error),
+        }
+        saw_operand |= matches!(interpreter.stack_top(), Some(GpssType::Integer(_)));
+        steps += 1;
+        assert!(steps < 1000, "model does not halt");
+    }
+    assert!(saw_operand);
+    assert_eq!(interpreter.current_time(), 11_000);
+    let line: &QueueStats = interpreter.queue_stats("LINE").expect("queue must be used");
+    assert_eq!(line.entries(), 2);
+    assert_eq!(line.zero_entries(), 2);
+    let barber_id = interpreter.symbols().facilities["BARBER"];
+    let barber: &FacilityStats = interpreter
+        .facility_stats(barber_id)
+        .expect("facility must be seized");
+    assert_eq!(barber.entries(), 2);
+}